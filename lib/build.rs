@@ -0,0 +1,127 @@
+use std::{
+	collections::hash_map::DefaultHasher,
+	env, fs,
+	hash::Hasher,
+	io,
+	path::{Path, PathBuf},
+	process::Command,
+};
+
+/// Hashes paths and contents of all files under `dir`, in a stable order
+fn hash_dir(hasher: &mut DefaultHasher, root: &Path, dir: &Path) -> io::Result<()> {
+	let mut entries = fs::read_dir(dir)?
+		.map(|e| e.map(|e| e.path()))
+		.collect::<io::Result<Vec<_>>>()?;
+	entries.sort();
+	for path in entries {
+		if path.is_dir() {
+			hash_dir(hasher, root, &path)?;
+		} else {
+			hasher.write(path.strip_prefix(root).unwrap().to_string_lossy().as_bytes());
+			hasher.write(&fs::read(&path)?);
+		}
+	}
+	Ok(())
+}
+
+/// Nearest `Cargo.lock` above the target directory, which belongs to the server or the plugin
+/// being built, or above this crate when it's built alone
+fn find_lock() -> Option<PathBuf> {
+	let out_dir = PathBuf::from(env::var("OUT_DIR").ok()?);
+	let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").ok()?);
+	out_dir
+		.ancestors()
+		.chain(manifest_dir.ancestors())
+		.map(|dir| dir.join("Cargo.lock"))
+		.find(|path| path.is_file())
+}
+
+/// `name version` of direct dependencies of this crate in `lock`, sorted.
+/// Only direct ones, as transitive ones differ between the server and plugins more often than
+/// types they share.
+fn locked_dependencies(lock: &str) -> Vec<String> {
+	let packages: Vec<(String, String, Vec<String>)> = lock
+		.split("[[package]]")
+		.skip(1)
+		.map(|block| {
+			let value = |key: &str| {
+				block
+					.lines()
+					.find(|l| l.starts_with(key) && l[key.len()..].trim_start().starts_with('='))
+					.map(|l| l.splitn(2, '=').nth(1).unwrap().trim().trim_matches('"').to_owned())
+					.unwrap_or_default()
+			};
+			let dependencies = block
+				.lines()
+				.skip_while(|l| !l.starts_with("dependencies"))
+				.skip(1)
+				.take_while(|l| !l.starts_with(']'))
+				.map(|l| l.trim().trim_end_matches(',').trim_matches('"').to_owned())
+				.collect();
+			(value("name"), value("version"), dependencies)
+		})
+		.collect();
+	let name = env::var("CARGO_PKG_NAME").unwrap();
+	let mut dependencies: Vec<String> = packages
+		.iter()
+		.filter(|(n, _, _)| n == &name)
+		.flat_map(|(_, _, dependencies)| dependencies.iter())
+		.map(|dependency| {
+			let mut parts = dependency.split(' ');
+			let name = parts.next().unwrap_or_default();
+			match parts.next() {
+				Some(version) => format!("{} {}", name, version),
+				None => packages
+					.iter()
+					.find(|(n, _, _)| n == name)
+					.map_or(name.to_owned(), |(n, v, _)| format!("{} {}", n, v)),
+			}
+		})
+		.collect();
+	dependencies.sort();
+	dependencies.dedup();
+	dependencies
+}
+
+/// Plugins are loaded as Rust trait objects, whose layout is unspecified and may change with the
+/// compiler, the source of this crate, its features or its dependencies. The fingerprint covers
+/// these to refuse obvious mismatches, but passing the check doesn't make the layout stable.
+fn main() -> io::Result<()> {
+	let rustc = env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+	let rustc_version = Command::new(&rustc)
+		.arg("--version")
+		.output()
+		.ok()
+		.and_then(|o| String::from_utf8(o.stdout).ok())
+		.map(|s| s.trim().to_owned())
+		.unwrap_or_else(|| String::from("unknown"));
+	let target = env::var("TARGET").unwrap();
+
+	let mut hasher = DefaultHasher::new();
+	hasher.write(rustc_version.as_bytes());
+	hasher.write(target.as_bytes());
+	let src = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("src");
+	hash_dir(&mut hasher, &src, &src)?;
+
+	let mut features: Vec<String> = env::vars()
+		.map(|(key, _)| key)
+		.filter(|key| key.starts_with("CARGO_FEATURE_"))
+		.collect();
+	features.sort();
+	for feature in &features {
+		hasher.write(feature.as_bytes());
+	}
+	if let Some(lock) = find_lock() {
+		for dependency in locked_dependencies(&fs::read_to_string(&lock)?) {
+			hasher.write(dependency.as_bytes());
+		}
+		println!("cargo:rerun-if-changed={}", lock.display());
+	}
+
+	println!("cargo:rustc-env=SOHABLOG_RUSTC_VERSION={}", rustc_version);
+	println!("cargo:rustc-env=SOHABLOG_TARGET={}", target);
+	println!("cargo:rustc-env=SOHABLOG_ABI_FINGERPRINT={:016x}", hasher.finish());
+	println!("cargo:rerun-if-changed=src");
+	println!("cargo:rerun-if-env-changed=RUSTC");
+	Ok(())
+}
@@ -0,0 +1,869 @@
+//! C ABI between the server and native plugins.
+//!
+//! Trait objects are not passed between the server and a library, since the layout of their
+//! vtables is up to the compiler. `declare_plugin_metadata!` and `declare_plugin!` export the
+//! object with `#[repr(C)]` tables of `extern "C"` functions instead, which the server wraps in
+//! `ForeignMetadata`, `ForeignTheme` and `ForeignPlugin`. A panic is caught before it leaves the
+//! library and raised again by the server, so a panicking hook still disables the plugin.
+//!
+//! Values passed through the tables, like `TemplateContext`, `Request` and models, are Rust types
+//! of `sohablog_lib`, and themes call `TemplateContext::builtin_theme` of the server. Their layout
+//! is only the same if both sides are built alike, which `PluginDeclaration::check` makes sure of
+//! before anything in the library is called.
+
+use crate::{
+	http::{AdminPage, Request, Response, Route},
+	interfaces::models::{Author, Content},
+	plugin::*,
+	settings::{SettingField, Settings},
+	utils::{Page, StaticFile, TemplateContext},
+};
+#[cfg(feature = "main")]
+use std::mem::{ManuallyDrop, MaybeUninit};
+use std::{
+	io,
+	os::raw::c_void,
+	panic::{self, AssertUnwindSafe},
+	ptr, slice, str,
+};
+
+/// The exported object, only used by the library it comes from
+type Object = *const c_void;
+/// Thin pointer to `&mut dyn io::Write`
+type Writer = *mut c_void;
+
+/// `&str` borrowed from the object, or from the caller while a function runs
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RawStr {
+	ptr: *const u8,
+	len: usize,
+}
+impl RawStr {
+	fn new(s: &str) -> Self {
+		Self {
+			ptr: s.as_ptr(),
+			len: s.len(),
+		}
+	}
+
+	/// Unsafe because the string must live for `'a`
+	unsafe fn as_str<'a>(self) -> &'a str {
+		str::from_utf8_unchecked(slice::from_raw_parts(self.ptr, self.len))
+	}
+}
+
+unsafe fn this<'a, T>(object: Object) -> &'a T {
+	&*(object as *const T)
+}
+
+/// Writes the result of `f` to `out`, `false` if it panicked. Unwinding out of an `extern "C"`
+/// function is undefined behavior.
+unsafe fn guard<R, F: FnOnce() -> R>(out: *mut R, f: F) -> bool {
+	match panic::catch_unwind(AssertUnwindSafe(f)) {
+		Ok(value) => {
+			ptr::write(out, value);
+			true
+		}
+		Err(_) => false,
+	}
+}
+
+/// Calls a function of a table, and panics again if the library caught a panic
+#[cfg(feature = "main")]
+unsafe fn call<R, F: FnOnce(*mut R) -> bool>(f: F) -> R {
+	let mut out = MaybeUninit::uninit();
+	if !f(out.as_mut_ptr()) {
+		panic!("Panicked in the plugin library");
+	}
+	out.assume_init()
+}
+
+#[cfg(feature = "main")]
+fn writer(out: &mut &mut dyn io::Write) -> Writer {
+	out as *mut &mut dyn io::Write as Writer
+}
+
+unsafe fn write_to<'a>(out: Writer) -> &'a mut dyn io::Write {
+	&mut **(out as *mut &mut dyn io::Write)
+}
+
+#[repr(C)]
+pub struct MetadataVTable {
+	plugin_version: unsafe extern "C" fn(Object, *mut u32) -> bool,
+	name: unsafe extern "C" fn(Object, *mut RawStr) -> bool,
+	description: unsafe extern "C" fn(Object, *mut RawStr) -> bool,
+	version: unsafe extern "C" fn(Object, *mut RawStr) -> bool,
+	r#type: unsafe extern "C" fn(Object, *mut PluginType) -> bool,
+	settings_schema: unsafe extern "C" fn(Object, *mut Vec<SettingField>) -> bool,
+	/// Drops the object, a panic is ignored
+	drop: unsafe extern "C" fn(*mut c_void),
+}
+impl MetadataVTable {
+	fn of<T: PluginMetadata>() -> Self {
+		unsafe extern "C" fn plugin_version<T: PluginMetadata>(o: Object, out: *mut u32) -> bool {
+			guard(out, || this::<T>(o).plugin_version())
+		}
+		unsafe extern "C" fn name<T: PluginMetadata>(o: Object, out: *mut RawStr) -> bool {
+			guard(out, || RawStr::new(this::<T>(o).name()))
+		}
+		unsafe extern "C" fn description<T: PluginMetadata>(o: Object, out: *mut RawStr) -> bool {
+			guard(out, || RawStr::new(this::<T>(o).description()))
+		}
+		unsafe extern "C" fn version<T: PluginMetadata>(o: Object, out: *mut RawStr) -> bool {
+			guard(out, || RawStr::new(this::<T>(o).version()))
+		}
+		unsafe extern "C" fn r#type<T: PluginMetadata>(o: Object, out: *mut PluginType) -> bool {
+			guard(out, || this::<T>(o).r#type())
+		}
+		unsafe extern "C" fn settings_schema<T: PluginMetadata>(
+			o: Object,
+			out: *mut Vec<SettingField>,
+		) -> bool {
+			guard(out, || this::<T>(o).settings_schema())
+		}
+		unsafe extern "C" fn drop<T>(o: *mut c_void) {
+			let _ = panic::catch_unwind(AssertUnwindSafe(|| Box::from_raw(o as *mut T)));
+		}
+		Self {
+			plugin_version: plugin_version::<T>,
+			name: name::<T>,
+			description: description::<T>,
+			version: version::<T>,
+			r#type: r#type::<T>,
+			settings_schema: settings_schema::<T>,
+			drop: drop::<T>,
+		}
+	}
+}
+
+/// Exported by `_plugin_metadata`, and the start of a theme or plugin
+#[repr(C)]
+pub struct RawMetadata {
+	object: *mut c_void,
+	vtable: MetadataVTable,
+}
+impl RawMetadata {
+	fn new<T: PluginMetadata>(object: T) -> Self {
+		Self {
+			object: Box::into_raw(Box::new(object)) as *mut c_void,
+			vtable: MetadataVTable::of::<T>(),
+		}
+	}
+}
+
+/// Pages listing posts, with title or query
+type ListPage = for<'a> unsafe extern "C" fn(
+	Object,
+	Writer,
+	*const TemplateContext<'a>,
+	RawStr,
+	*const Page,
+	*mut Vec<Box<dyn Content>>,
+	*mut io::Result<()>,
+) -> bool;
+/// Pages showing a post, with title and author of the previous comment
+type ContentPage = for<'a> unsafe extern "C" fn(
+	Object,
+	Writer,
+	*const TemplateContext<'a>,
+	RawStr,
+	*mut Box<dyn Content>,
+	*mut Option<Box<dyn Author>>,
+	*mut io::Result<()>,
+) -> bool;
+
+#[repr(C)]
+pub struct ThemeVTable {
+	identity: unsafe extern "C" fn(Object, *mut RawStr) -> bool,
+	post_list: ListPage,
+	post_show: ContentPage,
+	static_file: unsafe extern "C" fn(Object, RawStr, *mut Option<Box<dyn StaticFile>>) -> bool,
+	page_show: ContentPage,
+	error_page: for<'a> unsafe extern "C" fn(
+		Object,
+		Writer,
+		*const TemplateContext<'a>,
+		u16,
+		RawStr,
+		*mut io::Result<()>,
+	) -> bool,
+	search_result: ListPage,
+	archive: for<'a> unsafe extern "C" fn(
+		Object,
+		Writer,
+		*const TemplateContext<'a>,
+		*const Archive,
+		*const Page,
+		*mut Vec<Box<dyn Content>>,
+		*mut io::Result<()>,
+	) -> bool,
+	password_form: for<'a> unsafe extern "C" fn(
+		Object,
+		Writer,
+		*const TemplateContext<'a>,
+		RawStr,
+		RawStr,
+		bool,
+		*mut io::Result<()>,
+	) -> bool,
+}
+impl ThemeVTable {
+	fn of<T: Theme>() -> Self {
+		unsafe extern "C" fn identity<T: Theme>(o: Object, out: *mut RawStr) -> bool {
+			guard(out, || RawStr::new(this::<T>(o).identity()))
+		}
+		unsafe extern "C" fn post_list<T: Theme>(
+			o: Object,
+			w: Writer,
+			ctx: *const TemplateContext,
+			title: RawStr,
+			page: *const Page,
+			posts: *mut Vec<Box<dyn Content>>,
+			out: *mut io::Result<()>,
+		) -> bool {
+			let posts = ptr::read(posts);
+			guard(out, || {
+				this::<T>(o).post_list(write_to(w), &*ctx, title.as_str(), *page, posts)
+			})
+		}
+		unsafe extern "C" fn post_show<T: Theme>(
+			o: Object,
+			w: Writer,
+			ctx: *const TemplateContext,
+			title: RawStr,
+			post: *mut Box<dyn Content>,
+			previous_author: *mut Option<Box<dyn Author>>,
+			out: *mut io::Result<()>,
+		) -> bool {
+			let (post, previous_author) = (ptr::read(post), ptr::read(previous_author));
+			guard(out, || {
+				this::<T>(o).post_show(write_to(w), &*ctx, title.as_str(), post, previous_author)
+			})
+		}
+		unsafe extern "C" fn static_file<T: Theme>(
+			o: Object,
+			name: RawStr,
+			out: *mut Option<Box<dyn StaticFile>>,
+		) -> bool {
+			guard(out, || this::<T>(o).static_file(name.as_str()))
+		}
+		unsafe extern "C" fn page_show<T: Theme>(
+			o: Object,
+			w: Writer,
+			ctx: *const TemplateContext,
+			title: RawStr,
+			page: *mut Box<dyn Content>,
+			previous_author: *mut Option<Box<dyn Author>>,
+			out: *mut io::Result<()>,
+		) -> bool {
+			let (page, previous_author) = (ptr::read(page), ptr::read(previous_author));
+			guard(out, || {
+				this::<T>(o).page_show(write_to(w), &*ctx, title.as_str(), page, previous_author)
+			})
+		}
+		unsafe extern "C" fn error_page<T: Theme>(
+			o: Object,
+			w: Writer,
+			ctx: *const TemplateContext,
+			status: u16,
+			message: RawStr,
+			out: *mut io::Result<()>,
+		) -> bool {
+			guard(out, || {
+				this::<T>(o).error_page(write_to(w), &*ctx, status, message.as_str())
+			})
+		}
+		unsafe extern "C" fn search_result<T: Theme>(
+			o: Object,
+			w: Writer,
+			ctx: *const TemplateContext,
+			query: RawStr,
+			page: *const Page,
+			posts: *mut Vec<Box<dyn Content>>,
+			out: *mut io::Result<()>,
+		) -> bool {
+			let posts = ptr::read(posts);
+			guard(out, || {
+				this::<T>(o).search_result(write_to(w), &*ctx, query.as_str(), *page, posts)
+			})
+		}
+		unsafe extern "C" fn archive<T: Theme>(
+			o: Object,
+			w: Writer,
+			ctx: *const TemplateContext,
+			archive: *const Archive,
+			page: *const Page,
+			posts: *mut Vec<Box<dyn Content>>,
+			out: *mut io::Result<()>,
+		) -> bool {
+			let posts = ptr::read(posts);
+			guard(out, || {
+				this::<T>(o).archive(write_to(w), &*ctx, &*archive, *page, posts)
+			})
+		}
+		unsafe extern "C" fn password_form<T: Theme>(
+			o: Object,
+			w: Writer,
+			ctx: *const TemplateContext,
+			title: RawStr,
+			action: RawStr,
+			wrong_password: bool,
+			out: *mut io::Result<()>,
+		) -> bool {
+			guard(out, || {
+				this::<T>(o).password_form(
+					write_to(w),
+					&*ctx,
+					title.as_str(),
+					action.as_str(),
+					wrong_password,
+				)
+			})
+		}
+		Self {
+			identity: identity::<T>,
+			post_list: post_list::<T>,
+			post_show: post_show::<T>,
+			static_file: static_file::<T>,
+			page_show: page_show::<T>,
+			error_page: error_page::<T>,
+			search_result: search_result::<T>,
+			archive: archive::<T>,
+			password_form: password_form::<T>,
+		}
+	}
+}
+
+/// Exported by `_plugin_create` of a theme
+#[repr(C)]
+pub struct RawTheme {
+	metadata: RawMetadata,
+	vtable: ThemeVTable,
+}
+
+#[repr(C)]
+pub struct PluginVTable {
+	priority: unsafe extern "C" fn(Object, *mut i32) -> bool,
+	init: for<'a> unsafe extern "C" fn(
+		Object,
+		*const PluginContext<'a>,
+		*mut Result<(), String>,
+	) -> bool,
+	shutdown: unsafe extern "C" fn(Object, *mut ()) -> bool,
+	settings_changed: unsafe extern "C" fn(Object, *const Settings, *mut ()) -> bool,
+	filter_content_before_save: unsafe extern "C" fn(Object, *mut ContentDraft, *mut ()) -> bool,
+	filter_content_before_render: unsafe extern "C" fn(Object, i32, *mut String, *mut ()) -> bool,
+	filter_comment:
+		unsafe extern "C" fn(Object, *mut CommentDraft, *mut Result<(), String>) -> bool,
+	on_publish: unsafe extern "C" fn(Object, i32, *const ContentDraft, *mut ()) -> bool,
+	on_comment: unsafe extern "C" fn(Object, i32, *const CommentDraft, *mut ()) -> bool,
+	inject: unsafe extern "C" fn(Object, *const InjectionPoint, *mut Option<String>) -> bool,
+	routes: unsafe extern "C" fn(Object, *mut Vec<Route>) -> bool,
+	handle: unsafe extern "C" fn(Object, RawStr, *const Request, *mut Response) -> bool,
+	admin_pages: unsafe extern "C" fn(Object, *mut Vec<AdminPage>) -> bool,
+	admin_page: unsafe extern "C" fn(Object, RawStr, *const Request, *mut Response) -> bool,
+}
+impl PluginVTable {
+	fn of<T: Plugin>() -> Self {
+		unsafe extern "C" fn priority<T: Plugin>(o: Object, out: *mut i32) -> bool {
+			guard(out, || this::<T>(o).priority())
+		}
+		unsafe extern "C" fn init<T: Plugin>(
+			o: Object,
+			ctx: *const PluginContext,
+			out: *mut Result<(), String>,
+		) -> bool {
+			guard(out, || this::<T>(o).init(&*ctx))
+		}
+		unsafe extern "C" fn shutdown<T: Plugin>(o: Object, out: *mut ()) -> bool {
+			guard(out, || this::<T>(o).shutdown())
+		}
+		unsafe extern "C" fn settings_changed<T: Plugin>(
+			o: Object,
+			settings: *const Settings,
+			out: *mut (),
+		) -> bool {
+			guard(out, || this::<T>(o).settings_changed(&*settings))
+		}
+		unsafe extern "C" fn filter_content_before_save<T: Plugin>(
+			o: Object,
+			content: *mut ContentDraft,
+			out: *mut (),
+		) -> bool {
+			guard(out, || {
+				this::<T>(o).filter_content_before_save(&mut *content)
+			})
+		}
+		unsafe extern "C" fn filter_content_before_render<T: Plugin>(
+			o: Object,
+			content_id: i32,
+			content: *mut String,
+			out: *mut (),
+		) -> bool {
+			guard(out, || {
+				this::<T>(o).filter_content_before_render(content_id, &mut *content)
+			})
+		}
+		unsafe extern "C" fn filter_comment<T: Plugin>(
+			o: Object,
+			comment: *mut CommentDraft,
+			out: *mut Result<(), String>,
+		) -> bool {
+			guard(out, || this::<T>(o).filter_comment(&mut *comment))
+		}
+		unsafe extern "C" fn on_publish<T: Plugin>(
+			o: Object,
+			id: i32,
+			content: *const ContentDraft,
+			out: *mut (),
+		) -> bool {
+			guard(out, || this::<T>(o).on_publish(id, &*content))
+		}
+		unsafe extern "C" fn on_comment<T: Plugin>(
+			o: Object,
+			id: i32,
+			comment: *const CommentDraft,
+			out: *mut (),
+		) -> bool {
+			guard(out, || this::<T>(o).on_comment(id, &*comment))
+		}
+		unsafe extern "C" fn inject<T: Plugin>(
+			o: Object,
+			point: *const InjectionPoint,
+			out: *mut Option<String>,
+		) -> bool {
+			guard(out, || this::<T>(o).inject(*point))
+		}
+		unsafe extern "C" fn routes<T: Plugin>(o: Object, out: *mut Vec<Route>) -> bool {
+			guard(out, || this::<T>(o).routes())
+		}
+		unsafe extern "C" fn handle<T: Plugin>(
+			o: Object,
+			route: RawStr,
+			req: *const Request,
+			out: *mut Response,
+		) -> bool {
+			guard(out, || this::<T>(o).handle(route.as_str(), &*req))
+		}
+		unsafe extern "C" fn admin_pages<T: Plugin>(o: Object, out: *mut Vec<AdminPage>) -> bool {
+			guard(out, || this::<T>(o).admin_pages())
+		}
+		unsafe extern "C" fn admin_page<T: Plugin>(
+			o: Object,
+			slug: RawStr,
+			req: *const Request,
+			out: *mut Response,
+		) -> bool {
+			guard(out, || this::<T>(o).admin_page(slug.as_str(), &*req))
+		}
+		Self {
+			priority: priority::<T>,
+			init: init::<T>,
+			shutdown: shutdown::<T>,
+			settings_changed: settings_changed::<T>,
+			filter_content_before_save: filter_content_before_save::<T>,
+			filter_content_before_render: filter_content_before_render::<T>,
+			filter_comment: filter_comment::<T>,
+			on_publish: on_publish::<T>,
+			on_comment: on_comment::<T>,
+			inject: inject::<T>,
+			routes: routes::<T>,
+			handle: handle::<T>,
+			admin_pages: admin_pages::<T>,
+			admin_page: admin_page::<T>,
+		}
+	}
+}
+
+/// Exported by `_plugin_create` of a plugin
+#[repr(C)]
+pub struct RawPlugin {
+	metadata: RawMetadata,
+	vtable: PluginVTable,
+}
+
+/// Implemented by `dyn Theme` and `dyn Plugin`, see `declare_plugin!`
+pub trait Export<T> {
+	type Raw;
+	fn export(object: T) -> Self::Raw;
+}
+impl<T: Theme> Export<T> for dyn Theme {
+	type Raw = RawTheme;
+	fn export(object: T) -> RawTheme {
+		RawTheme {
+			metadata: RawMetadata::new(object),
+			vtable: ThemeVTable::of::<T>(),
+		}
+	}
+}
+impl<T: Plugin> Export<T> for dyn Plugin {
+	type Raw = RawPlugin;
+	fn export(object: T) -> RawPlugin {
+		RawPlugin {
+			metadata: RawMetadata::new(object),
+			vtable: PluginVTable::of::<T>(),
+		}
+	}
+}
+
+/// Body of `_plugin_metadata`, `false` if `constructor` panicked
+pub unsafe fn export_metadata<T: PluginMetadata>(
+	out: *mut RawMetadata,
+	constructor: fn() -> T,
+) -> bool {
+	guard(out, || RawMetadata::new(constructor()))
+}
+
+/// Body of `_plugin_create`, `false` if `constructor` panicked
+pub unsafe fn export<E: Export<T> + ?Sized, T>(out: *mut E::Raw, constructor: fn() -> T) -> bool {
+	guard(out, || E::export(constructor()))
+}
+
+/// Calls `_plugin_metadata` or `_plugin_create` of a library, `None` if it panicked
+#[cfg(feature = "main")]
+pub unsafe fn create<R>(constructor: unsafe extern "C" fn(*mut R) -> bool) -> Option<R> {
+	let mut out = MaybeUninit::uninit();
+	match constructor(out.as_mut_ptr()) {
+		true => Some(out.assume_init()),
+		false => None,
+	}
+}
+
+/// Metadata exported by a library, which must stay loaded until this is dropped
+#[cfg(feature = "main")]
+pub struct ForeignMetadata {
+	raw: RawMetadata,
+}
+#[cfg(feature = "main")]
+unsafe impl Send for ForeignMetadata {}
+#[cfg(feature = "main")]
+unsafe impl Sync for ForeignMetadata {}
+#[cfg(feature = "main")]
+impl ForeignMetadata {
+	/// Unsafe because `raw` must come from `_plugin_metadata` of a checked library
+	pub unsafe fn from_raw(raw: RawMetadata) -> Self {
+		Self { raw: raw }
+	}
+
+	fn object(&self) -> Object {
+		self.raw.object
+	}
+}
+#[cfg(feature = "main")]
+impl Drop for ForeignMetadata {
+	fn drop(&mut self) {
+		unsafe { (self.raw.vtable.drop)(self.raw.object) }
+	}
+}
+#[cfg(feature = "main")]
+impl PluginMetadata for ForeignMetadata {
+	fn plugin_version(&self) -> u32 {
+		unsafe { call(|out| (self.raw.vtable.plugin_version)(self.object(), out)) }
+	}
+	fn name(&self) -> &str {
+		unsafe { call(|out| (self.raw.vtable.name)(self.object(), out)).as_str() }
+	}
+	fn description(&self) -> &str {
+		unsafe { call(|out| (self.raw.vtable.description)(self.object(), out)).as_str() }
+	}
+	fn version(&self) -> &str {
+		unsafe { call(|out| (self.raw.vtable.version)(self.object(), out)).as_str() }
+	}
+	fn r#type(&self) -> PluginType {
+		unsafe { call(|out| (self.raw.vtable.r#type)(self.object(), out)) }
+	}
+	fn settings_schema(&self) -> Vec<SettingField> {
+		unsafe { call(|out| (self.raw.vtable.settings_schema)(self.object(), out)) }
+	}
+}
+
+/// `PluginMetadata` of a theme or plugin is read from its `metadata`
+#[cfg(feature = "main")]
+macro_rules! delegate_metadata {
+	($type:ty) => {
+		impl PluginMetadata for $type {
+			fn plugin_version(&self) -> u32 {
+				self.metadata.plugin_version()
+			}
+			fn name(&self) -> &str {
+				self.metadata.name()
+			}
+			fn description(&self) -> &str {
+				self.metadata.description()
+			}
+			fn version(&self) -> &str {
+				self.metadata.version()
+			}
+			fn r#type(&self) -> PluginType {
+				self.metadata.r#type()
+			}
+			fn settings_schema(&self) -> Vec<SettingField> {
+				self.metadata.settings_schema()
+			}
+		}
+	};
+}
+
+/// Theme exported by a library, which must stay loaded until this is dropped
+#[cfg(feature = "main")]
+pub struct ForeignTheme {
+	metadata: ForeignMetadata,
+	vtable: ThemeVTable,
+}
+#[cfg(feature = "main")]
+impl ForeignTheme {
+	/// Unsafe because `raw` must come from `_plugin_create` of a checked library
+	pub unsafe fn from_raw(raw: RawTheme) -> Self {
+		Self {
+			metadata: ForeignMetadata::from_raw(raw.metadata),
+			vtable: raw.vtable,
+		}
+	}
+
+	fn object(&self) -> Object {
+		self.metadata.object()
+	}
+}
+#[cfg(feature = "main")]
+delegate_metadata!(ForeignTheme);
+#[cfg(feature = "main")]
+impl Theme for ForeignTheme {
+	fn identity(&self) -> &str {
+		unsafe { call(|out| (self.vtable.identity)(self.object(), out)).as_str() }
+	}
+	fn post_list(
+		&self,
+		mut out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		title: &str,
+		page: Page,
+		posts: Vec<Box<dyn Content>>,
+	) -> io::Result<()> {
+		let mut posts = ManuallyDrop::new(posts);
+		unsafe {
+			call(|result| {
+				(self.vtable.post_list)(
+					self.object(),
+					writer(&mut out),
+					ctx,
+					RawStr::new(title),
+					&page,
+					&mut *posts,
+					result,
+				)
+			})
+		}
+	}
+	fn post_show(
+		&self,
+		mut out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		title: &str,
+		post: Box<dyn Content>,
+		previous_author: Option<Box<dyn Author>>,
+	) -> io::Result<()> {
+		let mut post = ManuallyDrop::new(post);
+		let mut previous_author = ManuallyDrop::new(previous_author);
+		unsafe {
+			call(|result| {
+				(self.vtable.post_show)(
+					self.object(),
+					writer(&mut out),
+					ctx,
+					RawStr::new(title),
+					&mut *post,
+					&mut *previous_author,
+					result,
+				)
+			})
+		}
+	}
+	fn static_file(&self, name: &str) -> Option<Box<dyn StaticFile>> {
+		unsafe { call(|out| (self.vtable.static_file)(self.object(), RawStr::new(name), out)) }
+	}
+	fn page_show(
+		&self,
+		mut out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		title: &str,
+		page: Box<dyn Content>,
+		previous_author: Option<Box<dyn Author>>,
+	) -> io::Result<()> {
+		let mut page = ManuallyDrop::new(page);
+		let mut previous_author = ManuallyDrop::new(previous_author);
+		unsafe {
+			call(|result| {
+				(self.vtable.page_show)(
+					self.object(),
+					writer(&mut out),
+					ctx,
+					RawStr::new(title),
+					&mut *page,
+					&mut *previous_author,
+					result,
+				)
+			})
+		}
+	}
+	fn error_page(
+		&self,
+		mut out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		status: u16,
+		message: &str,
+	) -> io::Result<()> {
+		unsafe {
+			call(|result| {
+				(self.vtable.error_page)(
+					self.object(),
+					writer(&mut out),
+					ctx,
+					status,
+					RawStr::new(message),
+					result,
+				)
+			})
+		}
+	}
+	fn search_result(
+		&self,
+		mut out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		query: &str,
+		page: Page,
+		posts: Vec<Box<dyn Content>>,
+	) -> io::Result<()> {
+		let mut posts = ManuallyDrop::new(posts);
+		unsafe {
+			call(|result| {
+				(self.vtable.search_result)(
+					self.object(),
+					writer(&mut out),
+					ctx,
+					RawStr::new(query),
+					&page,
+					&mut *posts,
+					result,
+				)
+			})
+		}
+	}
+	fn archive(
+		&self,
+		mut out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		archive: &Archive,
+		page: Page,
+		posts: Vec<Box<dyn Content>>,
+	) -> io::Result<()> {
+		let mut posts = ManuallyDrop::new(posts);
+		unsafe {
+			call(|result| {
+				(self.vtable.archive)(
+					self.object(),
+					writer(&mut out),
+					ctx,
+					archive,
+					&page,
+					&mut *posts,
+					result,
+				)
+			})
+		}
+	}
+	fn password_form(
+		&self,
+		mut out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		title: &str,
+		action: &str,
+		wrong_password: bool,
+	) -> io::Result<()> {
+		unsafe {
+			call(|result| {
+				(self.vtable.password_form)(
+					self.object(),
+					writer(&mut out),
+					ctx,
+					RawStr::new(title),
+					RawStr::new(action),
+					wrong_password,
+					result,
+				)
+			})
+		}
+	}
+}
+
+/// Plugin exported by a library, which must stay loaded until this is dropped
+#[cfg(feature = "main")]
+pub struct ForeignPlugin {
+	metadata: ForeignMetadata,
+	vtable: PluginVTable,
+}
+#[cfg(feature = "main")]
+impl ForeignPlugin {
+	/// Unsafe because `raw` must come from `_plugin_create` of a checked library
+	pub unsafe fn from_raw(raw: RawPlugin) -> Self {
+		Self {
+			metadata: ForeignMetadata::from_raw(raw.metadata),
+			vtable: raw.vtable,
+		}
+	}
+
+	fn object(&self) -> Object {
+		self.metadata.object()
+	}
+}
+#[cfg(feature = "main")]
+delegate_metadata!(ForeignPlugin);
+#[cfg(feature = "main")]
+impl Plugin for ForeignPlugin {
+	fn priority(&self) -> i32 {
+		unsafe { call(|out| (self.vtable.priority)(self.object(), out)) }
+	}
+	fn init(&self, ctx: &PluginContext) -> Result<(), String> {
+		unsafe { call(|out| (self.vtable.init)(self.object(), ctx, out)) }
+	}
+	fn shutdown(&self) {
+		unsafe { call(|out| (self.vtable.shutdown)(self.object(), out)) }
+	}
+	fn settings_changed(&self, settings: &Settings) {
+		unsafe { call(|out| (self.vtable.settings_changed)(self.object(), settings, out)) }
+	}
+	fn filter_content_before_save(&self, content: &mut ContentDraft) {
+		unsafe { call(|out| (self.vtable.filter_content_before_save)(self.object(), content, out)) }
+	}
+	fn filter_content_before_render(&self, content_id: i32, content: &mut String) {
+		unsafe {
+			call(|out| {
+				(self.vtable.filter_content_before_render)(self.object(), content_id, content, out)
+			})
+		}
+	}
+	fn filter_comment(&self, comment: &mut CommentDraft) -> Result<(), String> {
+		unsafe { call(|out| (self.vtable.filter_comment)(self.object(), comment, out)) }
+	}
+	fn on_publish(&self, id: i32, content: &ContentDraft) {
+		unsafe { call(|out| (self.vtable.on_publish)(self.object(), id, content, out)) }
+	}
+	fn on_comment(&self, id: i32, comment: &CommentDraft) {
+		unsafe { call(|out| (self.vtable.on_comment)(self.object(), id, comment, out)) }
+	}
+	fn inject(&self, point: InjectionPoint) -> Option<String> {
+		unsafe { call(|out| (self.vtable.inject)(self.object(), &point, out)) }
+	}
+	fn routes(&self) -> Vec<Route> {
+		unsafe { call(|out| (self.vtable.routes)(self.object(), out)) }
+	}
+	fn handle(&self, route: &str, req: &Request) -> Response {
+		unsafe { call(|out| (self.vtable.handle)(self.object(), RawStr::new(route), req, out)) }
+	}
+	fn admin_pages(&self) -> Vec<AdminPage> {
+		unsafe { call(|out| (self.vtable.admin_pages)(self.object(), out)) }
+	}
+	fn admin_page(&self, slug: &str, req: &Request) -> Response {
+		unsafe { call(|out| (self.vtable.admin_page)(self.object(), RawStr::new(slug), req, out)) }
+	}
+}
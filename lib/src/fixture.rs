@@ -0,0 +1,849 @@
+//! In-memory implementations of `interfaces::models`, loaded from a JSON or TOML fixture, so
+//! themes can be rendered and snapshotted without a database. See `render_all`.
+//!
+//! A fixture lists `users`, `categories`, `posts` and `comments`, which refer to each other by
+//! `id`. Times are RFC 3339 strings. Posts with `page = true` are single pages.
+
+use crate::{
+	interfaces::models::*,
+	plugin::{Archive, PluginMetadata, PluginType, Theme, THEME_TRAIT_VERSION},
+	render::{RenderHelper, CONTENT_TRUNCATE_MARK},
+	settings::Settings,
+	types::{CommentStatus, ContentStatus, ContentType, UserStatus},
+	utils::{Page, SessionInfo, StaticFile, SystemConfig, TemplateContext, VisitorIP},
+};
+use chrono::{DateTime, Local, TimeZone};
+use ipnetwork::IpNetwork;
+use serde::{Deserialize, Deserializer};
+use serde_derive::*;
+use std::{
+	collections::HashMap,
+	fs,
+	io::{self, Write},
+	net::IpAddr,
+	path::{Path, PathBuf},
+	rc::Rc,
+};
+use uuid::Uuid;
+
+fn local_time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Local>, D::Error> {
+	let s = String::deserialize(deserializer)?;
+	DateTime::parse_from_rfc3339(&s)
+		.map(|t| t.with_timezone(&Local))
+		.map_err(serde::de::Error::custom)
+}
+
+fn default_true() -> bool {
+	true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserFixture {
+	pub id: i32,
+	pub username: String,
+	/// `username` is shown if empty
+	#[serde(default)]
+	pub name: String,
+	#[serde(default)]
+	pub email: String,
+	#[serde(default)]
+	pub website: Option<String>,
+	#[serde(default)]
+	pub avatar_url: Option<String>,
+	#[serde(default)]
+	pub permission: i32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CategoryFixture {
+	pub id: i32,
+	pub slug: String,
+	pub name: String,
+	#[serde(default)]
+	pub description: Option<String>,
+	#[serde(default)]
+	pub order: i32,
+	#[serde(default)]
+	pub parent_id: Option<i32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileFixture {
+	pub url: String,
+	#[serde(default)]
+	pub filename: String,
+	pub mime: String,
+	#[serde(default)]
+	pub width: Option<i32>,
+	#[serde(default)]
+	pub height: Option<i32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PostFixture {
+	pub id: i32,
+	pub user_id: i32,
+	#[serde(default)]
+	pub title: Option<String>,
+	#[serde(default)]
+	pub slug: Option<String>,
+	/// Markdown
+	pub content: String,
+	#[serde(deserialize_with = "local_time")]
+	pub time: DateTime<Local>,
+	/// Single page instead of an article
+	#[serde(default)]
+	pub page: bool,
+	#[serde(default = "default_true")]
+	pub allow_comment: bool,
+	#[serde(default)]
+	pub category_id: Option<i32>,
+	#[serde(default)]
+	pub tags: Vec<String>,
+	#[serde(default)]
+	pub featured_image: Option<FileFixture>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthorFixture {
+	pub name: String,
+	#[serde(default)]
+	pub mail: Option<String>,
+	#[serde(default)]
+	pub link: Option<String>,
+	/// `default_url` of `Author::avatar_url` is used if absent
+	#[serde(default)]
+	pub avatar_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommentFixture {
+	pub id: i32,
+	pub post_id: i32,
+	pub author: AuthorFixture,
+	pub text: String,
+	#[serde(deserialize_with = "local_time")]
+	pub time: DateTime<Local>,
+	#[serde(default)]
+	pub reply_to: Option<i32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Fixture {
+	#[serde(default)]
+	pub users: Vec<UserFixture>,
+	#[serde(default)]
+	pub categories: Vec<CategoryFixture>,
+	#[serde(default)]
+	pub posts: Vec<PostFixture>,
+	#[serde(default)]
+	pub comments: Vec<CommentFixture>,
+	/// Id of the logged in user, a guest if absent
+	#[serde(default)]
+	pub viewer: Option<i32>,
+	/// Query of the search page, nothing is searched if empty
+	#[serde(default)]
+	pub search: String,
+	/// Filled in comment forms, as if it was remembered from a previous comment
+	#[serde(default)]
+	pub previous_author: Option<AuthorFixture>,
+	/// Values of the theme's settings, defaults are used for missing ones
+	#[serde(default)]
+	pub theme_settings: HashMap<String, String>,
+}
+impl Fixture {
+	pub fn from_json(s: &str) -> Result<Self, String> {
+		let fixture: Self = serde_json::from_str(s).map_err(|e| e.to_string())?;
+		fixture.check()?;
+		Ok(fixture)
+	}
+
+	pub fn from_toml(s: &str) -> Result<Self, String> {
+		let fixture: Self = toml::from_str(s).map_err(|e| e.to_string())?;
+		fixture.check()?;
+		Ok(fixture)
+	}
+
+	/// Format is chosen by extension, `.json` or `.toml`
+	pub fn load(path: &Path) -> Result<Self, String> {
+		let s = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+		match path.extension().and_then(|s| s.to_str()) {
+			Some("json") => Self::from_json(&s),
+			Some("toml") => Self::from_toml(&s),
+			_ => Err(String::from("Fixture should be a `.json` or `.toml` file")),
+		}
+	}
+
+	/// Every `id` referred to must exist
+	fn check(&self) -> Result<(), String> {
+		let missing = |what: &str, id: i32| Err(format!("{} {} doesn't exist", what, id));
+		if let Some(id) = self.viewer.filter(|&id| self.user(id).is_none()) {
+			return missing("User", id);
+		}
+		for c in &self.categories {
+			if let Some(id) = c.parent_id.filter(|&id| self.category(id).is_none()) {
+				return missing("Category", id);
+			}
+		}
+		for p in &self.posts {
+			if self.user(p.user_id).is_none() {
+				return missing("User", p.user_id);
+			}
+			if let Some(id) = p.category_id.filter(|&id| self.category(id).is_none()) {
+				return missing("Category", id);
+			}
+		}
+		for c in &self.comments {
+			if self.post(c.post_id).is_none() {
+				return missing("Post", c.post_id);
+			}
+			if let Some(id) = c.reply_to.filter(|&id| self.comment(id).is_none()) {
+				return missing("Comment", id);
+			}
+		}
+		Ok(())
+	}
+
+	fn user(&self, id: i32) -> Option<usize> {
+		self.users.iter().position(|u| u.id == id)
+	}
+
+	fn category(&self, id: i32) -> Option<usize> {
+		self.categories.iter().position(|c| c.id == id)
+	}
+
+	fn post(&self, id: i32) -> Option<usize> {
+		self.posts.iter().position(|p| p.id == id)
+	}
+
+	fn comment(&self, id: i32) -> Option<usize> {
+		self.comments.iter().position(|c| c.id == id)
+	}
+}
+
+/// Times which are not in fixtures, so output doesn't change between runs
+fn epoch() -> DateTime<Local> {
+	Local.timestamp(0, 0)
+}
+
+/// `User` at an index of `Fixture::users`
+pub struct MemoryUser(Rc<Fixture>, usize);
+impl MemoryUser {
+	fn data(&self) -> &UserFixture {
+		&self.0.users[self.1]
+	}
+}
+impl User for MemoryUser {
+	fn id(&self) -> i32 {
+		self.data().id
+	}
+	fn username(&self) -> &String {
+		&self.data().username
+	}
+	fn name(&self) -> &String {
+		match self.data().name.is_empty() {
+			true => &self.data().username,
+			false => &self.data().name,
+		}
+	}
+	fn email(&self) -> &String {
+		&self.data().email
+	}
+	fn website(&self) -> Option<&String> {
+		self.data().website.as_ref()
+	}
+	fn avatar_url(&self) -> Option<&String> {
+		self.data().avatar_url.as_ref()
+	}
+	fn permission(&self) -> i32 {
+		self.data().permission
+	}
+	fn created_at(&self) -> DateTime<Local> {
+		epoch()
+	}
+	fn modified_at(&self) -> DateTime<Local> {
+		epoch()
+	}
+	fn last_login_time(&self) -> DateTime<Local> {
+		epoch()
+	}
+	fn status(&self) -> UserStatus {
+		UserStatus::Normal
+	}
+}
+
+pub struct MemoryTag(String);
+impl Tag for MemoryTag {
+	fn name(&self) -> &String {
+		&self.0
+	}
+}
+
+/// `Category` at an index of `Fixture::categories`
+pub struct MemoryCategory(Rc<Fixture>, usize);
+impl MemoryCategory {
+	fn data(&self) -> &CategoryFixture {
+		&self.0.categories[self.1]
+	}
+}
+impl Category for MemoryCategory {
+	fn id(&self) -> i32 {
+		self.data().id
+	}
+	fn slug(&self) -> &String {
+		&self.data().slug
+	}
+	fn name(&self) -> &String {
+		&self.data().name
+	}
+	fn description(&self) -> Option<&String> {
+		self.data().description.as_ref()
+	}
+	fn order(&self) -> i32 {
+		self.data().order
+	}
+	fn parent_id(&self) -> Option<i32> {
+		self.data().parent_id
+	}
+	fn parent(&self) -> Option<Box<dyn Category>> {
+		let index = self.0.category(self.data().parent_id?)?;
+		Some(Box::new(MemoryCategory(self.0.clone(), index)))
+	}
+}
+
+/// Featured image of a post, it has no resized copies
+pub struct MemoryFile(FileFixture);
+impl File for MemoryFile {
+	fn id(&self) -> i32 {
+		0
+	}
+	fn filename(&self) -> &String {
+		&self.0.filename
+	}
+	fn mime(&self) -> &String {
+		&self.0.mime
+	}
+	fn url(&self) -> String {
+		self.0.url.to_owned()
+	}
+	fn width(&self) -> Option<i32> {
+		self.0.width
+	}
+	fn height(&self) -> Option<i32> {
+		self.0.height
+	}
+	fn derivatives(&self) -> Vec<ImageVariant> {
+		Vec::new()
+	}
+}
+
+/// `Content` at an index of `Fixture::posts`, links are the same as the server's
+pub struct MemoryContent(Rc<Fixture>, usize);
+impl MemoryContent {
+	fn data(&self) -> &PostFixture {
+		&self.0.posts[self.1]
+	}
+
+	/// Articles ordered by time, newest first
+	fn articles(fixture: &Rc<Fixture>) -> Vec<Box<dyn Content>> {
+		let mut indexes: Vec<usize> = (0..fixture.posts.len())
+			.filter(|&i| !fixture.posts[i].page)
+			.collect();
+		indexes.sort_by_key(|&i| (fixture.posts[i].time, fixture.posts[i].id));
+		indexes
+			.into_iter()
+			.rev()
+			.map(|i| Box::new(MemoryContent(fixture.clone(), i)) as Box<dyn Content>)
+			.collect()
+	}
+}
+impl Content for MemoryContent {
+	fn id(&self) -> i32 {
+		self.data().id
+	}
+	fn user(&self) -> Box<dyn User> {
+		let index = self.0.user(self.data().user_id).unwrap(); // checked when loaded
+		Box::new(MemoryUser(self.0.clone(), index))
+	}
+	fn created_at(&self) -> DateTime<Local> {
+		self.data().time
+	}
+	fn modified_at(&self) -> DateTime<Local> {
+		self.data().time
+	}
+	fn time(&self) -> DateTime<Local> {
+		self.data().time
+	}
+	fn title(&self) -> Option<&String> {
+		self.data().title.as_ref()
+	}
+	fn slug(&self) -> Option<&String> {
+		self.data().slug.as_ref()
+	}
+	fn content(&self) -> &String {
+		&self.data().content
+	}
+	fn draft_content(&self) -> Option<&String> {
+		None
+	}
+	fn order_level(&self) -> i32 {
+		0
+	}
+	fn r#type(&self) -> ContentType {
+		match self.data().page {
+			true => ContentType::SinglePage,
+			false => ContentType::Article,
+		}
+	}
+	fn status(&self) -> ContentStatus {
+		ContentStatus::Normal
+	}
+	fn allow_comment(&self) -> bool {
+		self.data().allow_comment
+	}
+	fn category_id(&self) -> Option<i32> {
+		self.data().category_id
+	}
+	fn is_protected(&self) -> bool {
+		false
+	}
+	fn category(&self) -> Option<Box<dyn Category>> {
+		let index = self.0.category(self.data().category_id?)?;
+		Some(Box::new(MemoryCategory(self.0.clone(), index)))
+	}
+	fn tags(&self) -> Vec<Box<dyn Tag>> {
+		self.data()
+			.tags
+			.iter()
+			.map(|t| Box::new(MemoryTag(t.to_owned())) as Box<dyn Tag>)
+			.collect()
+	}
+
+	fn link(&self) -> String {
+		let path = self
+			.data()
+			.slug
+			.to_owned()
+			.unwrap_or(self.data().id.to_string());
+		match self.data().page {
+			true => format!("/{}", path),
+			false => format!("/post/{}.html", path),
+		}
+	}
+	fn get_tags_name(&self) -> Vec<String> {
+		self.data().tags.to_owned()
+	}
+	/// Previous one is older
+	fn get_neighbor_post(&self, prev: bool) -> Option<Box<dyn Content>> {
+		let articles = Self::articles(&self.0);
+		let position = articles.iter().position(|p| p.id() == self.id())?;
+		match prev {
+			true => articles.into_iter().nth(position + 1),
+			false if position > 0 => articles.into_iter().nth(position - 1),
+			false => None,
+		}
+	}
+	fn get_comment_url(&self) -> String {
+		format!("/comment/content/{}", self.data().id)
+	}
+	fn get_parent_comments(&self) -> Vec<Box<dyn Comment>> {
+		let id = self.data().id;
+		MemoryComment::find(&self.0, |c| c.post_id == id && c.reply_to.is_none())
+	}
+	fn featured_image(&self) -> Option<Box<dyn File>> {
+		let image = self.data().featured_image.to_owned()?;
+		Some(Box::new(MemoryFile(image)))
+	}
+}
+
+pub struct MemoryAuthor(AuthorFixture);
+impl Author for MemoryAuthor {
+	fn name(&self) -> &String {
+		&self.0.name
+	}
+	fn mail(&self) -> Option<&String> {
+		self.0.mail.as_ref()
+	}
+	fn link(&self) -> Option<&String> {
+		self.0.link.as_ref()
+	}
+	fn avatar_url(&self, default_url: &str) -> String {
+		self.0
+			.avatar_url
+			.to_owned()
+			.unwrap_or(String::from(default_url))
+	}
+}
+
+/// `Comment` at an index of `Fixture::comments`
+pub struct MemoryComment(Rc<Fixture>, usize);
+impl MemoryComment {
+	fn data(&self) -> &CommentFixture {
+		&self.0.comments[self.1]
+	}
+
+	/// Comments matching `filter`, oldest first
+	fn find<F: Fn(&CommentFixture) -> bool>(fixture: &Rc<Fixture>, filter: F) -> Vec<Box<dyn Comment>> {
+		let mut indexes: Vec<usize> = (0..fixture.comments.len())
+			.filter(|&i| filter(&fixture.comments[i]))
+			.collect();
+		indexes.sort_by_key(|&i| (fixture.comments[i].time, fixture.comments[i].id));
+		indexes
+			.into_iter()
+			.map(|i| Box::new(MemoryComment(fixture.clone(), i)) as Box<dyn Comment>)
+			.collect()
+	}
+}
+impl Comment for MemoryComment {
+	fn id(&self) -> i32 {
+		self.data().id
+	}
+	fn author(&self) -> Box<dyn Author> {
+		Box::new(MemoryAuthor(self.data().author.to_owned()))
+	}
+	fn ip(&self) -> Option<&IpNetwork> {
+		None
+	}
+	fn user_agent(&self) -> Option<&String> {
+		None
+	}
+	fn text(&self) -> &String {
+		&self.data().text
+	}
+	fn time(&self) -> DateTime<Local> {
+		self.data().time
+	}
+	fn status(&self) -> CommentStatus {
+		CommentStatus::Normal
+	}
+	fn reply_to(&self) -> Option<i32> {
+		self.data().reply_to
+	}
+	fn parent(&self) -> Option<Box<dyn Comment>> {
+		let index = self.0.comment(self.data().reply_to?)?;
+		Some(Box::new(MemoryComment(self.0.clone(), index)))
+	}
+	fn content(&self) -> Box<dyn Content> {
+		let index = self.0.post(self.data().post_id).unwrap(); // checked when loaded
+		Box::new(MemoryContent(self.0.clone(), index))
+	}
+
+	fn children(&self) -> Vec<Box<dyn Comment>> {
+		let id = self.data().id;
+		MemoryComment::find(&self.0, |c| c.reply_to == Some(id))
+	}
+}
+
+/// Escapes text instead of rendering Markdown, for rendering fixtures outside the server
+pub struct PlainRenderHelper;
+impl PlainRenderHelper {
+	fn escape(s: &str) -> String {
+		s.replace('&', "&amp;")
+			.replace('<', "&lt;")
+			.replace('>', "&gt;")
+			.replace('"', "&quot;")
+	}
+}
+impl RenderHelper for PlainRenderHelper {
+	/// Paragraphs are split by blank lines
+	fn markdown_to_html(&self, s: &str) -> String {
+		s.replace("\r\n", "\n")
+			.split("\n\n")
+			.filter(|p| !p.trim().is_empty())
+			.map(|p| format!("<p>{}</p>", Self::escape(p.trim())))
+			.collect::<Vec<String>>()
+			.join("\n")
+	}
+	fn nl2br(&self, s: &str) -> String {
+		s.replace("\r\n", "\n").replace("\r", "\n").replace("\n", "<br />")
+	}
+	fn date_format(&self, time: &chrono::NaiveDateTime, fmt: &str) -> String {
+		time.format(fmt).to_string()
+	}
+	fn truncate(&self, s: &str, len: usize) -> String {
+		s.chars().take(len).collect()
+	}
+	fn truncate_content(&self, s: &str, len: usize, truncate_mark: bool) -> String {
+		match s.find(CONTENT_TRUNCATE_MARK).filter(|_| truncate_mark) {
+			Some(end) => self.markdown_to_html(&s[..end]),
+			None => Self::escape(&self.truncate(s, len)),
+		}
+	}
+}
+
+/// Writes a comment for every page, as the built-in theme when rendering outside the server.
+/// Themes which don't implement a page get this comment instead.
+pub struct Unimplemented;
+impl Unimplemented {
+	fn write(out: &mut dyn Write, page: &str) -> io::Result<()> {
+		write!(out, "<!-- `{}` is not implemented by the theme -->", page)
+	}
+}
+impl PluginMetadata for Unimplemented {
+	fn plugin_version(&self) -> u32 {
+		THEME_TRAIT_VERSION
+	}
+	fn name(&self) -> &'static str {
+		"unimplemented"
+	}
+	fn description(&self) -> &'static str {
+		"Placeholder for pages a theme doesn't implement"
+	}
+	fn version(&self) -> &'static str {
+		env!("CARGO_PKG_VERSION")
+	}
+	fn r#type(&self) -> PluginType {
+		PluginType::Theme
+	}
+}
+impl Theme for Unimplemented {
+	fn identity(&self) -> &'static str {
+		"unimplemented"
+	}
+	fn post_list(
+		&self,
+		out: &mut dyn Write,
+		_ctx: &TemplateContext,
+		_title: &str,
+		_page: Page,
+		_posts: Vec<Box<dyn Content>>,
+	) -> io::Result<()> {
+		Self::write(out, "post_list")
+	}
+	fn post_show(
+		&self,
+		out: &mut dyn Write,
+		_ctx: &TemplateContext,
+		_title: &str,
+		_post: Box<dyn Content>,
+		_previous_author: Option<Box<dyn Author>>,
+	) -> io::Result<()> {
+		Self::write(out, "post_show")
+	}
+	fn static_file(&self, _name: &str) -> Option<Box<dyn StaticFile>> {
+		None
+	}
+	fn page_show(
+		&self,
+		out: &mut dyn Write,
+		_ctx: &TemplateContext,
+		_title: &str,
+		_page: Box<dyn Content>,
+		_previous_author: Option<Box<dyn Author>>,
+	) -> io::Result<()> {
+		Self::write(out, "page_show")
+	}
+	fn error_page(
+		&self,
+		out: &mut dyn Write,
+		_ctx: &TemplateContext,
+		_status: u16,
+		_message: &str,
+	) -> io::Result<()> {
+		Self::write(out, "error_page")
+	}
+	fn search_result(
+		&self,
+		out: &mut dyn Write,
+		_ctx: &TemplateContext,
+		_query: &str,
+		_page: Page,
+		_posts: Vec<Box<dyn Content>>,
+	) -> io::Result<()> {
+		Self::write(out, "search_result")
+	}
+	fn archive(
+		&self,
+		out: &mut dyn Write,
+		_ctx: &TemplateContext,
+		_archive: &Archive,
+		_page: Page,
+		_posts: Vec<Box<dyn Content>>,
+	) -> io::Result<()> {
+		Self::write(out, "archive")
+	}
+	fn password_form(
+		&self,
+		out: &mut dyn Write,
+		_ctx: &TemplateContext,
+		_title: &str,
+		_action: &str,
+		_wrong_password: bool,
+	) -> io::Result<()> {
+		Self::write(out, "password_form")
+	}
+}
+
+/// Part of a file name, other characters are replaced by `_`
+fn file_name(s: &str) -> String {
+	s.chars()
+		.map(|c| match c.is_alphanumeric() || c == '-' {
+			true => c,
+			false => '_',
+		})
+		.collect()
+}
+
+/// Renders every page of `theme` with `fixture` to HTML files in `out_dir`, and returns their
+/// paths. `None` renders `builtin`, which also renders pages the theme doesn't implement.
+/// Everything is on one page, and output is the same on every run.
+pub fn render_all(
+	theme: Option<&dyn Theme>,
+	builtin: &dyn Theme,
+	fixture: Fixture,
+	render_helper: Box<dyn RenderHelper>,
+	out_dir: &Path,
+) -> io::Result<Vec<PathBuf>> {
+	let current = theme.unwrap_or(builtin);
+	let fixture = Rc::new(fixture);
+	let system_config = SystemConfig {
+		plugin_dir: String::from("plugin/"),
+		upload_dir: String::from("upload/"),
+		upload_route: String::from("/static/upload"),
+		session_name: String::from("SOHABLOG_SESSION"),
+		robots_txt_path: String::from("robots.txt"),
+		real_ip_header: None,
+		csrf_cookie_name: None,
+		csrf_field_name: String::from("_token"),
+		is_prod: false,
+		theme_name: String::from(current.identity()),
+		plugin_hot_reload: false,
+		site_url: None,
+	};
+	let session_info = SessionInfo {
+		user: None,
+		csrf_token: Uuid::nil().into(),
+		preview_theme: None,
+	};
+	let ip = VisitorIP(IpAddr::from([127, 0, 0, 1]));
+	let ctx = TemplateContext {
+		ip: &ip,
+		user: fixture
+			.viewer
+			.and_then(|id| fixture.user(id))
+			.map(|index| Box::new(MemoryUser(fixture.clone(), index)) as Box<dyn User>),
+		system_config: &system_config,
+		user_agent: None,
+		session_info: &session_info,
+		render_helper: render_helper,
+		theme: theme,
+		builtin_theme: builtin,
+		fingerprints: None,
+		theme_settings: Settings::new(&current.settings_schema(), &fixture.theme_settings),
+		plugin_head: String::new(),
+		plugin_footer: String::new(),
+		plugin_admin_pages: Vec::new(),
+	};
+
+	fs::create_dir_all(out_dir)?;
+	let mut written = Vec::new();
+	let mut save = |name: String, html: Vec<u8>| -> io::Result<()> {
+		let path = out_dir.join(name);
+		fs::write(&path, html)?;
+		written.push(path);
+		Ok(())
+	};
+	let page = Page::new(1, 1);
+	let articles = || MemoryContent::articles(&fixture);
+	let untitled = String::from("Untitled");
+
+	let mut out = Vec::new();
+	current.post_list(&mut out, &ctx, "Index", page, articles())?;
+	save(String::from("post_list.html"), out)?;
+
+	for (index, post) in fixture.posts.iter().enumerate() {
+		let content = Box::new(MemoryContent(fixture.clone(), index));
+		let title = post.title.as_ref().unwrap_or(&untitled);
+		let previous_author = fixture
+			.previous_author
+			.to_owned()
+			.map(|a| Box::new(MemoryAuthor(a)) as Box<dyn Author>);
+		let mut out = Vec::new();
+		match post.page {
+			true => {
+				current.page_show(&mut out, &ctx, title, content, previous_author)?;
+				save(format!("page_show-{}.html", post.id), out)?;
+			}
+			false => {
+				current.post_show(&mut out, &ctx, title, content, previous_author)?;
+				save(format!("post_show-{}.html", post.id), out)?;
+			}
+		}
+	}
+
+	for &(status, message) in &[(403, "Forbidden"), (404, "Not Found"), (500, "Internal Server Error")] {
+		let mut out = Vec::new();
+		current.error_page(&mut out, &ctx, status, message)?;
+		save(format!("error_page-{}.html", status), out)?;
+	}
+
+	let query = fixture.search.to_lowercase();
+	let found = match query.is_empty() {
+		true => Vec::new(),
+		false => articles()
+			.into_iter()
+			.filter(|p| {
+				p.title().map_or(false, |t| t.to_lowercase().contains(&query))
+					|| p.content().to_lowercase().contains(&query)
+			})
+			.collect(),
+	};
+	let mut out = Vec::new();
+	current.search_result(&mut out, &ctx, &fixture.search, page, found)?;
+	save(String::from("search_result.html"), out)?;
+
+	let mut archives: Vec<(String, Archive, Vec<Box<dyn Content>>)> = Vec::new();
+	let mut months: Vec<(i32, u32)> = articles()
+		.iter()
+		.map(|p| {
+			use chrono::Datelike;
+			(p.time().year(), p.time().month())
+		})
+		.collect();
+	months.dedup();
+	for (year, month) in months {
+		let posts = articles()
+			.into_iter()
+			.filter(|p| {
+				use chrono::Datelike;
+				(p.time().year(), p.time().month()) == (year, month)
+			})
+			.collect();
+		let name = format!("month-{}-{:02}", year, month);
+		archives.push((name, Archive::Month { year: year, month: month }, posts));
+	}
+	for (index, category) in fixture.categories.iter().enumerate() {
+		let posts = articles()
+			.into_iter()
+			.filter(|p| p.category_id() == Some(category.id))
+			.collect();
+		let archive = Archive::Category(Box::new(MemoryCategory(fixture.clone(), index)));
+		archives.push((format!("category-{}", file_name(&category.slug)), archive, posts));
+	}
+	let mut tags: Vec<&String> = fixture.posts.iter().flat_map(|p| p.tags.iter()).collect();
+	tags.sort();
+	tags.dedup();
+	for tag in tags {
+		let posts = articles()
+			.into_iter()
+			.filter(|p| p.get_tags_name().contains(tag))
+			.collect();
+		archives.push((format!("tag-{}", file_name(tag)), Archive::Tag(tag.to_owned()), posts));
+	}
+	for (name, archive, posts) in archives {
+		let mut out = Vec::new();
+		current.archive(&mut out, &ctx, &archive, page, posts)?;
+		save(format!("archive-{}.html", name), out)?;
+	}
+
+	for &wrong_password in &[false, true] {
+		let mut out = Vec::new();
+		current.password_form(&mut out, &ctx, "Protected", "/content/1/password", wrong_password)?;
+		let name = match wrong_password {
+			true => "password_form-wrong.html",
+			false => "password_form.html",
+		};
+		save(String::from(name), out)?;
+	}
+	Ok(written)
+}
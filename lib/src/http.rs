@@ -0,0 +1,261 @@
+//! Requests and responses of plugin routes, so plugins don't depend on the web framework.
+
+#[cfg(feature = "main")]
+use serde_derive::*;
+use std::collections::HashMap;
+
+/// Request headers with credentials of the visitor, which plugins never see
+pub const PRIVATE_HEADERS: &[&str] = &["cookie", "authorization", "proxy-authorization"];
+
+/// Response headers a plugin may set, others are dropped so it can't set cookies
+pub const ALLOWED_RESPONSE_HEADERS: &[&str] = &[
+	"cache-control",
+	"content-disposition",
+	"content-language",
+	"content-type",
+	"etag",
+	"last-modified",
+	"location",
+];
+
+/// Given to plugins instead of the CSRF token, and replaced with the token in their
+/// output, so forms still work without the plugin knowing the token
+pub const CSRF_TOKEN_PLACEHOLDER: &str = "{{sohablog_csrf_token}}";
+
+#[cfg_attr(feature = "main", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "main", serde(rename_all = "lowercase"))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Method {
+	Get,
+	Post,
+	Put,
+	Delete,
+	Patch,
+}
+
+/// A route declared by a plugin, mounted under `/plugin/<plugin name>`
+#[cfg_attr(feature = "main", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct Route {
+	pub method: Method,
+	/// Like `/entry/<id>`, `<name>` matches one segment, and `<name..>` at the end matches the rest
+	pub path: String,
+	/// Passed to `Plugin::handle` to tell which route matched
+	pub name: String,
+}
+impl Route {
+	pub fn new(method: Method, path: &str, name: &str) -> Self {
+		Self {
+			method: method,
+			path: path.to_owned(),
+			name: name.to_owned(),
+		}
+	}
+
+	/// Values of `<name>` segments if `path` matches
+	pub fn matches(&self, method: Method, path: &str) -> Option<HashMap<String, String>> {
+		if method != self.method {
+			return None;
+		}
+		let mut params = HashMap::new();
+		let mut segments = path.split('/').filter(|s| !s.is_empty());
+		for pattern in self.path.split('/').filter(|s| !s.is_empty()) {
+			if pattern.starts_with('<') && pattern.ends_with("..>") {
+				let rest: Vec<String> = segments.by_ref().map(percent_decode).collect();
+				params.insert(pattern[1..pattern.len() - 3].to_owned(), rest.join("/"));
+				return Some(params);
+			}
+			let segment = segments.next()?;
+			if pattern.starts_with('<') && pattern.ends_with('>') {
+				params.insert(
+					pattern[1..pattern.len() - 1].to_owned(),
+					percent_decode(segment),
+				);
+			} else if pattern != segment {
+				return None;
+			}
+		}
+		match segments.next() {
+			Some(_) => None,
+			None => Some(params),
+		}
+	}
+}
+
+/// An admin page of a plugin at `/admin/plugin/<plugin name>/<slug>`, shown in admin navigation
+#[cfg_attr(feature = "main", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct AdminPage {
+	pub slug: String,
+	pub title: String,
+	/// Permission bits a user needs to see the page, see `types::PERM_*`
+	pub permission: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+	pub method: Method,
+	/// Path under the prefix of plugin, starts with `/`
+	pub path: String,
+	/// Values of `<name>` segments in the matched route
+	pub params: HashMap<String, String>,
+	/// Raw query string without `?`
+	pub query: Option<String>,
+	/// Names are lowercase
+	pub headers: Vec<(String, String)>,
+	pub body: Vec<u8>,
+	pub ip: String,
+	/// `None` for guests
+	pub user_id: Option<i32>,
+	/// Forms posting to plugin routes must carry the token in this field, or they are rejected
+	pub csrf_field_name: String,
+	/// `CSRF_TOKEN_PLACEHOLDER`, which is replaced with the token in the response
+	pub csrf_token: String,
+}
+impl Request {
+	pub fn param(&self, name: &str) -> Option<&str> {
+		self.params.get(name).map(|s| s.as_str())
+	}
+
+	pub fn header(&self, name: &str) -> Option<&str> {
+		let name = name.to_lowercase();
+		self.headers
+			.iter()
+			.find(|(n, _)| n == &name)
+			.map(|(_, v)| v.as_str())
+	}
+
+	/// Decoded value of `name` in query string
+	pub fn query_value(&self, name: &str) -> Option<String> {
+		find_form_value(self.query.as_ref()?, name)
+	}
+
+	/// Decoded value of `name` in a `application/x-www-form-urlencoded` body
+	pub fn form_value(&self, name: &str) -> Option<String> {
+		find_form_value(std::str::from_utf8(&self.body).ok()?, name)
+	}
+
+	/// Copy without `PRIVATE_HEADERS`, and with `CSRF_TOKEN_PLACEHOLDER` as the token
+	pub fn without_credentials(&self) -> Self {
+		Self {
+			headers: self
+				.headers
+				.iter()
+				.filter(|(name, _)| !PRIVATE_HEADERS.contains(&name.to_lowercase().as_str()))
+				.cloned()
+				.collect(),
+			csrf_token: String::from(CSRF_TOKEN_PLACEHOLDER),
+			..self.clone()
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+	pub status: u16,
+	pub headers: Vec<(String, String)>,
+	pub body: Vec<u8>,
+}
+impl Response {
+	pub fn new(status: u16) -> Self {
+		Self {
+			status: status,
+			headers: Vec::new(),
+			body: Vec::new(),
+		}
+	}
+
+	pub fn html<S: Into<String>>(html: S) -> Self {
+		Self::new(200)
+			.header("Content-Type", "text/html; charset=utf-8")
+			.body(html.into().into_bytes())
+	}
+
+	pub fn text<S: Into<String>>(text: S) -> Self {
+		Self::new(200)
+			.header("Content-Type", "text/plain; charset=utf-8")
+			.body(text.into().into_bytes())
+	}
+
+	pub fn json<S: Into<String>>(json: S) -> Self {
+		Self::new(200)
+			.header("Content-Type", "application/json")
+			.body(json.into().into_bytes())
+	}
+
+	/// `303 See Other`, so browsers `GET` the location after posting a form
+	pub fn redirect(location: &str) -> Self {
+		Self::new(303).header("Location", location)
+	}
+
+	pub fn not_found() -> Self {
+		Self::new(404)
+	}
+
+	pub fn header(mut self, name: &str, value: &str) -> Self {
+		self.headers.push((name.to_owned(), value.to_owned()));
+		self
+	}
+
+	pub fn body(mut self, body: Vec<u8>) -> Self {
+		self.body = body;
+		self
+	}
+
+	pub fn get_header(&self, name: &str) -> Option<&str> {
+		self.headers
+			.iter()
+			.find(|(n, _)| n.eq_ignore_ascii_case(name))
+			.map(|(_, v)| v.as_str())
+	}
+
+	/// Drops headers not in `ALLOWED_RESPONSE_HEADERS`, and puts `csrf_token` in place of
+	/// `CSRF_TOKEN_PLACEHOLDER` in a text body
+	pub fn sanitize(mut self, csrf_token: &str) -> Self {
+		self.headers
+			.retain(|(name, _)| ALLOWED_RESPONSE_HEADERS.contains(&name.to_lowercase().as_str()));
+		if let Ok(body) = std::str::from_utf8(&self.body) {
+			if body.contains(CSRF_TOKEN_PLACEHOLDER) {
+				self.body = body.replace(CSRF_TOKEN_PLACEHOLDER, csrf_token).into_bytes();
+			}
+		}
+		self
+	}
+}
+
+fn find_form_value(s: &str, name: &str) -> Option<String> {
+	s.split('&').find_map(|pair| {
+		let mut parts = pair.splitn(2, '=');
+		let key = form_decode(parts.next()?);
+		if key == name {
+			Some(form_decode(parts.next().unwrap_or("")))
+		} else {
+			None
+		}
+	})
+}
+
+/// Like `percent_decode`, but `+` means space
+fn form_decode(s: &str) -> String {
+	percent_decode(&s.replace('+', " "))
+}
+
+/// Invalid escapes are kept as is
+pub fn percent_decode(s: &str) -> String {
+	let bytes = s.as_bytes();
+	let mut out = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+	while i < bytes.len() {
+		if bytes[i] == b'%' && i + 2 < bytes.len() {
+			let hex = |b: u8| (b as char).to_digit(16);
+			if let (Some(high), Some(low)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+				out.push((high * 16 + low) as u8);
+				i += 3;
+				continue;
+			}
+		}
+		out.push(bytes[i]);
+		i += 1;
+	}
+	String::from_utf8_lossy(&out).into_owned()
+}
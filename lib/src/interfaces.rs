@@ -16,6 +16,14 @@ pub mod models {
 		fn modified_at(&self) -> DateTime<Local>;
 		fn last_login_time(&self) -> DateTime<Local>;
 		fn status(&self) -> UserStatus;
+
+		fn has_permission(&self, perm: i32) -> bool {
+			(self.permission() & perm) != 0
+		}
+		/// Unlike `has_permission`, every bit of `perm` is needed
+		fn has_all_permissions(&self, perm: i32) -> bool {
+			(self.permission() & perm) == perm
+		}
 	}
 
 	pub trait Tag {
@@ -47,6 +55,8 @@ pub mod models {
 		fn status(&self) -> ContentStatus;
 		fn allow_comment(&self) -> bool;
 		fn category_id(&self) -> Option<i32>;
+		/// Needs a password the visitor hasn't entered, `content` is empty then
+		fn is_protected(&self) -> bool;
 		fn category(&self) -> Option<Box<dyn Category>>;
 		fn tags(&self) -> Vec<Box<dyn Tag>>;
 
@@ -55,6 +65,53 @@ pub mod models {
 		fn get_neighbor_post(&self, prev: bool) -> Option<Box<dyn Content>>;
 		fn get_comment_url(&self) -> String;
 		fn get_parent_comments(&self) -> Vec<Box<dyn Comment>>;
+		/// Cover image of the post, `None` if not set
+		fn featured_image(&self) -> Option<Box<dyn File>>;
+	}
+
+	/// A resized copy of an uploaded image
+	#[derive(Debug, Clone)]
+	pub struct ImageVariant {
+		pub url: String,
+		pub width: i32,
+		pub height: i32,
+		pub mime: String,
+	}
+
+	pub trait File {
+		fn id(&self) -> i32;
+		fn filename(&self) -> &String;
+		fn mime(&self) -> &String;
+		fn url(&self) -> String;
+		/// `None` if not an image
+		fn width(&self) -> Option<i32>;
+		fn height(&self) -> Option<i32>;
+		/// Resized copies, narrowest first. WebP copies are included, check `mime`.
+		fn derivatives(&self) -> Vec<ImageVariant>;
+
+		/// Value of `srcset`, `None` if it has no resized copies.
+		/// WebP copies are listed instead if `webp = true`, they should be put in `<picture>`.
+		fn srcset(&self, webp: bool) -> Option<String> {
+			let mut items: Vec<(i32, String)> = self
+				.derivatives()
+				.into_iter()
+				.filter(|d| (d.mime == "image/webp") == webp)
+				.map(|d| (d.width, d.url))
+				.collect();
+			if items.is_empty() {
+				return None;
+			}
+			if !webp {
+				items.push((self.width()?, self.url()));
+			}
+			Some(
+				items
+					.iter()
+					.map(|(w, u)| format!("{} {}w", u.replace('"', "%22"), w))
+					.collect::<Vec<String>>()
+					.join(", "),
+			)
+		}
 	}
 
 	pub trait Author {
@@ -4,9 +4,16 @@
 #[macro_use]
 extern crate diesel;
 
+pub mod abi;
+#[cfg(feature = "fixture")]
+pub mod fixture;
+pub mod http;
 pub mod interfaces;
 pub mod utils;
 #[macro_use]
 pub mod plugin;
 pub mod render;
+pub mod settings;
 pub mod types;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -1,37 +1,166 @@
 use crate::{
-	interfaces::models::{Author, Content},
+	http::{AdminPage, Request, Response, Route},
+	interfaces::models::{Author, Category, Content},
+	settings::{SettingField, Settings},
+	types::{CommentStatus, ContentStatus},
 	utils::{Page, StaticFile, TemplateContext},
 };
-use std::{any::Any, io};
+use std::{any::Any, ffi::CStr, io, os::raw::c_char, path::PathBuf};
 #[cfg(feature = "main")]
-use std::{collections::HashMap, ffi::OsStr};
+use crate::utils::{Fingerprints, SystemConfig};
+#[cfg(feature = "main")]
+use serde_derive::*;
+#[cfg(feature = "main")]
+use std::{
+	collections::HashMap,
+	ffi::OsStr,
+	panic::{self, AssertUnwindSafe},
+	path::Path,
+	sync::{
+		atomic::{AtomicBool, AtomicUsize, Ordering},
+		RwLock,
+	},
+};
 
+#[cfg(feature = "main")]
+use crate::abi::{
+	self, ForeignMetadata, ForeignPlugin, ForeignTheme, RawMetadata, RawPlugin, RawTheme,
+};
 #[cfg(feature = "main")]
 use libloading::{Library, Symbol};
+#[cfg(feature = "wasm")]
+use crate::wasm::{WasmLimits, WasmModule};
+
+/// Start of `PluginDeclaration`, so a random symbol with the same name is not read further
+pub const PLUGIN_DECLARATION_MAGIC: u32 = 0x534f_4841; // "SOHA"
+/// Version of `PluginDeclaration` layout itself, and of tables in `abi`
+pub const PLUGIN_DECLARATION_VERSION: u32 = 2;
+
+const RUSTC_VERSION: &str = concat!(env!("SOHABLOG_RUSTC_VERSION"), "\0");
+const LIB_VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+const TARGET: &str = concat!(env!("SOHABLOG_TARGET"), "\0");
+/// Hash of compiler version, target, source, features and locked dependencies of this crate,
+/// see `build.rs`
+const ABI_FINGERPRINT: &str = concat!(env!("SOHABLOG_ABI_FINGERPRINT"), "\0");
+
+/// Exported by every plugin as `_plugin_declaration`, see `declare_plugin_metadata!`.
+///
+/// Plugins are called through the C tables of `abi`, but values passed to them are Rust types,
+/// whose layout is unstable and not guaranteed even by the same compiler. Only C types are used
+/// here, so the server can read it from any library and refuse an obviously mismatched one before
+/// calling into it. A plugin passing `check` is still trusted rather than proven compatible, so
+/// build plugins together with the server.
+#[repr(C)]
+pub struct PluginDeclaration {
+	pub magic: u32,
+	pub version: u32,
+	/// NUL-terminated strings
+	pub rustc_version: *const c_char,
+	pub lib_version: *const c_char,
+	pub target: *const c_char,
+	pub abi_fingerprint: *const c_char,
+}
+unsafe impl Sync for PluginDeclaration {}
+impl PluginDeclaration {
+	/// Declaration of the crate being compiled
+	pub const CURRENT: Self = Self {
+		magic: PLUGIN_DECLARATION_MAGIC,
+		version: PLUGIN_DECLARATION_VERSION,
+		rustc_version: RUSTC_VERSION.as_ptr() as *const c_char,
+		lib_version: LIB_VERSION.as_ptr() as *const c_char,
+		target: TARGET.as_ptr() as *const c_char,
+		abi_fingerprint: ABI_FINGERPRINT.as_ptr() as *const c_char,
+	};
+
+	/// Checks if the plugin declaring `self` is built the same way as the server.
+	/// Unsafe because string pointers are read, `magic` and `version` are checked before that.
+	pub unsafe fn check(&self) -> Result<(), String> {
+		if self.magic != PLUGIN_DECLARATION_MAGIC {
+			return Err(String::from("Not a SOHABlog plugin"));
+		}
+		if self.version != PLUGIN_DECLARATION_VERSION {
+			return Err(format!(
+				"Plugin declaration version {} is not supported, expected {}",
+				self.version, PLUGIN_DECLARATION_VERSION
+			));
+		}
+		let current = Self::CURRENT;
+		let fields: [(&str, *const c_char, *const c_char); 3] = [
+			("rustc", self.rustc_version, current.rustc_version),
+			("sohablog_lib", self.lib_version, current.lib_version),
+			("target", self.target, current.target),
+		];
+		for (name, theirs, ours) in fields.iter() {
+			let theirs = CStr::from_ptr(*theirs).to_string_lossy();
+			let ours = CStr::from_ptr(*ours).to_string_lossy();
+			if theirs != ours {
+				return Err(format!(
+					"Built with {} `{}`, but the server uses `{}`",
+					name, theirs, ours
+				));
+			}
+		}
+		if CStr::from_ptr(self.abi_fingerprint) != CStr::from_ptr(current.abi_fingerprint) {
+			return Err(String::from(
+				"Built with a different source, features or dependencies of sohablog_lib, rebuild the plugin against the same one",
+			));
+		}
+		Ok(())
+	}
+}
 
 #[repr(u8)]
+#[cfg_attr(feature = "main", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PluginType {
 	Theme,
+	Plugin,
 }
 pub trait PluginMetadata: Any + Send + Sync {
 	/// Plugin trait version, must be equal with PLUGIN_TRAIT_VERSION.
 	/// Or the plugin will not be loaded.
 	fn plugin_version(&self) -> u32;
 	/// Plugin name
-	fn name(&self) -> &'static str;
+	fn name(&self) -> &str;
 	/// Plugin description
-	fn description(&self) -> &'static str;
+	fn description(&self) -> &str;
 	/// Plugin version
-	fn version(&self) -> &'static str;
+	fn version(&self) -> &str;
 	/// Plugin type
 	fn r#type(&self) -> PluginType;
+	/// Settings edited in admin, see `Plugin::settings_changed` and
+	/// `TemplateContext::theme_settings` for reading them
+	fn settings_schema(&self) -> Vec<SettingField> {
+		Vec::new()
+	}
+}
+
+/// What an archive page lists
+pub enum Archive {
+	/// Posts published in a month, `month` starts from 1
+	Month { year: i32, month: u32 },
+	Category(Box<dyn Category>),
+	Tag(String),
+}
+impl Archive {
+	/// Like `2019-10`, `Category: Rust` or `Tag: rust`
+	pub fn title(&self) -> String {
+		match self {
+			Archive::Month { year, month } => format!("{}-{:02}", year, month),
+			Archive::Category(category) => format!("Category: {}", category.name()),
+			Archive::Tag(name) => format!("Tag: {}", name),
+		}
+	}
 }
 
-pub const THEME_TRAIT_VERSION: u32 = 1;
-/// Although theme is also a dynamically loaded plugin, it needs a special interface
+pub const THEME_TRAIT_VERSION: u32 = 2;
+/// Although theme is also a dynamically loaded plugin, it needs a special interface.
+///
+/// Pages with a default implementation are rendered by `TemplateContext::builtin_theme` unless
+/// the theme overrides them.
 pub trait Theme: PluginMetadata {
 	/// Theme identity string, should be unique
-	fn identity(&self) -> &'static str;
+	fn identity(&self) -> &str;
 	/// This function should write the render result for post list page to `out`
 	fn post_list(
 		&self,
@@ -52,22 +181,183 @@ pub trait Theme: PluginMetadata {
 	) -> io::Result<()>;
 	/// This function should return `StaticFile` struct for server to serve static files
 	fn static_file(&self, name: &str) -> Option<Box<dyn StaticFile>>;
+	/// Single page like "About", which has no category or neighbors
+	fn page_show(
+		&self,
+		out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		title: &str,
+		page: Box<dyn Content>,
+		previous_author: Option<Box<dyn Author>>,
+	) -> io::Result<()> {
+		ctx.builtin_theme.page_show(out, ctx, title, page, previous_author)
+	}
+	/// Page for an HTTP error like `404`, `message` is safe to show to visitors
+	fn error_page(
+		&self,
+		out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		status: u16,
+		message: &str,
+	) -> io::Result<()> {
+		ctx.builtin_theme.error_page(out, ctx, status, message)
+	}
+	/// Posts matching `query`, which may be empty if nothing is searched yet
+	fn search_result(
+		&self,
+		out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		query: &str,
+		page: Page,
+		posts: Vec<Box<dyn Content>>,
+	) -> io::Result<()> {
+		ctx.builtin_theme.search_result(out, ctx, query, page, posts)
+	}
+	/// Posts of a month, a category or a tag
+	fn archive(
+		&self,
+		out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		archive: &Archive,
+		page: Page,
+		posts: Vec<Box<dyn Content>>,
+	) -> io::Result<()> {
+		ctx.builtin_theme.archive(out, ctx, archive, page, posts)
+	}
+	/// Form for a protected post or page, which should post field `password` with CSRF token
+	/// to `action`
+	fn password_form(
+		&self,
+		out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		title: &str,
+		action: &str,
+		wrong_password: bool,
+	) -> io::Result<()> {
+		ctx.builtin_theme.password_form(out, ctx, title, action, wrong_password)
+	}
+}
+
+/// What a plugin can see while initializing
+pub struct PluginContext<'a> {
+	/// Directory for the plugin's own files, created before `init`
+	pub data_dir: PathBuf,
+	pub upload_route: &'a str,
+	pub is_prod: bool,
+}
+
+/// A post or page about to be saved
+#[cfg_attr(feature = "main", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct ContentDraft {
+	/// `None` if not created yet
+	pub id: Option<i32>,
+	pub title: Option<String>,
+	pub slug: Option<String>,
+	/// Markdown source
+	pub content: String,
+	pub status: ContentStatus,
+	/// Saved as draft, the published content is not changed
+	pub is_draft: bool,
+}
+
+/// A comment about to be inserted
+#[cfg_attr(feature = "main", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct CommentDraft {
+	pub content_id: i32,
+	/// `None` for guests
+	pub user_id: Option<i32>,
+	pub author_name: String,
+	pub author_mail: Option<String>,
+	pub author_link: Option<String>,
+	pub ip: String,
+	pub user_agent: Option<String>,
+	pub text: String,
+	pub reply_to: Option<i32>,
+	/// Filters may change this, e.g. to `Spam` or `PendingReview`
+	pub status: CommentStatus,
 }
 
-pub const PLUGIN_TRAIT_VERSION: u32 = 0;
+#[cfg_attr(feature = "main", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "main", serde(rename_all = "lowercase"))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InjectionPoint {
+	/// End of `<head>`
+	Head,
+	/// End of `<body>`
+	Footer,
+}
+
+pub const PLUGIN_TRAIT_VERSION: u32 = 2;
+/// General plugin, every hook does nothing by default.
+///
+/// Hooks of all plugins are called in ascending `priority`. A plugin panicking in a hook is
+/// disabled until restart, and the changes it made in that hook are dropped.
 pub trait Plugin: PluginMetadata {
-	// TODO: Finish this.
+	/// Smaller runs earlier, plugins with same priority run in loading order
+	fn priority(&self) -> i32 {
+		100
+	}
+	/// Called once after loaded. Plugin is disabled if this returns an error.
+	fn init(&self, _ctx: &PluginContext) -> Result<(), String> {
+		Ok(())
+	}
+	/// Called once when plugins are unloaded, not called if the plugin is disabled
+	fn shutdown(&self) {}
+	/// Called after `init` with saved settings, and again whenever an admin changes them
+	fn settings_changed(&self, _settings: &Settings) {}
+
+	/// Called before a post or page is saved in admin panel
+	fn filter_content_before_save(&self, _content: &mut ContentDraft) {}
+	/// Called with markdown source of a post or page before rendering
+	fn filter_content_before_render(&self, _content_id: i32, _content: &mut String) {}
+	/// Called before a comment is inserted, returning an error rejects it with the message
+	fn filter_comment(&self, _comment: &mut CommentDraft) -> Result<(), String> {
+		Ok(())
+	}
+
+	/// Called after a post or page becomes published
+	fn on_publish(&self, _id: i32, _content: &ContentDraft) {}
+	/// Called after a comment is inserted
+	fn on_comment(&self, _id: i32, _comment: &CommentDraft) {}
+
+	/// HTML inserted into pages of the theme at `point`
+	fn inject(&self, _point: InjectionPoint) -> Option<String> {
+		None
+	}
+
+	/// Routes mounted under `/plugin/<name>`, the first matching one is used
+	fn routes(&self) -> Vec<Route> {
+		Vec::new()
+	}
+	/// Handles a request matching the route named `route`
+	fn handle(&self, _route: &str, _req: &Request) -> Response {
+		Response::not_found()
+	}
+
+	/// Pages shown in admin navigation, only for users having their permission
+	fn admin_pages(&self) -> Vec<AdminPage> {
+		Vec::new()
+	}
+	/// Handles `GET` or `POST` to the admin page `slug`. Body of a `200` response is HTML
+	/// put into the admin layout, other responses like redirects are sent as is.
+	fn admin_page(&self, _slug: &str, _req: &Request) -> Response {
+		Response::not_found()
+	}
 }
 
 #[macro_export]
 macro_rules! declare_plugin_metadata {
 	($plugin:ty, $constructor:path) => {
 		#[no_mangle]
-		pub extern "C" fn _plugin_metadata() -> *mut $crate::plugin::PluginMetadata {
+		pub static _plugin_declaration: $crate::plugin::PluginDeclaration =
+			$crate::plugin::PluginDeclaration::CURRENT;
+
+		#[no_mangle]
+		pub unsafe extern "C" fn _plugin_metadata(out: *mut $crate::abi::RawMetadata) -> bool {
 			let constructor: fn() -> $plugin = $constructor;
-			let object = constructor();
-			let boxed: Box<$crate::plugin::PluginMetadata> = Box::new(object);
-			Box::into_raw(boxed)
+			$crate::abi::export_metadata(out, constructor)
 		}
 	};
 }
@@ -75,22 +365,115 @@ macro_rules! declare_plugin_metadata {
 macro_rules! declare_plugin {
 	($plugin:ty, $constructor:path, $plugin_type:path) => {
 		#[no_mangle]
-		pub extern "C" fn _plugin_create() -> *mut $plugin_type {
+		pub unsafe extern "C" fn _plugin_create(
+			out: *mut <dyn $plugin_type as $crate::abi::Export<$plugin>>::Raw,
+		) -> bool {
 			let constructor: fn() -> $plugin = $constructor;
-			let object = constructor();
-			let boxed: Box<$plugin_type> = Box::new(object);
-			Box::into_raw(boxed)
+			$crate::abi::export::<dyn $plugin_type, $plugin>(out, constructor)
 		}
 	};
 }
 
+#[cfg(feature = "main")]
+struct LoadedPlugin {
+	plugin: Box<dyn Plugin>,
+	/// Set after init failed or a hook panicked
+	disabled: AtomicBool,
+}
+
+/// Result of loading a file in the plugin directory
+#[cfg(feature = "main")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadStatus {
+	Loaded,
+	/// Disabled in admin, or after it crashed the server while initializing
+	Disabled,
+	/// Why the file couldn't be loaded or initialized
+	Failed(String),
+}
+
+/// Metadata of a plugin file, read before anything else in it is called
+#[cfg(feature = "main")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileMetadata {
+	pub name: String,
+	pub description: String,
+	pub version: String,
+	pub r#type: PluginType,
+	/// Name of a plugin, or `Theme::identity` of a theme. Used to enable or disable it.
+	pub identity: String,
+}
+#[cfg(feature = "main")]
+impl FileMetadata {
+	fn of(metadata: &dyn PluginMetadata) -> Self {
+		Self {
+			name: String::from(metadata.name()),
+			description: String::from(metadata.description()),
+			version: String::from(metadata.version()),
+			r#type: metadata.r#type(),
+			identity: String::from(metadata.name()),
+		}
+	}
+}
+
+/// `FileMetadata` saved for a library, so a disabled one is skipped without being opened.
+/// Size and modified time tell if the file is replaced.
+#[cfg(feature = "main")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedMetadata {
+	size: u64,
+	modified: u64,
+	metadata: FileMetadata,
+}
+#[cfg(feature = "main")]
+impl CachedMetadata {
+	fn stamp(filename: &OsStr) -> Option<(u64, u64)> {
+		let file = std::fs::metadata(filename).ok()?;
+		let modified = file
+			.modified()
+			.ok()?
+			.duration_since(std::time::UNIX_EPOCH)
+			.ok()?;
+		Some((file.len(), modified.as_secs()))
+	}
+}
+
+/// A file found in the plugin directory
+#[cfg(feature = "main")]
+#[derive(Debug, Clone)]
+pub struct PluginFile {
+	pub path: String,
+	/// `None` if loading failed before metadata could be read
+	pub metadata: Option<FileMetadata>,
+	pub status: LoadStatus,
+}
+
+/// Shared by requests, written only when plugins are reloaded
+#[cfg(feature = "main")]
+pub type SharedPluginManager = RwLock<PluginManager>;
+
+/// Plugins and themes must be dropped before the libraries they come from
 #[cfg(feature = "main")]
 pub struct PluginManager {
-	plugins: Vec<Box<dyn Plugin>>,
+	/// Sorted by priority
+	plugins: Vec<LoadedPlugin>,
 	themes: HashMap<String, Box<dyn Theme>>,
 	loaded_libraries: Vec<Library>,
+	/// Every file found by `load_from_dir`
+	files: Vec<PluginFile>,
+	/// Identities which are not loaded
+	disabled: Vec<String>,
+	/// Plugin disabled since it crashed the server while initializing last time
+	crashed: Option<String>,
+	/// By path of library, saved in `{plugin_dir}/data/.metadata.json`
+	metadata_cache: HashMap<String, CachedMetadata>,
+	/// Libraries are loaded from copies, so a new build can replace the file
+	hot_reload: bool,
+	/// Of static files of themes
+	fingerprints: Fingerprints,
+	#[cfg(feature = "wasm")]
+	wasm_limits: WasmLimits,
 }
-#[allow(unreachable_patterns)]
 #[cfg(feature = "main")]
 impl PluginManager {
 	pub fn new() -> Self {
@@ -98,50 +481,459 @@ impl PluginManager {
 			plugins: Vec::new(),
 			themes: HashMap::new(),
 			loaded_libraries: Vec::new(),
+			files: Vec::new(),
+			disabled: Vec::new(),
+			crashed: None,
+			metadata_cache: HashMap::new(),
+			hot_reload: false,
+			fingerprints: Fingerprints::default(),
+			#[cfg(feature = "wasm")]
+			wasm_limits: WasmLimits::default(),
 		}
 	}
 
+	pub fn fingerprints(&self) -> &Fingerprints {
+		&self.fingerprints
+	}
+
+	/// Should be set before loading, see `reload`
+	pub fn set_hot_reload(&mut self, enabled: bool) {
+		self.hot_reload = enabled;
+	}
+
+	/// Plugins and themes skipped by later loads, by identity
+	pub fn set_disabled(&mut self, identities: Vec<String>) {
+		self.disabled = identities;
+	}
+
+	pub fn files(&self) -> &[PluginFile] {
+		&self.files
+	}
+
+	pub fn crashed(&self) -> Option<&str> {
+		self.crashed.as_ref().map(|s| s.as_str())
+	}
+
+	/// Written while a plugin is created or initializes, with its identity
+	fn init_marker<P: AsRef<Path>>(plugin_dir: P) -> PathBuf {
+		plugin_dir.as_ref().join("data").join(".initializing")
+	}
+
+	fn metadata_cache_path<P: AsRef<Path>>(plugin_dir: P) -> PathBuf {
+		plugin_dir.as_ref().join("data").join(".metadata.json")
+	}
+
+	/// Runs `f` with the marker holding `identity`, which is left if the server crashes
+	fn with_init_marker<T, F: FnOnce() -> T>(marker: &Path, identity: &str, f: F) -> T {
+		if let Some(dir) = marker.parent() {
+			let _ = std::fs::create_dir_all(dir);
+		}
+		let _ = std::fs::write(marker, identity);
+		let result = f();
+		let _ = std::fs::remove_file(marker);
+		result
+	}
+
+	/// Disables the plugin which crashed the server while being created or initialized last
+	/// time, since the marker is left. Returns its identity, which should be saved as disabled.
+	pub fn recover_from_crash(&mut self, plugin_dir: &str) -> Option<String> {
+		let marker = Self::init_marker(plugin_dir);
+		let name = std::fs::read_to_string(&marker).ok()?;
+		let _ = std::fs::remove_file(&marker);
+		println!("Plugin `{}` crashed while being created or initialized and is disabled", name);
+		self.disabled.push(name.to_owned());
+		self.crashed = Some(name.to_owned());
+		Some(name)
+	}
+
+	/// Loaded plugins in priority order, with whether they are enabled
+	pub fn plugins(&self) -> Vec<(&dyn Plugin, bool)> {
+		self.plugins
+			.iter()
+			.map(|loaded| (loaded.plugin.as_ref(), !loaded.disabled.load(Ordering::Relaxed)))
+			.collect()
+	}
+
+	/// Limits for WebAssembly plugins loaded later
+	#[cfg(feature = "wasm")]
+	pub fn set_wasm_limits(&mut self, limits: WasmLimits) {
+		self.wasm_limits = limits;
+	}
+
 	pub fn get_theme(&self, name: &String) -> Option<&Box<dyn Theme>> {
 		self.themes.get(name)
 	}
 
-	pub unsafe fn load<T: AsRef<OsStr>>(&mut self, filename: T) -> Result<(), &str> {
-		let lib = Library::new(filename.as_ref()).map_err(|_| "Unable to load the theme")?;
-		self.loaded_libraries.push(lib);
-		let lib = self.loaded_libraries.last().unwrap();
+	/// All loaded themes, ordered by identity
+	pub fn themes(&self) -> Vec<&dyn Theme> {
+		let mut themes: Vec<&dyn Theme> = self.themes.values().map(|t| t.as_ref()).collect();
+		themes.sort_by_key(|t| t.identity());
+		themes
+	}
+
+	/// Calls `f` with the plugin, which is disabled if it panics
+	fn call<T, F: FnOnce(&dyn Plugin) -> T>(&self, loaded: &LoadedPlugin, hook: &str, f: F) -> Option<T> {
+		let plugin = loaded.plugin.as_ref();
+		match panic::catch_unwind(AssertUnwindSafe(|| f(plugin))) {
+			Ok(result) => Some(result),
+			Err(_) => {
+				loaded.disabled.store(true, Ordering::Relaxed);
+				println!(
+					"Plugin `{}` panicked in `{}` and is disabled",
+					plugin.name(),
+					hook
+				);
+				None
+			}
+		}
+	}
+
+	fn enabled(&self) -> impl Iterator<Item = &LoadedPlugin> {
+		self.plugins
+			.iter()
+			.filter(|loaded| !loaded.disabled.load(Ordering::Relaxed))
+	}
+
+	fn find_enabled(&self, name: &str) -> Option<&LoadedPlugin> {
+		self.enabled().find(|loaded| loaded.plugin.name() == name)
+	}
+
+	/// Calls `hook` of every enabled plugin in priority order
+	fn each<F: FnMut(&dyn Plugin)>(&self, hook: &str, mut f: F) {
+		for loaded in self.enabled() {
+			self.call(loaded, hook, |plugin| f(plugin));
+		}
+	}
 
-		let constructor: Symbol<unsafe extern "C" fn() -> *mut dyn PluginMetadata> = lib
+	/// Like `each`, but changes to `value` by a panicking plugin are dropped
+	fn filter<T: Clone, R, F: Fn(&dyn Plugin, &mut T) -> Result<(), R>>(
+		&self,
+		hook: &str,
+		value: &mut T,
+		f: F,
+	) -> Result<(), R> {
+		for loaded in self.enabled() {
+			let mut changed = value.clone();
+			if let Some(result) = self.call(loaded, hook, |plugin| f(plugin, &mut changed)) {
+				*value = changed;
+				result?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Initializes loaded plugins, each gets `{plugin_dir}/data/{name}/` as data directory
+	pub fn init_plugins(&mut self, config: &SystemConfig) {
+		self.plugins.sort_by_key(|loaded| loaded.plugin.priority());
+		let marker = Self::init_marker(&config.plugin_dir);
+		for loaded in &self.plugins {
+			let plugin = loaded.plugin.as_ref();
+			let data_dir = Path::new(&config.plugin_dir)
+				.join("data")
+				.join(plugin.name());
+			let result = std::fs::create_dir_all(&data_dir)
+				.map_err(|e| e.to_string())
+				.and_then(|_| {
+					let ctx = PluginContext {
+						data_dir: data_dir,
+						upload_route: &config.upload_route,
+						is_prod: config.is_prod,
+					};
+					Self::with_init_marker(&marker, plugin.name(), || {
+						panic::catch_unwind(AssertUnwindSafe(|| plugin.init(&ctx)))
+							.unwrap_or_else(|_| Err(String::from("panicked")))
+					})
+				});
+			if let Err(e) = result {
+				loaded.disabled.store(true, Ordering::Relaxed);
+				println!("Plugin `{}` failed to initialize: {}", plugin.name(), e);
+				let file = self.files.iter_mut().find(|f| {
+					f.metadata.as_ref().map_or(false, |m| {
+						m.r#type == PluginType::Plugin && m.identity == plugin.name()
+					})
+				});
+				if let Some(file) = file {
+					file.status = LoadStatus::Failed(format!("Failed to initialize: {}", e));
+				}
+			}
+		}
+	}
+
+	pub fn filter_content_before_save(&self, content: &mut ContentDraft) {
+		let _ = self.filter("filter_content_before_save", content, |p, c| {
+			p.filter_content_before_save(c);
+			Ok::<(), ()>(())
+		});
+	}
+
+	pub fn filter_content_before_render(&self, content_id: i32, content: &mut String) {
+		let _ = self.filter("filter_content_before_render", content, |p, c| {
+			p.filter_content_before_render(content_id, c);
+			Ok::<(), ()>(())
+		});
+	}
+
+	/// Returns the message of the first plugin rejecting the comment
+	pub fn filter_comment(&self, comment: &mut CommentDraft) -> Result<(), String> {
+		self.filter("filter_comment", comment, |p, c| p.filter_comment(c))
+	}
+
+	pub fn on_publish(&self, id: i32, content: &ContentDraft) {
+		self.each("on_publish", |p| p.on_publish(id, content));
+	}
+
+	pub fn on_comment(&self, id: i32, comment: &CommentDraft) {
+		self.each("on_comment", |p| p.on_comment(id, comment));
+	}
+
+	/// HTML of all plugins for `point`, joined with new lines
+	pub fn inject(&self, point: InjectionPoint) -> String {
+		let mut html = Vec::new();
+		self.each("inject", |p| {
+			if let Some(s) = p.inject(point) {
+				html.push(s);
+			}
+		});
+		html.join("\n")
+	}
+
+	/// Handles a request to `/plugin/<name>/...`, `None` if no route of the plugin matches
+	pub fn handle_request(&self, name: &str, req: &mut Request) -> Option<Response> {
+		let loaded = self.find_enabled(name)?;
+		let routes = self.call(loaded, "routes", |p| p.routes())?;
+		let (route, params) = routes
+			.iter()
+			.find_map(|route| Some((route, route.matches(req.method, &req.path)?)))?;
+		req.params = params;
+		let req: &Request = req;
+		Some(
+			self.call(loaded, "handle", |p| p.handle(&route.name, req))
+				.unwrap_or_else(|| Response::new(500)),
+		)
+	}
+
+	/// Admin pages of all enabled plugins, with names of the plugins
+	pub fn admin_pages(&self) -> Vec<(String, AdminPage)> {
+		let mut pages = Vec::new();
+		self.each("admin_pages", |p| {
+			pages.extend(p.admin_pages().into_iter().map(|page| (p.name().to_owned(), page)));
+		});
+		pages
+	}
+
+	pub fn find_admin_page(&self, name: &str, slug: &str) -> Option<AdminPage> {
+		let loaded = self.find_enabled(name)?;
+		self.call(loaded, "admin_pages", |p| p.admin_pages())?
+			.into_iter()
+			.find(|page| page.slug == slug)
+	}
+
+	pub fn handle_admin_page(&self, name: &str, slug: &str, req: &Request) -> Response {
+		self.find_enabled(name)
+			.and_then(|loaded| self.call(loaded, "admin_page", |p| p.admin_page(slug, req)))
+			.unwrap_or_else(|| Response::new(500))
+	}
+
+	/// `(type, identity, schema)` of themes and enabled plugins declaring settings. Themes are
+	/// identified by `identity`, plugins by `name`.
+	pub fn settings_schemas(&self) -> Vec<(PluginType, String, Vec<SettingField>)> {
+		let mut schemas: Vec<(PluginType, String, Vec<SettingField>)> = self
+			.themes()
+			.into_iter()
+			.map(|t| (PluginType::Theme, t.identity().to_owned(), t.settings_schema()))
+			.collect();
+		self.each("settings_schema", |p| {
+			schemas.push((PluginType::Plugin, p.name().to_owned(), p.settings_schema()));
+		});
+		schemas.retain(|(_, _, schema)| !schema.is_empty());
+		schemas
+	}
+
+	pub fn settings_schema(&self, r#type: PluginType, identity: &str) -> Option<Vec<SettingField>> {
+		self.settings_schemas()
+			.into_iter()
+			.find(|(t, i, _)| *t == r#type && i == identity)
+			.map(|(_, _, schema)| schema)
+	}
+
+	/// Tells the plugin `name` about its current settings
+	pub fn settings_changed(&self, name: &str, settings: &Settings) {
+		if let Some(loaded) = self.find_enabled(name) {
+			self.call(loaded, "settings_changed", |p| p.settings_changed(settings));
+		}
+	}
+
+	/// Copies a library to a new file, the system may return the old library if the same path
+	/// is loaded again. The copy is removed once loaded, which is fine on Unix.
+	fn copy_for_reload(filename: &OsStr) -> Result<PathBuf, String> {
+		static COUNTER: AtomicUsize = AtomicUsize::new(0);
+		let source = Path::new(filename);
+		let dir = std::env::temp_dir().join("sohablog-plugins");
+		std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+		let copy = dir.join(format!(
+			"{}-{}-{}",
+			std::process::id(),
+			COUNTER.fetch_add(1, Ordering::Relaxed),
+			source.file_name().and_then(|s| s.to_str()).unwrap_or("plugin")
+		));
+		std::fs::copy(source, &copy).map_err(|e| format!("Unable to copy the library: {}", e))?;
+		Ok(copy)
+	}
+
+	fn is_disabled(&self, identity: &str) -> bool {
+		self.disabled.iter().any(|i| i == identity)
+	}
+
+	/// Loads a theme or plugin, nothing in the library is called unless its declaration matches
+	pub unsafe fn load<T: AsRef<OsStr>>(&mut self, filename: T) -> Result<(), String> {
+		self.load_library(filename.as_ref(), &mut None).map(|_| ())
+	}
+
+	/// Like `load`, `info` is set once metadata is read. `Ok(false)` if it's disabled, which is
+	/// known from `metadata_cache` without opening the library if it's loaded before.
+	unsafe fn load_library(
+		&mut self,
+		filename: &OsStr,
+		info: &mut Option<FileMetadata>,
+	) -> Result<bool, String> {
+		let key = Path::new(filename).display().to_string();
+		let stamp = CachedMetadata::stamp(filename);
+		let cached = self
+			.metadata_cache
+			.get(&key)
+			.filter(|c| Some((c.size, c.modified)) == stamp)
+			.map(|c| c.metadata.clone());
+		if let Some(cached) = &cached {
+			*info = Some(cached.clone());
+			if self.is_disabled(&cached.identity) {
+				return Ok(false);
+			}
+		}
+
+		let lib = if self.hot_reload {
+			let copy = Self::copy_for_reload(filename)?;
+			let lib = Library::new(copy.as_os_str());
+			let _ = std::fs::remove_file(&copy);
+			lib
+		} else {
+			Library::new(filename)
+		}
+		.map_err(|e| format!("Unable to load the library: {}", e))?;
+		let declaration: Symbol<*const PluginDeclaration> = lib
+			.get(b"_plugin_declaration")
+			.map_err(|_| "No plugin declaration, the plugin may be built with an old sohablog_lib")?;
+		(**declaration).check()?;
+
+		let constructor: Symbol<unsafe extern "C" fn(*mut RawMetadata) -> bool> = lib
 			.get(b"_plugin_metadata")
 			.map_err(|_| "Not a valid plugin library")?;
-		let raw_box = constructor();
-		let metadata: Box<dyn PluginMetadata> = Box::from_raw(raw_box);
+		let raw = abi::create(*constructor).ok_or("Panicked while reading metadata")?;
+		let metadata = ForeignMetadata::from_raw(raw);
+		let mut file_metadata = FileMetadata::of(&metadata);
+		if let Some(cached) = &cached {
+			file_metadata.identity = cached.identity.to_owned(); // a theme's is known after created
+		}
+		self.cache_metadata(&key, stamp, &file_metadata);
+		*info = Some(file_metadata.to_owned());
+		if self.is_disabled(&file_metadata.identity) {
+			return Ok(false);
+		}
+		let marker = Path::new(filename)
+			.parent()
+			.map(|dir| Self::init_marker(dir))
+			.unwrap_or_else(|| Self::init_marker("."));
 
 		match metadata.r#type() {
 			PluginType::Theme => {
-				let constructor: Symbol<unsafe extern "C" fn() -> *mut dyn Theme> = lib
+				let constructor: Symbol<unsafe extern "C" fn(*mut RawTheme) -> bool> = lib
 					.get(b"_plugin_create")
 					.map_err(|_| "Not a valid plugin library")?;
-				let raw_box = constructor();
-				let theme: Box<dyn Theme> = Box::from_raw(raw_box);
+				let raw = Self::with_init_marker(&marker, &file_metadata.identity, || {
+					abi::create(*constructor)
+				})
+				.ok_or("Panicked while being created")?;
+				let theme: Box<dyn Theme> = Box::new(ForeignTheme::from_raw(raw));
 				if theme.plugin_version() != THEME_TRAIT_VERSION {
-					return Err("Theme version is not compatible.");
+					return Err(String::from("Theme version is not compatible."));
+				}
+				let identity = String::from(theme.identity());
+				if let Some(info) = info {
+					info.identity = identity.to_owned();
+					self.cache_metadata(&key, stamp, &info.clone());
+				}
+				if self.is_disabled(&identity) {
+					return Ok(false);
 				}
-				self.themes.insert(String::from(theme.identity()), theme);
+				self.themes.insert(identity, theme);
 			}
-			_ => {
-				let constructor: Symbol<unsafe extern "C" fn() -> *mut dyn Plugin> = lib
+			PluginType::Plugin => {
+				let constructor: Symbol<unsafe extern "C" fn(*mut RawPlugin) -> bool> = lib
 					.get(b"_plugin_create")
 					.map_err(|_| "Not a valid plugin library")?;
-				let raw_box = constructor();
-				let plugin: Box<dyn Plugin> = Box::from_raw(raw_box);
+				let raw = Self::with_init_marker(&marker, &file_metadata.identity, || {
+					abi::create(*constructor)
+				})
+				.ok_or("Panicked while being created")?;
+				let plugin: Box<dyn Plugin> = Box::new(ForeignPlugin::from_raw(raw));
 				if plugin.plugin_version() != PLUGIN_TRAIT_VERSION {
-					return Err("Plugin version is not compatible.");
+					return Err(String::from("Plugin version is not compatible."));
 				}
-				self.plugins.push(plugin);
+				self.plugins.push(LoadedPlugin {
+					plugin: plugin,
+					disabled: AtomicBool::new(false),
+				});
 			}
 		}
+		// objects from the library are dropped before it, see field order
+		self.loaded_libraries.push(lib);
+		Ok(true)
+	}
 
-		Ok(())
+	fn cache_metadata(&mut self, key: &str, stamp: Option<(u64, u64)>, metadata: &FileMetadata) {
+		if let Some((size, modified)) = stamp {
+			self.metadata_cache.insert(
+				key.to_owned(),
+				CachedMetadata {
+					size: size,
+					modified: modified,
+					metadata: metadata.to_owned(),
+				},
+			);
+		}
+	}
+
+	/// Loads a WebAssembly plugin or theme, see `wasm` module for its ABI
+	#[cfg(feature = "wasm")]
+	pub fn load_wasm<T: AsRef<Path>>(&mut self, filename: T) -> Result<(), String> {
+		self.load_wasm_file(filename.as_ref(), &mut None).map(|_| ())
+	}
+
+	#[cfg(feature = "wasm")]
+	fn load_wasm_file(
+		&mut self,
+		filename: &Path,
+		info: &mut Option<FileMetadata>,
+	) -> Result<bool, String> {
+		let bytes = std::fs::read(filename).map_err(|e| e.to_string())?;
+		let module = WasmModule::load(&bytes, self.wasm_limits)?;
+		let mut metadata = FileMetadata::of(&module);
+		if module.is_theme() {
+			metadata.identity = String::from(module.identity());
+		}
+		let identity = metadata.identity.to_owned();
+		*info = Some(metadata);
+		if self.is_disabled(&identity) {
+			return Ok(false);
+		}
+		if module.is_theme() {
+			self.themes.insert(identity, Box::new(module));
+		} else {
+			self.plugins.push(LoadedPlugin {
+				plugin: Box::new(module),
+				disabled: AtomicBool::new(false),
+			});
+		}
+		Ok(true)
 	}
 
 	pub fn load_from_dir(&mut self, path: &String) -> std::io::Result<()> {
@@ -150,22 +942,89 @@ impl PluginManager {
 			path::{Path, PathBuf},
 		};
 		let path = Path::new(path);
+		let cache_path = Self::metadata_cache_path(path);
+		self.metadata_cache = std::fs::read(&cache_path)
+			.ok()
+			.and_then(|bytes| serde_json::from_slice(&bytes).ok())
+			.unwrap_or_default();
 		if path.is_dir() {
 			for file in read_dir(path)? {
 				let file = file?;
 				let path: PathBuf = file.path();
-				if path.is_file() {
-					// TODO: LOG
-					unsafe {
-						if let Err(e) = self.load(path.as_os_str()) {
-							dbg!(e);
-						} else {
-							dbg!(format!("library loaded from {:?}", path));
-						}
-					}
+				if !path.is_file() {
+					continue;
 				}
+				let mut info = None;
+				let result = if path.extension() == Some(OsStr::new("wasm")) {
+					#[cfg(feature = "wasm")]
+					let result = self.load_wasm_file(&path, &mut info);
+					#[cfg(not(feature = "wasm"))]
+					let result = Err(String::from("WebAssembly plugins are not enabled"));
+					result
+				} else {
+					unsafe { self.load_library(path.as_os_str(), &mut info) }
+				};
+				let status = match result {
+					Ok(true) => {
+						println!("Library loaded from {:?}", path);
+						LoadStatus::Loaded
+					}
+					Ok(false) => {
+						println!("Skipped disabled plugin {:?}", path);
+						LoadStatus::Disabled
+					}
+					Err(e) => {
+						println!("Failed to load {:?}: {}", path, e);
+						LoadStatus::Failed(e)
+					}
+				};
+				self.files.push(PluginFile {
+					path: path.display().to_string(),
+					metadata: info,
+					status: status,
+				});
+			}
+			let files = &self.files;
+			self.metadata_cache
+				.retain(|key, _| files.iter().any(|file| &file.path == key));
+			if let Ok(json) = serde_json::to_vec(&self.metadata_cache) {
+				let _ = std::fs::create_dir_all(path.join("data"));
+				let _ = std::fs::write(&cache_path, json);
 			}
 		}
 		Ok(())
 	}
+
+	/// Calls `shutdown` of enabled plugins
+	fn shutdown_plugins(&self) {
+		for loaded in &self.plugins {
+			let plugin = loaded.plugin.as_ref();
+			if !loaded.disabled.load(Ordering::Relaxed)
+				&& panic::catch_unwind(AssertUnwindSafe(|| plugin.shutdown())).is_err()
+			{
+				println!("Plugin `{}` panicked while shutting down", plugin.name());
+			}
+		}
+	}
+
+	/// Shuts down and unloads everything, then loads and initializes `plugin_dir` again
+	pub fn reload(&mut self, config: &SystemConfig) -> std::io::Result<()> {
+		self.shutdown_plugins();
+		// objects from the libraries must be dropped first
+		self.plugins.clear();
+		self.themes.clear();
+		self.loaded_libraries.clear();
+		self.files.clear();
+		self.fingerprints = Fingerprints::default();
+		self.load_from_dir(&config.plugin_dir)?;
+		self.init_plugins(config);
+		Ok(())
+	}
+}
+
+#[cfg(feature = "main")]
+impl Drop for PluginManager {
+	fn drop(&mut self) {
+		self.shutdown_plugins();
+	}
 }
@@ -34,6 +34,11 @@ impl ToHtml for Origin<'_> {
 	}
 }
 
+/// write `s` as is, for HTML from trusted sources like plugins
+pub fn raw_html(out: &mut dyn Write, s: &str) -> IoResult<()> {
+	out.write_all(s.as_bytes())
+}
+
 pub const CONTENT_TRUNCATE_MARK: &str = "\n<!--more-->";
 pub trait RenderHelper {
 	fn markdown_to_html(&self, s: &str) -> String;
@@ -46,4 +51,9 @@ pub trait RenderHelper {
 	/// And all html format will be keeped.
 	/// If truncating depends on length, no HTML tag will be keeped.
 	fn truncate_content(&self, s: &str, len: usize, truncate_mark: bool) -> String;
+	/// Value of `srcset` for an uploaded image at `url`, `None` if it has no resized copies.
+	/// WebP copies are listed instead if `webp = true`, they should be put in `<picture>`.
+	fn srcset(&self, _url: &str, _webp: bool) -> Option<String> {
+		None
+	}
 }
@@ -0,0 +1,163 @@
+//! Options declared by plugins and themes, edited in admin and stored by the server.
+
+#[cfg(feature = "main")]
+use serde_derive::*;
+use std::collections::HashMap;
+
+#[cfg_attr(feature = "main", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "main", serde(tag = "type", rename_all = "lowercase"))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettingKind {
+	/// Single line text
+	String,
+	/// Multi-line text
+	Text,
+	/// Stored as `true` or `false`
+	Bool,
+	Number {
+		#[cfg_attr(feature = "main", serde(default))]
+		min: Option<f64>,
+		#[cfg_attr(feature = "main", serde(default))]
+		max: Option<f64>,
+	},
+	/// One of `(value, label)`
+	Choice { choices: Vec<(String, String)> },
+}
+impl SettingKind {
+	/// Form control used in admin, `checkbox`, `textarea`, `select`, `number` or `text`
+	pub fn input_type(&self) -> &'static str {
+		match self {
+			SettingKind::String => "text",
+			SettingKind::Text => "textarea",
+			SettingKind::Bool => "checkbox",
+			SettingKind::Number { .. } => "number",
+			SettingKind::Choice { .. } => "select",
+		}
+	}
+
+	pub fn choices(&self) -> Option<&Vec<(String, String)>> {
+		match self {
+			SettingKind::Choice { choices } => Some(choices),
+			_ => None,
+		}
+	}
+}
+
+/// A setting in the schema of a plugin
+#[cfg_attr(feature = "main", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+pub struct SettingField {
+	/// Unique in the plugin
+	pub key: String,
+	pub label: String,
+	#[cfg_attr(feature = "main", serde(default))]
+	pub description: Option<String>,
+	pub kind: SettingKind,
+	/// Used until an admin saves another value, must be valid
+	#[cfg_attr(feature = "main", serde(default))]
+	pub default: String,
+}
+impl SettingField {
+	/// Default value is empty, `false`, `0` or the first choice
+	pub fn new(key: &str, label: &str, kind: SettingKind) -> Self {
+		let default = match &kind {
+			SettingKind::Bool => String::from("false"),
+			SettingKind::Number { min, .. } => min.unwrap_or(0.0).to_string(),
+			SettingKind::Choice { choices } => choices
+				.first()
+				.map(|(value, _)| value.to_owned())
+				.unwrap_or_default(),
+			_ => String::new(),
+		};
+		Self {
+			key: key.to_owned(),
+			label: label.to_owned(),
+			description: None,
+			kind: kind,
+			default: default,
+		}
+	}
+
+	pub fn description(mut self, description: &str) -> Self {
+		self.description = Some(description.to_owned());
+		self
+	}
+
+	pub fn default(mut self, value: &str) -> Self {
+		self.default = value.to_owned();
+		self
+	}
+
+	/// Normalized value, or why it's invalid. `None` is an unchecked checkbox or a missing field.
+	pub fn validate(&self, value: Option<&str>) -> Result<String, String> {
+		let value = value.unwrap_or("");
+		match &self.kind {
+			SettingKind::String => match value.contains('\n') {
+				true => Err(String::from("Must be a single line")),
+				false => Ok(value.trim().to_owned()),
+			},
+			SettingKind::Text => Ok(value.replace("\r\n", "\n")),
+			SettingKind::Bool => Ok(String::from(match value {
+				"true" | "on" | "1" => "true",
+				_ => "false",
+			})),
+			SettingKind::Number { min, max } => {
+				let n: f64 = value
+					.trim()
+					.parse()
+					.map_err(|_| String::from("Must be a number"))?;
+				if !n.is_finite() {
+					return Err(String::from("Must be a finite number"));
+				}
+				if let Some(min) = min.filter(|&min| n < min) {
+					return Err(format!("Must be at least {}", min));
+				}
+				if let Some(max) = max.filter(|&max| n > max) {
+					return Err(format!("Must be at most {}", max));
+				}
+				Ok(n.to_string())
+			}
+			SettingKind::Choice { choices } => match choices.iter().any(|(v, _)| v == value) {
+				true => Ok(value.to_owned()),
+				false => Err(String::from("Must be one of the choices")),
+			},
+		}
+	}
+}
+
+/// Values of a plugin, every key in its schema has a valid value
+#[derive(Debug, Clone, Default)]
+pub struct Settings(HashMap<String, String>);
+impl Settings {
+	/// Stored values which are missing or no longer valid are replaced by defaults
+	pub fn new(schema: &[SettingField], stored: &HashMap<String, String>) -> Self {
+		Self(
+			schema
+				.iter()
+				.map(|field| {
+					let value = stored
+						.get(&field.key)
+						.and_then(|v| field.validate(Some(v)).ok())
+						.unwrap_or_else(|| field.default.to_owned());
+					(field.key.to_owned(), value)
+				})
+				.collect(),
+		)
+	}
+
+	pub fn get(&self, key: &str) -> Option<&str> {
+		self.0.get(key).map(|s| s.as_str())
+	}
+
+	pub fn get_bool(&self, key: &str) -> bool {
+		self.get(key) == Some("true")
+	}
+
+	pub fn get_number(&self, key: &str) -> Option<f64> {
+		self.get(key)?.parse().ok()
+	}
+
+	pub fn values(&self) -> &HashMap<String, String> {
+		&self.0
+	}
+}
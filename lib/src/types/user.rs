@@ -5,6 +5,18 @@ use serde_derive::*;
 
 use super::*;
 
+// Permission bits of users, plugins may use them for their pages
+pub const PERM_LOGIN: i32 = 1 << 0; // login only
+pub const PERM_POST_VIEW: i32 = 1 << 1; // view all posts (such as hidden post)
+pub const PERM_POST_EDIT: i32 = 1 << 2; // create & edit post
+pub const PERM_POST_DELETE: i32 = 1 << 3; // delete post
+pub const PERM_CATEGORY_MANAGE: i32 = 1 << 4; // manage category
+pub const PERM_COMMENT_MANAGE: i32 = 1 << 5; // manage category
+pub const PERM_AUDIT_VIEW: i32 = 1 << 6; // view audit log
+pub const PERM_FILE_MANAGE: i32 = 1 << 7; // manage files uploaded by others
+pub const PERM_SETTINGS_MANAGE: i32 = 1 << 8; // change site settings and theme
+pub const PERM_ALL: i32 = !0;
+
 #[cfg_attr(feature = "main", derive(Serialize, Deserialize, FromSqlRow, AsExpression))]
 #[cfg_attr(feature = "main", serde(rename_all = "lowercase"))]
 #[cfg_attr(feature = "main", sql_type = "Integer")]
@@ -26,4 +38,52 @@ impl EnumType for UserStatus {
 }
 
 #[cfg(feature = "main")]
-sql_from_to!(UserStatus);
+sql_from_to!(UserStatus, LoginMethod, LoginResult);
+
+#[cfg_attr(feature = "main", derive(Serialize, Deserialize, FromSqlRow, AsExpression))]
+#[cfg_attr(feature = "main", serde(rename_all = "lowercase"))]
+#[cfg_attr(feature = "main", sql_type = "Integer")]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u8)]
+pub enum LoginMethod {
+	Password = 0,
+	Totp = 1,
+	Oidc = 2,
+	Token = 3,
+}
+impl EnumType for LoginMethod {
+	fn try_from(n: i32) -> Result<Self> {
+		match n {
+			0 => Ok(Self::Password),
+			1 => Ok(Self::Totp),
+			2 => Ok(Self::Oidc),
+			3 => Ok(Self::Token),
+			_ => Err(Error::None),
+		}
+	}
+	fn number(self) -> i32 { self as i32 }
+}
+
+#[cfg_attr(feature = "main", derive(Serialize, Deserialize, FromSqlRow, AsExpression))]
+#[cfg_attr(feature = "main", serde(rename_all = "lowercase"))]
+#[cfg_attr(feature = "main", sql_type = "Integer")]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u8)]
+pub enum LoginResult {
+	Success = 0,
+	/// Wrong password, or the user doesn't exist
+	BadCredential = 1,
+	/// Rejected by identity provider, or the state is invalid
+	Rejected = 2,
+}
+impl EnumType for LoginResult {
+	fn try_from(n: i32) -> Result<Self> {
+		match n {
+			0 => Ok(Self::Success),
+			1 => Ok(Self::BadCredential),
+			2 => Ok(Self::Rejected),
+			_ => Err(Error::None),
+		}
+	}
+	fn number(self) -> i32 { self as i32 }
+}
@@ -14,10 +14,13 @@ mod db;
 pub use db::DatabaseConnection;
 
 mod static_file;
-pub use static_file::StaticFile;
+pub use static_file::{fingerprint, Fingerprints, StaticFile};
 
+use crate::http::AdminPage;
 use crate::interfaces::models::User;
+use crate::plugin::Theme;
 use crate::render::RenderHelper;
+use crate::settings::Settings;
 
 #[derive(Debug)]
 pub struct SystemConfig {
@@ -30,7 +33,22 @@ pub struct SystemConfig {
 	pub csrf_cookie_name: Option<String>,
 	pub csrf_field_name: String,
 	pub is_prod: bool,
+	/// Theme used until another one is activated in admin
 	pub theme_name: String,
+	/// Reload plugins when files in `plugin_dir` change, for development
+	pub plugin_hot_reload: bool,
+	/// Like `https://blog.example.com`, for links which must be absolute like `og:image`
+	pub site_url: Option<String>,
+}
+impl SystemConfig {
+	/// `url` prefixed with `site_url` if it's relative, `None` if `site_url` is not set then
+	pub fn absolute_url(&self, url: &str) -> Option<String> {
+		if url.contains("://") || url.starts_with("//") {
+			return Some(url.to_owned());
+		}
+		let site_url = self.site_url.as_ref()?.trim_end_matches('/');
+		Some(format!("{}/{}", site_url, url.trim_start_matches('/')))
+	}
 }
 
 pub struct TemplateContext<'a> {
@@ -40,4 +58,44 @@ pub struct TemplateContext<'a> {
 	pub user_agent: Option<&'a String>,
 	pub session_info: &'a SessionInfo,
 	pub render_helper: Box<dyn RenderHelper>,
+	/// Active theme plugin, `None` for the built-in theme
+	pub theme: Option<&'a dyn Theme>,
+	/// Theme compiled into the server, which implements every page
+	pub builtin_theme: &'a dyn Theme,
+	/// Used by `theme_static`, files are hashed on every call without it
+	pub fingerprints: Option<&'a Fingerprints>,
+	/// Settings of the theme being rendered, see `PluginMetadata::settings_schema`
+	pub theme_settings: Settings,
+	/// HTML from plugins, themes should output it unescaped at end of `<head>`
+	pub plugin_head: String,
+	/// HTML from plugins, themes should output it unescaped at end of `<body>`
+	pub plugin_footer: String,
+	/// `(plugin name, page)` for admin navigation, empty for guests
+	pub plugin_admin_pages: Vec<(String, AdminPage)>,
+}
+impl<'a> TemplateContext<'a> {
+	/// URL of a static file of the active theme, themes should use this instead of hard-coding
+	/// `/static/theme/`. Files of theme plugins get `?v=<fingerprint>`, so browsers can cache
+	/// them forever and still see changes.
+	pub fn theme_static(&self, name: &str) -> String {
+		let theme = match self.theme {
+			Some(theme) => theme,
+			None => return format!("/static/theme/{}", name), // names of built-in statics contain a hash
+		};
+		match theme.static_file(name) {
+			Some(file) => {
+				let version = match self.fingerprints {
+					Some(fingerprints) => fingerprints.get(theme.identity(), name, file.as_ref()),
+					None => fingerprint(file.content()),
+				};
+				format!("/static/theme/{}?v={}", name, version)
+			}
+			None => format!("/static/theme/{}", name),
+		}
+	}
+
+	/// URL of a static file embedded in the server
+	pub fn system_static(&self, name: &str) -> String {
+		format!("/static/system/{}", name)
+	}
 }
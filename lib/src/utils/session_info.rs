@@ -16,6 +16,9 @@ use super::CSRFToken;
 pub struct SessionInfo {
 	pub user: Option<UserSessionInfo>,
 	pub csrf_token: CSRFToken,
+	/// Theme shown to this session instead of the active one, only for users who can change it
+	#[cfg_attr(feature = "main", serde(default))]
+	pub preview_theme: Option<String>,
 }
 #[cfg(feature = "main")]
 impl SessionInfo {
@@ -44,6 +47,7 @@ impl Default for SessionInfo {
 		Self {
 			user: None,
 			csrf_token: Uuid::new_v4().into(),
+			preview_theme: None,
 		}
 	}
 }
@@ -1,7 +1,36 @@
 use mime::Mime;
+use std::{
+	collections::{hash_map::DefaultHasher, HashMap},
+	hash::Hasher,
+	sync::RwLock,
+};
 
 pub trait StaticFile {
-	fn content(&self) -> &'static [u8];
-	fn name(&self) -> &'static str;
-	fn mime(&self) -> &'static Mime;
+	fn content(&self) -> &[u8];
+	fn name(&self) -> &str;
+	fn mime(&self) -> &Mime;
+}
+
+/// Short hash of `content`, used in URLs of static files which don't have it in their names.
+/// Only stable within the same build, which is enough for cache busting.
+pub fn fingerprint(content: &[u8]) -> String {
+	let mut hasher = DefaultHasher::new();
+	hasher.write(content);
+	format!("{:016x}", hasher.finish())
+}
+
+/// Fingerprints of theme files by `(theme identity, name)`, so each file is hashed once.
+/// Must be cleared when themes are reloaded.
+#[derive(Default)]
+pub struct Fingerprints(RwLock<HashMap<(String, String), String>>);
+impl Fingerprints {
+	pub fn get(&self, identity: &str, name: &str, file: &dyn StaticFile) -> String {
+		let key = (identity.to_owned(), name.to_owned());
+		if let Some(version) = self.0.read().unwrap().get(&key) {
+			return version.to_owned();
+		}
+		let version = fingerprint(file.content());
+		self.0.write().unwrap().insert(key, version.to_owned());
+		version
+	}
 }
@@ -0,0 +1,783 @@
+//! Plugins and themes compiled to WebAssembly, run in a sandbox without access to files,
+//! network or the database.
+//!
+//! ABI, every value is passed as UTF-8 JSON in the memory of the module:
+//! - the module exports `memory`, `sohablog_alloc(len: i32) -> i32` and
+//!   `sohablog_call(hook_ptr: i32, hook_len: i32, arg_ptr: i32, arg_len: i32) -> i64`.
+//!   Buffers allocated for a call belong to the module afterwards. The result of
+//!   `sohablog_call` is the pointer of output in high 32 bits and its length in low 32 bits,
+//!   the output must stay valid until the next call. `0` means the hook is not implemented.
+//! - the host provides `sohablog.log(ptr: i32, len: i32)` and `sohablog.now() -> i64`,
+//!   which returns unix time in milliseconds.
+//! - hook `metadata` must return `{"abi": WASM_ABI_VERSION, "name", "description", "version",
+//!   "type": "plugin" | "theme"}`. Plugins may add `priority`, `routes` and `admin_pages`,
+//!   themes must add `identity` and may add `static_files: [{"name", "mime"}]`. Both may add
+//!   `settings`, a list of `SettingField`.
+//! - other hooks are named after methods of `Plugin` and `Theme`, see the implementations below
+//!   for their arguments. Filters return the changed value. Theme pages which are not
+//!   implemented are rendered by the built-in theme.
+//!
+//! Every call gets `WasmLimits::fuel`, roughly the count of instructions, and `WasmLimits::time`,
+//! so a module can't stall a request. Running out of fuel, memory or any other trap disables a
+//! plugin like a panic of native plugins, a theme fails the request instead. A module which ran
+//! out of time is not called again until it's reloaded.
+//!
+//! Modules never see cookies, `Authorization` or the CSRF token, they get
+//! `CSRF_TOKEN_PLACEHOLDER` which is replaced in their output. Only
+//! `ALLOWED_RESPONSE_HEADERS` of their responses are sent.
+
+use crate::{
+	http::{AdminPage, Request, Response, Route, CSRF_TOKEN_PLACEHOLDER},
+	interfaces::models::{Author, Comment, Content},
+	plugin::*,
+	settings::{SettingField, Settings},
+	utils::{Page, StaticFile, TemplateContext},
+};
+use mime::Mime;
+use serde::de::DeserializeOwned;
+use serde_derive::*;
+use serde_json::{json, Value};
+use std::{
+	collections::HashMap,
+	io,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		mpsc::{self, RecvTimeoutError},
+		Arc, Mutex,
+	},
+	thread,
+	time::Duration,
+};
+use wasmi::{
+	Caller, Config, Engine, Extern, Linker, Memory, Module, Store, StoreLimits,
+	StoreLimitsBuilder, TypedFunc,
+};
+
+pub const WASM_ABI_VERSION: u32 = 1;
+
+/// Longer log messages are cut
+const MAX_LOG_LEN: usize = 4096;
+
+#[derive(Debug, Clone, Copy)]
+pub struct WasmLimits {
+	/// Fuel for each call
+	pub fuel: u64,
+	/// Max size of linear memory in bytes
+	pub memory: usize,
+	/// Wall-clock time of each call
+	pub time: Duration,
+}
+impl Default for WasmLimits {
+	fn default() -> Self {
+		Self {
+			fuel: 50_000_000,
+			memory: 64 << 20,
+			time: Duration::from_secs(2),
+		}
+	}
+}
+
+struct HostState {
+	limits: StoreLimits,
+	name: String,
+}
+
+struct Runtime {
+	store: Store<HostState>,
+	memory: Memory,
+	alloc: TypedFunc<i32, i32>,
+	call: TypedFunc<(i32, i32, i32, i32), i64>,
+	fuel: u64,
+	/// Total fuel given to the store, which only counts consumed fuel
+	fuel_added: u64,
+}
+impl Runtime {
+	fn new(bytes: &[u8], limits: WasmLimits) -> Result<Self, String> {
+		let mut config = Config::default();
+		config.consume_fuel(true);
+		let engine = Engine::new(&config);
+		let module = Module::new(&engine, bytes).map_err(|e| format!("Invalid module: {}", e))?;
+		let mut store = Store::new(
+			&engine,
+			HostState {
+				limits: StoreLimitsBuilder::new()
+					.memory_size(limits.memory)
+					.instances(1)
+					.build(),
+				name: String::new(),
+			},
+		);
+		store.limiter(|state| &mut state.limits);
+		store.add_fuel(limits.fuel).map_err(|e| e.to_string())?;
+
+		let mut linker = <Linker<HostState>>::new(&engine);
+		linker
+			.func_wrap(
+				"sohablog",
+				"log",
+				|caller: Caller<HostState>, ptr: i32, len: i32| {
+					let memory = match caller.get_export("memory").and_then(Extern::into_memory) {
+						Some(m) => m,
+						None => return,
+					};
+					let mut buf = vec![0; (len.max(0) as usize).min(MAX_LOG_LEN)];
+					if memory.read(&caller, ptr as u32 as usize, &mut buf).is_ok() {
+						println!("[{}] {}", caller.data().name, String::from_utf8_lossy(&buf));
+					}
+				},
+			)
+			.and_then(|linker| {
+				linker.func_wrap("sohablog", "now", || -> i64 {
+					chrono::Utc::now().timestamp_millis()
+				})
+			})
+			.map_err(|e| e.to_string())?;
+		let instance = linker
+			.instantiate(&mut store, &module)
+			.and_then(|pre| pre.start(&mut store))
+			.map_err(|e| format!("Unable to instantiate: {}", e))?;
+
+		let memory = instance
+			.get_memory(&store, "memory")
+			.ok_or("No exported `memory`")?;
+		let alloc = instance
+			.get_typed_func::<i32, i32>(&store, "sohablog_alloc")
+			.map_err(|_| "No exported `sohablog_alloc(i32) -> i32`")?;
+		let call = instance
+			.get_typed_func::<(i32, i32, i32, i32), i64>(&store, "sohablog_call")
+			.map_err(|_| "No exported `sohablog_call(i32, i32, i32, i32) -> i64`")?;
+		Ok(Self {
+			store: store,
+			memory: memory,
+			alloc: alloc,
+			call: call,
+			fuel: limits.fuel,
+			fuel_added: limits.fuel,
+		})
+	}
+
+	/// Tops fuel up to the limit, leftover of the last call is not accumulated
+	fn refuel(&mut self) {
+		let consumed = self.store.fuel_consumed().unwrap_or(0);
+		let remaining = self.fuel_added.saturating_sub(consumed);
+		if remaining < self.fuel && self.store.add_fuel(self.fuel - remaining).is_ok() {
+			self.fuel_added += self.fuel - remaining;
+		}
+	}
+
+	fn write(&mut self, data: &[u8]) -> Result<i32, String> {
+		let ptr = self
+			.alloc
+			.call(&mut self.store, data.len() as i32)
+			.map_err(|e| e.to_string())?;
+		self.memory
+			.write(&mut self.store, ptr as u32 as usize, data)
+			.map_err(|e| e.to_string())?;
+		Ok(ptr)
+	}
+
+	/// Output of `hook`, `None` if not implemented
+	fn call(&mut self, hook: &str, arg: &[u8]) -> Result<Option<Vec<u8>>, String> {
+		self.refuel();
+		let hook_ptr = self.write(hook.as_bytes())?;
+		let arg_ptr = self.write(arg)?;
+		let packed = self
+			.call
+			.call(
+				&mut self.store,
+				(hook_ptr, hook.len() as i32, arg_ptr, arg.len() as i32),
+			)
+			.map_err(|e| e.to_string())? as u64;
+		if packed == 0 {
+			return Ok(None);
+		}
+		let (ptr, len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+		if ptr.saturating_add(len) > self.memory.data(&self.store).len() {
+			return Err(String::from("Output out of memory bounds"));
+		}
+		let mut out = vec![0; len];
+		self.memory
+			.read(&self.store, ptr, &mut out)
+			.map_err(|e| e.to_string())?;
+		Ok(Some(out))
+	}
+}
+
+#[derive(Deserialize)]
+struct StaticFileInfo {
+	name: String,
+	mime: String,
+}
+
+#[derive(Deserialize)]
+struct Metadata {
+	abi: u32,
+	name: String,
+	description: String,
+	version: String,
+	r#type: String,
+	#[serde(default)]
+	priority: Option<i32>,
+	#[serde(default)]
+	routes: Vec<Route>,
+	#[serde(default)]
+	admin_pages: Vec<AdminPage>,
+	#[serde(default)]
+	identity: Option<String>,
+	#[serde(default)]
+	static_files: Vec<StaticFileInfo>,
+	#[serde(default)]
+	settings: Vec<SettingField>,
+}
+
+/// Content is loaded once, and shared with responses
+#[derive(Clone)]
+struct WasmStaticFile {
+	content: Arc<Vec<u8>>,
+	name: String,
+	mime: Mime,
+}
+impl StaticFile for WasmStaticFile {
+	fn content(&self) -> &[u8] {
+		&self.content
+	}
+	fn name(&self) -> &str {
+		&self.name
+	}
+	fn mime(&self) -> &Mime {
+		&self.mime
+	}
+}
+
+/// Runs `hook` in another thread, and gives up after `limits.time`. The call keeps running
+/// until it runs out of fuel, `timed_out` is set so the runtime is not used again.
+fn call_with_timeout(
+	runtime: &Arc<Mutex<Runtime>>,
+	time: Duration,
+	timed_out: &AtomicBool,
+	hook: &str,
+	arg: Vec<u8>,
+) -> Result<Option<Vec<u8>>, String> {
+	if timed_out.load(Ordering::Relaxed) {
+		return Err(String::from("Timed out in an earlier call"));
+	}
+	let (sender, receiver) = mpsc::channel();
+	let runtime = runtime.clone();
+	let hook = hook.to_owned();
+	thread::spawn(move || {
+		let mut runtime = runtime.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+		let _ = sender.send(runtime.call(&hook, &arg));
+	});
+	match receiver.recv_timeout(time) {
+		Ok(result) => result,
+		Err(RecvTimeoutError::Timeout) => {
+			timed_out.store(true, Ordering::Relaxed);
+			Err(format!("Timed out after {:?}", time))
+		}
+		Err(RecvTimeoutError::Disconnected) => Err(String::from("Runtime panicked")),
+	}
+}
+
+/// A loaded WebAssembly module, which is a plugin or a theme by its metadata
+pub struct WasmModule {
+	runtime: Arc<Mutex<Runtime>>,
+	time: Duration,
+	timed_out: AtomicBool,
+	name: String,
+	description: String,
+	version: String,
+	r#type: PluginType,
+	priority: i32,
+	routes: Vec<Route>,
+	admin_pages: Vec<AdminPage>,
+	identity: String,
+	static_files: HashMap<String, WasmStaticFile>,
+	settings: Vec<SettingField>,
+}
+impl WasmModule {
+	pub fn load(bytes: &[u8], limits: WasmLimits) -> Result<Self, String> {
+		let runtime = Arc::new(Mutex::new(Runtime::new(bytes, limits)?));
+		let timed_out = AtomicBool::new(false);
+		let call = |hook: &str, arg: Vec<u8>| {
+			call_with_timeout(&runtime, limits.time, &timed_out, hook, arg)
+		};
+		let metadata = call("metadata", b"{}".to_vec())?
+			.ok_or("Hook `metadata` is not implemented")?;
+		let metadata: Metadata = serde_json::from_slice(&metadata)
+			.map_err(|e| format!("Invalid metadata: {}", e))?;
+		if metadata.abi != WASM_ABI_VERSION {
+			return Err(format!(
+				"ABI version {} is not supported, expected {}",
+				metadata.abi, WASM_ABI_VERSION
+			));
+		}
+		runtime.lock().unwrap().store.data_mut().name = metadata.name.to_owned();
+		let r#type = match metadata.r#type.as_str() {
+			"plugin" => PluginType::Plugin,
+			"theme" => PluginType::Theme,
+			t => return Err(format!("Unknown type `{}`", t)),
+		};
+
+		let mut static_files = HashMap::new();
+		for info in metadata.static_files {
+			let content = call("static_file", json!({ "name": info.name }).to_string().into_bytes())?
+				.ok_or("Hook `static_file` is not implemented")?;
+			let mime: Mime = info
+				.mime
+				.parse()
+				.map_err(|_| format!("Invalid MIME type of `{}`", info.name))?;
+			let file = WasmStaticFile {
+				content: Arc::new(content),
+				name: info.name.to_owned(),
+				mime: mime,
+			};
+			static_files.insert(info.name, file);
+		}
+		Ok(Self {
+			runtime: runtime,
+			time: limits.time,
+			timed_out: timed_out,
+			name: metadata.name,
+			description: metadata.description,
+			version: metadata.version,
+			r#type: r#type,
+			priority: metadata.priority.unwrap_or(100),
+			routes: metadata.routes,
+			admin_pages: metadata.admin_pages,
+			identity: metadata.identity.unwrap_or_default(),
+			static_files: static_files,
+			settings: metadata.settings,
+		})
+	}
+
+	pub fn is_theme(&self) -> bool {
+		self.r#type == PluginType::Theme
+	}
+
+	/// Output of `hook` as JSON, `None` if not implemented
+	fn call<T: DeserializeOwned>(&self, hook: &str, arg: Value) -> Result<Option<T>, String> {
+		let output = call_with_timeout(
+			&self.runtime,
+			self.time,
+			&self.timed_out,
+			hook,
+			arg.to_string().into_bytes(),
+		)?;
+		match output {
+			Some(output) => serde_json::from_slice(&output)
+				.map(Some)
+				.map_err(|e| format!("Invalid output: {}", e)),
+			None => Ok(None),
+		}
+	}
+
+	/// For hooks of `Plugin`, a failure is turned into a panic so `PluginManager` disables it
+	fn call_plugin<T: DeserializeOwned>(&self, hook: &str, arg: Value) -> Option<T> {
+		self.call(hook, arg)
+			.unwrap_or_else(|e| panic!("WebAssembly plugin `{}` failed in `{}`: {}", self.name, hook, e))
+	}
+
+	fn render(
+		&self,
+		out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		hook: &str,
+		arg: Value,
+	) -> io::Result<()> {
+		self.render_or(out, ctx, hook, arg, |_| {
+			Err(io::Error::new(io::ErrorKind::Other, "Hook is not implemented"))
+		})
+	}
+
+	/// Like `render`, but uses `fallback` if the module doesn't implement the hook
+	fn render_or<F>(
+		&self,
+		out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		hook: &str,
+		arg: Value,
+		fallback: F,
+	) -> io::Result<()>
+	where
+		F: FnOnce(&mut dyn io::Write) -> io::Result<()>,
+	{
+		match self
+			.call::<String>(hook, arg)
+			.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+		{
+			Some(html) => {
+				let html = html.replace(CSRF_TOKEN_PLACEHOLDER, ctx.session_info.csrf_token.as_str());
+				out.write_all(html.as_bytes())
+			}
+			None => fallback(out),
+		}
+	}
+
+	fn context_json(&self, ctx: &TemplateContext) -> Value {
+		let static_urls: HashMap<&str, String> = self
+			.static_files
+			.keys()
+			.map(|name| (name.as_str(), ctx.theme_static(name)))
+			.collect();
+		json!({
+			"user": ctx.user.as_ref().map(|u| json!({ "id": u.id(), "name": u.name() })),
+			"csrf_field_name": ctx.system_config.csrf_field_name,
+			"csrf_token": CSRF_TOKEN_PLACEHOLDER,
+			"plugin_head": ctx.plugin_head,
+			"plugin_footer": ctx.plugin_footer,
+			"static_urls": static_urls,
+			"settings": ctx.theme_settings.values(),
+		})
+	}
+}
+
+fn content_json(ctx: &TemplateContext, post: &dyn Content) -> Value {
+	json!({
+		"id": post.id(),
+		"title": post.title(),
+		"slug": post.slug(),
+		"link": post.link(),
+		"time": post.time().to_rfc3339(),
+		"author": post.user().name(),
+		"category": post.category().map(|c| c.name().to_owned()),
+		"tags": post.get_tags_name(),
+		"allow_comment": post.allow_comment(),
+		"comment_url": post.get_comment_url(),
+		"featured_image": post.featured_image().map(|f| f.url()),
+		"protected": post.is_protected(),
+		"content_html": match post.is_protected() {
+			true => None,
+			false => Some(ctx.render_helper.markdown_to_html(post.content())),
+		},
+	})
+}
+
+fn comment_json(ctx: &TemplateContext, comment: &dyn Comment) -> Value {
+	let author = comment.author();
+	json!({
+		"id": comment.id(),
+		"author": author.name(),
+		"author_link": author.link(),
+		"avatar_url": author.avatar_url(""),
+		"time": comment.time().to_rfc3339(),
+		"reply_to": comment.reply_to(),
+		"text_html": ctx.render_helper.nl2br(comment.text()),
+		"children": comment
+			.children()
+			.iter()
+			.map(|c| comment_json(ctx, c.as_ref()))
+			.collect::<Vec<Value>>(),
+	})
+}
+
+/// Without credentials of the visitor, see `Request::without_credentials`
+fn request_json(req: &Request) -> Value {
+	let req = req.without_credentials();
+	json!({
+		"method": req.method,
+		"path": req.path,
+		"params": req.params,
+		"query": req.query,
+		"headers": req.headers,
+		"body": String::from_utf8_lossy(&req.body),
+		"ip": req.ip,
+		"user_id": req.user_id,
+		"csrf_field_name": req.csrf_field_name,
+		"csrf_token": req.csrf_token,
+	})
+}
+
+/// Body is a string in JSON, binary responses are not supported
+#[derive(Deserialize)]
+struct ResponseJson {
+	status: u16,
+	#[serde(default)]
+	headers: Vec<(String, String)>,
+	#[serde(default)]
+	body: String,
+}
+impl From<ResponseJson> for Response {
+	fn from(r: ResponseJson) -> Self {
+		Response {
+			status: r.status,
+			headers: r.headers,
+			body: r.body.into_bytes(),
+		}
+	}
+}
+
+#[derive(Deserialize)]
+struct CommentFilterOutput {
+	comment: Option<CommentDraft>,
+	/// Message if the comment is rejected
+	reject: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct InitOutput {
+	error: Option<String>,
+}
+
+impl PluginMetadata for WasmModule {
+	fn plugin_version(&self) -> u32 {
+		match self.r#type {
+			PluginType::Theme => THEME_TRAIT_VERSION,
+			PluginType::Plugin => PLUGIN_TRAIT_VERSION,
+		}
+	}
+	fn name(&self) -> &str {
+		&self.name
+	}
+	fn description(&self) -> &str {
+		&self.description
+	}
+	fn version(&self) -> &str {
+		&self.version
+	}
+	fn r#type(&self) -> PluginType {
+		self.r#type
+	}
+	fn settings_schema(&self) -> Vec<SettingField> {
+		self.settings.to_owned()
+	}
+}
+
+impl Plugin for WasmModule {
+	fn priority(&self) -> i32 {
+		self.priority
+	}
+	/// The module has no file access, so `data_dir` is not passed
+	fn init(&self, ctx: &PluginContext) -> Result<(), String> {
+		let output: Option<InitOutput> = self
+			.call("init", json!({ "upload_route": ctx.upload_route, "is_prod": ctx.is_prod }))?;
+		match output.and_then(|o| o.error) {
+			Some(e) => Err(e),
+			None => Ok(()),
+		}
+	}
+	fn shutdown(&self) {
+		let _: Option<Value> = self.call("shutdown", json!({})).unwrap_or(None);
+	}
+	fn settings_changed(&self, settings: &Settings) {
+		let _: Option<Value> = self.call_plugin("settings_changed", json!(settings.values()));
+	}
+
+	fn filter_content_before_save(&self, content: &mut ContentDraft) {
+		if let Some(changed) = self.call_plugin("filter_content_before_save", json!(content)) {
+			*content = changed;
+		}
+	}
+	fn filter_content_before_render(&self, content_id: i32, content: &mut String) {
+		let arg = json!({ "content_id": content_id, "content": content });
+		if let Some(changed) = self.call_plugin("filter_content_before_render", arg) {
+			*content = changed;
+		}
+	}
+	fn filter_comment(&self, comment: &mut CommentDraft) -> Result<(), String> {
+		let output: Option<CommentFilterOutput> =
+			self.call_plugin("filter_comment", json!(comment));
+		if let Some(output) = output {
+			if let Some(changed) = output.comment {
+				*comment = changed;
+			}
+			if let Some(reason) = output.reject {
+				return Err(reason);
+			}
+		}
+		Ok(())
+	}
+
+	fn on_publish(&self, id: i32, content: &ContentDraft) {
+		let _: Option<Value> = self.call_plugin("on_publish", json!({ "id": id, "content": content }));
+	}
+	fn on_comment(&self, id: i32, comment: &CommentDraft) {
+		let _: Option<Value> = self.call_plugin("on_comment", json!({ "id": id, "comment": comment }));
+	}
+
+	fn inject(&self, point: InjectionPoint) -> Option<String> {
+		self.call_plugin::<Option<String>>("inject", json!({ "point": point }))
+			.unwrap_or(None)
+	}
+
+	fn routes(&self) -> Vec<Route> {
+		self.routes.clone()
+	}
+	fn handle(&self, route: &str, req: &Request) -> Response {
+		let arg = json!({ "route": route, "request": request_json(req) });
+		self.call_plugin::<ResponseJson>("handle", arg)
+			.map(|r| Response::from(r).sanitize(&req.csrf_token))
+			.unwrap_or_else(Response::not_found)
+	}
+
+	fn admin_pages(&self) -> Vec<AdminPage> {
+		self.admin_pages.clone()
+	}
+	fn admin_page(&self, slug: &str, req: &Request) -> Response {
+		let arg = json!({ "slug": slug, "request": request_json(req) });
+		self.call_plugin::<ResponseJson>("admin_page", arg)
+			.map(|r| Response::from(r).sanitize(&req.csrf_token))
+			.unwrap_or_else(Response::not_found)
+	}
+}
+
+impl Theme for WasmModule {
+	fn identity(&self) -> &str {
+		&self.identity
+	}
+	fn post_list(
+		&self,
+		out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		title: &str,
+		page: Page,
+		posts: Vec<Box<dyn Content>>,
+	) -> io::Result<()> {
+		let arg = json!({
+			"ctx": self.context_json(ctx),
+			"title": title,
+			"page": { "current": page.current, "total": page.total },
+			"posts": posts.iter().map(|p| content_json(ctx, p.as_ref())).collect::<Vec<Value>>(),
+		});
+		self.render(out, ctx, "post_list", arg)
+	}
+	fn post_show(
+		&self,
+		out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		title: &str,
+		post: Box<dyn Content>,
+		previous_author: Option<Box<dyn Author>>,
+	) -> io::Result<()> {
+		let arg = json!({
+			"ctx": self.context_json(ctx),
+			"title": title,
+			"post": content_json(ctx, post.as_ref()),
+			"comments": post
+				.get_parent_comments()
+				.iter()
+				.map(|c| comment_json(ctx, c.as_ref()))
+				.collect::<Vec<Value>>(),
+			"previous_author": previous_author.map(|a| json!({
+				"name": a.name(),
+				"mail": a.mail(),
+				"link": a.link(),
+			})),
+		});
+		self.render(out, ctx, "post_show", arg)
+	}
+	fn static_file(&self, name: &str) -> Option<Box<dyn StaticFile>> {
+		self.static_files
+			.get(name)
+			.map(|f| Box::new(f.clone()) as Box<dyn StaticFile>)
+	}
+	fn page_show(
+		&self,
+		out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		title: &str,
+		page: Box<dyn Content>,
+		previous_author: Option<Box<dyn Author>>,
+	) -> io::Result<()> {
+		let arg = json!({
+			"ctx": self.context_json(ctx),
+			"title": title,
+			"post": content_json(ctx, page.as_ref()),
+			"comments": page
+				.get_parent_comments()
+				.iter()
+				.map(|c| comment_json(ctx, c.as_ref()))
+				.collect::<Vec<Value>>(),
+			"previous_author": previous_author.as_ref().map(|a| json!({
+				"name": a.name(),
+				"mail": a.mail(),
+				"link": a.link(),
+			})),
+		});
+		self.render_or(out, ctx, "page_show", arg, |out| {
+			ctx.builtin_theme.page_show(out, ctx, title, page, previous_author)
+		})
+	}
+	fn error_page(
+		&self,
+		out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		status: u16,
+		message: &str,
+	) -> io::Result<()> {
+		let arg = json!({
+			"ctx": self.context_json(ctx),
+			"status": status,
+			"message": message,
+		});
+		self.render_or(out, ctx, "error_page", arg, |out| {
+			ctx.builtin_theme.error_page(out, ctx, status, message)
+		})
+	}
+	fn search_result(
+		&self,
+		out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		query: &str,
+		page: Page,
+		posts: Vec<Box<dyn Content>>,
+	) -> io::Result<()> {
+		let arg = json!({
+			"ctx": self.context_json(ctx),
+			"query": query,
+			"page": { "current": page.current, "total": page.total },
+			"posts": posts.iter().map(|p| content_json(ctx, p.as_ref())).collect::<Vec<Value>>(),
+		});
+		self.render_or(out, ctx, "search_result", arg, |out| {
+			ctx.builtin_theme.search_result(out, ctx, query, page, posts)
+		})
+	}
+	fn archive(
+		&self,
+		out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		archive: &Archive,
+		page: Page,
+		posts: Vec<Box<dyn Content>>,
+	) -> io::Result<()> {
+		let archive_json = match archive {
+			Archive::Month { year, month } => json!({
+				"kind": "month",
+				"year": year,
+				"month": month,
+			}),
+			Archive::Category(c) => json!({
+				"kind": "category",
+				"name": c.name(),
+				"slug": c.slug(),
+			}),
+			Archive::Tag(name) => json!({ "kind": "tag", "name": name }),
+		};
+		let arg = json!({
+			"ctx": self.context_json(ctx),
+			"archive": archive_json,
+			"page": { "current": page.current, "total": page.total },
+			"posts": posts.iter().map(|p| content_json(ctx, p.as_ref())).collect::<Vec<Value>>(),
+		});
+		self.render_or(out, ctx, "archive", arg, |out| {
+			ctx.builtin_theme.archive(out, ctx, archive, page, posts)
+		})
+	}
+	fn password_form(
+		&self,
+		out: &mut dyn io::Write,
+		ctx: &TemplateContext,
+		title: &str,
+		action: &str,
+		wrong_password: bool,
+	) -> io::Result<()> {
+		let arg = json!({
+			"ctx": self.context_json(ctx),
+			"title": title,
+			"action": action,
+			"wrong_password": wrong_password,
+		});
+		self.render_or(out, ctx, "password_form", arg, |out| {
+			ctx.builtin_theme.password_form(out, ctx, title, action, wrong_password)
+		})
+	}
+}
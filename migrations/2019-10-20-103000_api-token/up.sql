@@ -0,0 +1,17 @@
+CREATE TABLE public."api_token" (
+	"id" serial NOT NULL,
+	"user" int4 NOT NULL,
+	"name" varchar(200) NOT NULL,
+	"token_hash" varchar(64) NOT NULL,
+	"scope" int4 NOT NULL DEFAULT 0,
+	"created_at" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	"expires_at" timestamptz NULL,
+	"last_used_at" timestamptz NULL,
+	"last_used_ip" inet NULL,
+	CONSTRAINT "pk_api_token" PRIMARY KEY ("id"),
+	CONSTRAINT "uk_api_token__token_hash" UNIQUE ("token_hash"),
+	CONSTRAINT "fk_api_token__user" FOREIGN KEY ("user") REFERENCES "user"("id") ON DELETE CASCADE
+);
+CREATE INDEX "idx_api_token__user" ON public."api_token" USING btree ("user");
+COMMENT ON COLUMN public."api_token"."token_hash" IS 'Hex encoded SHA-256 of the token, the token itself is only shown once';
+COMMENT ON COLUMN public."api_token"."scope" IS 'Permission bits granted to this token, a subset of `user.permission`';
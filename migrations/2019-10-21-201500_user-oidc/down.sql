@@ -0,0 +1,2 @@
+DROP INDEX "idx_user__oidc_subject";
+ALTER TABLE public."user" DROP COLUMN "oidc_subject";
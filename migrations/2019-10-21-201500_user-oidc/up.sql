@@ -0,0 +1,3 @@
+ALTER TABLE public."user" ADD COLUMN "oidc_subject" varchar(255) NULL;
+CREATE UNIQUE INDEX "idx_user__oidc_subject" ON public."user" USING btree ("oidc_subject");
+COMMENT ON COLUMN public."user"."oidc_subject" IS 'Subject (`sub` claim) of the linked OpenID Connect identity';
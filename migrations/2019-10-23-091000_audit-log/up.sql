@@ -0,0 +1,18 @@
+CREATE TABLE public."audit_log" (
+	"id" serial NOT NULL,
+	"user" int4 NULL,
+	"ip" inet NULL,
+	"action" varchar(64) NOT NULL,
+	"target_type" varchar(64) NULL,
+	"target_id" int4 NULL,
+	"detail" text NULL,
+	"time" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	CONSTRAINT "pk_audit_log" PRIMARY KEY ("id"),
+	CONSTRAINT "fk_audit_log__user" FOREIGN KEY ("user") REFERENCES "user"("id") ON DELETE SET NULL
+);
+CREATE INDEX "idx_audit_log__user" ON public."audit_log" USING btree ("user");
+CREATE INDEX "idx_audit_log__action" ON public."audit_log" USING btree ("action");
+CREATE INDEX "idx_audit_log__target" ON public."audit_log" USING btree ("target_type", "target_id");
+CREATE INDEX "idx_audit_log__time" ON public."audit_log" USING btree ("time");
+COMMENT ON COLUMN public."audit_log"."user" IS 'Actor, NULL for anonymous actions like failed logins';
+COMMENT ON COLUMN public."audit_log"."detail" IS 'JSON like `{"before": ..., "after": ...}`';
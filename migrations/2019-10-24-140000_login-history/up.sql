@@ -0,0 +1,16 @@
+CREATE TABLE public."login_history" (
+	"id" serial NOT NULL,
+	"user" int4 NULL,
+	"username" varchar(64) NULL,
+	"ip" inet NULL,
+	"user_agent" text NULL,
+	"result" int4 NOT NULL,
+	"method" int4 NOT NULL,
+	"new_network" bool NOT NULL DEFAULT false,
+	"time" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	CONSTRAINT "pk_login_history" PRIMARY KEY ("id"),
+	CONSTRAINT "fk_login_history__user" FOREIGN KEY ("user") REFERENCES "user"("id") ON DELETE CASCADE
+);
+CREATE INDEX "idx_login_history__user" ON public."login_history" USING btree ("user", "time");
+COMMENT ON COLUMN public."login_history"."username" IS 'Username tried, for failed logins of unknown users';
+COMMENT ON COLUMN public."login_history"."new_network" IS 'No successful login from the same network before';
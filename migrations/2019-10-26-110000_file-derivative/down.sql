@@ -0,0 +1,3 @@
+DROP TABLE "file_derivative" CASCADE;
+ALTER TABLE public."file" DROP COLUMN "width";
+ALTER TABLE public."file" DROP COLUMN "height";
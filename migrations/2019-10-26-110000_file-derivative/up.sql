@@ -0,0 +1,19 @@
+ALTER TABLE public."file" ADD COLUMN "width" int4 NULL;
+ALTER TABLE public."file" ADD COLUMN "height" int4 NULL;
+COMMENT ON COLUMN public."file"."width" IS 'Image width in pixels, NULL if not an image';
+COMMENT ON COLUMN public."file"."height" IS 'Image height in pixels, NULL if not an image';
+
+CREATE TABLE public."file_derivative" (
+	"id" serial NOT NULL,
+	"file" int4 NOT NULL,
+	"key" varchar(500) NOT NULL,
+	"width" int4 NOT NULL,
+	"height" int4 NOT NULL,
+	"mime" varchar(64) NOT NULL,
+	"time" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	CONSTRAINT "pk_file_derivative" PRIMARY KEY ("id"),
+	CONSTRAINT "uk_file_derivative__key" UNIQUE ("key"),
+	CONSTRAINT "fk_file_derivative__file" FOREIGN KEY ("file") REFERENCES "file"("id") ON DELETE CASCADE
+);
+CREATE INDEX "idx_file_derivative__file" ON public."file_derivative" USING btree ("file");
+COMMENT ON TABLE public."file_derivative" IS 'Resized copies of uploaded images';
@@ -0,0 +1,2 @@
+ALTER TABLE public."file" DROP COLUMN "mime";
+ALTER TABLE public."file" DROP COLUMN "size";
@@ -0,0 +1,4 @@
+ALTER TABLE public."file" ADD COLUMN "mime" varchar(255) NOT NULL DEFAULT 'application/octet-stream';
+ALTER TABLE public."file" ADD COLUMN "size" int8 NOT NULL DEFAULT 0;
+COMMENT ON COLUMN public."file"."mime" IS 'MIME type sniffed from content when uploading';
+COMMENT ON COLUMN public."file"."size" IS 'Size in bytes, 0 for files uploaded before it was recorded';
@@ -0,0 +1,6 @@
+DROP INDEX "idx_file_derivative__key";
+ALTER TABLE public."file_derivative" ADD CONSTRAINT "uk_file_derivative__key" UNIQUE ("key");
+ALTER TABLE public."file" ADD CONSTRAINT "uk_file__key" UNIQUE ("key");
+DROP INDEX "idx_file__hash";
+ALTER TABLE public."file" DROP COLUMN "hash";
+DROP TABLE "blob" CASCADE;
@@ -0,0 +1,20 @@
+CREATE TABLE public."blob" (
+	"id" serial NOT NULL,
+	"hash" varchar(64) NOT NULL,
+	"key" varchar(500) NOT NULL,
+	"ref_count" int4 NOT NULL DEFAULT 1,
+	"time" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	CONSTRAINT "pk_blob" PRIMARY KEY ("id"),
+	CONSTRAINT "uk_blob__hash" UNIQUE ("hash")
+);
+COMMENT ON TABLE public."blob" IS 'Stored objects shared by files with identical content';
+COMMENT ON COLUMN public."blob"."hash" IS 'SHA-256 of stored bytes, hex encoded';
+COMMENT ON COLUMN public."blob"."ref_count" IS 'Number of `file` rows using this object';
+
+ALTER TABLE public."file" ADD COLUMN "hash" varchar(64) NULL;
+CREATE INDEX "idx_file__hash" ON public."file" USING btree ("hash");
+COMMENT ON COLUMN public."file"."hash" IS 'SHA-256 of stored bytes, NULL for files uploaded before deduplication';
+-- files with identical content share the same key
+ALTER TABLE public."file" DROP CONSTRAINT "uk_file__key";
+ALTER TABLE public."file_derivative" DROP CONSTRAINT "uk_file_derivative__key";
+CREATE INDEX "idx_file_derivative__key" ON public."file_derivative" USING btree ("key");
@@ -0,0 +1,2 @@
+ALTER TABLE public."content" DROP CONSTRAINT "fk_content__featured_file";
+ALTER TABLE public."content" DROP COLUMN "featured_file";
@@ -0,0 +1,3 @@
+ALTER TABLE public."content" ADD COLUMN "featured_file" int4 NULL;
+ALTER TABLE public."content" ADD CONSTRAINT "fk_content__featured_file" FOREIGN KEY ("featured_file") REFERENCES "file"("id") ON DELETE SET NULL;
+COMMENT ON COLUMN public."content"."featured_file" IS 'Cover image shown by themes, one of the attachments';
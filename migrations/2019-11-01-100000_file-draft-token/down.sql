@@ -0,0 +1,2 @@
+DROP INDEX public."idx_file__draft_token";
+ALTER TABLE public."file" DROP COLUMN "draft_token";
@@ -0,0 +1,3 @@
+ALTER TABLE public."file" ADD COLUMN "draft_token" varchar(64) NULL;
+CREATE INDEX "idx_file__draft_token" ON public."file" USING btree ("draft_token");
+COMMENT ON COLUMN public."file"."draft_token" IS 'Token of the unsaved post this file was uploaded for, cleared when the post is saved';
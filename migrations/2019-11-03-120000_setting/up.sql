@@ -0,0 +1,7 @@
+CREATE TABLE public."setting" (
+	"key" varchar(200) NOT NULL,
+	"value" text NOT NULL,
+	"modified_at" timestamptz NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	CONSTRAINT "pk_setting" PRIMARY KEY ("key")
+);
+COMMENT ON TABLE public."setting" IS 'Site settings changed in admin panel, which take effect without restart';
@@ -0,0 +1 @@
+ALTER TABLE public."file_derivative" DROP CONSTRAINT "uk_file_derivative__file_width_mime";
@@ -0,0 +1,4 @@
+-- copies shared by `share_derivatives` twice are kept once
+DELETE FROM public."file_derivative" a USING public."file_derivative" b
+	WHERE a."file" = b."file" AND a."width" = b."width" AND a."mime" = b."mime" AND a."id" > b."id";
+ALTER TABLE public."file_derivative" ADD CONSTRAINT "uk_file_derivative__file_width_mime" UNIQUE ("file", "width", "mime");
@@ -0,0 +1,217 @@
+//! Serving of embedded static files, with cache headers and compression.
+//!
+//! Compressed bodies are generated on first request and kept in `AssetCache`, keyed by a hash
+//! of content, so a reloaded theme never gets compressed data of its previous build.
+
+use brotli::enc::BrotliEncoderParams;
+use flate2::{write::GzEncoder, Compression};
+use mime::Mime;
+use rocket::{
+	http::{ContentType, Status},
+	request::Request,
+	response::{self, Responder, Response},
+};
+use std::{
+	collections::HashMap,
+	io::{Cursor, Write},
+	sync::{Arc, RwLock},
+};
+
+/// For files whose URL changes with content
+const CACHE_CONTROL_IMMUTABLE: &str = "public, max-age=31536000, immutable";
+/// For files which may change under the same URL, browsers revalidate with `ETag`
+const CACHE_CONTROL_REVALIDATE: &str = "public, no-cache";
+
+/// Smaller files are not worth compressing
+const MIN_COMPRESS_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+	Identity,
+	Gzip,
+	Brotli,
+}
+impl Encoding {
+	/// Picks the best encoding accepted in `Accept-Encoding`, brotli is preferred if weighted equal
+	pub fn negotiate(accept: Option<&str>) -> Self {
+		let accept = match accept {
+			Some(s) => s,
+			None => return Encoding::Identity,
+		};
+		let (mut gzip, mut brotli, mut any) = (None, None, None);
+		for item in accept.split(',') {
+			let mut parts = item.split(';');
+			let name = parts.next().unwrap_or("").trim().to_lowercase();
+			let q = parts
+				.filter_map(|p| {
+					let p = p.trim();
+					if p.starts_with("q=") {
+						p[2..].parse::<f32>().ok()
+					} else {
+						None
+					}
+				})
+				.next()
+				.unwrap_or(1.0);
+			match name.as_str() {
+				"gzip" | "x-gzip" => gzip = Some(q),
+				"br" => brotli = Some(q),
+				"*" => any = Some(q),
+				_ => (),
+			}
+		}
+		let brotli = brotli.or(any).unwrap_or(0.0);
+		let gzip = gzip.or(any).unwrap_or(0.0);
+		if brotli > 0.0 && brotli >= gzip {
+			Encoding::Brotli
+		} else if gzip > 0.0 {
+			Encoding::Gzip
+		} else {
+			Encoding::Identity
+		}
+	}
+
+	fn name(self) -> Option<&'static str> {
+		match self {
+			Encoding::Identity => None,
+			Encoding::Gzip => Some("gzip"),
+			Encoding::Brotli => Some("br"),
+		}
+	}
+}
+
+/// Text-like types, images and archives are compressed already
+fn is_compressible(mime: &Mime) -> bool {
+	let sub = mime.subtype().as_str();
+	let suffix = mime.suffix().map(|s| s.as_str());
+	mime.type_() == mime::TEXT
+		|| ["javascript", "x-javascript", "json", "xml", "wasm"].contains(&sub)
+		|| suffix == Some("xml")
+		|| suffix == Some("json")
+}
+
+fn compress(content: &[u8], encoding: Encoding) -> std::io::Result<Vec<u8>> {
+	match encoding {
+		Encoding::Identity => Ok(content.to_vec()),
+		Encoding::Gzip => {
+			let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+			encoder.write_all(content)?;
+			encoder.finish()
+		}
+		Encoding::Brotli => {
+			let mut params = BrotliEncoderParams::default();
+			params.quality = 11;
+			let mut out = Vec::new();
+			brotli::BrotliCompress(&mut Cursor::new(content), &mut out, &params)?;
+			Ok(out)
+		}
+	}
+}
+
+/// Bytes shared with the cache, so a response doesn't copy them
+struct SharedBytes(Arc<Vec<u8>>);
+impl AsRef<[u8]> for SharedBytes {
+	fn as_ref(&self) -> &[u8] {
+		&self.0
+	}
+}
+
+/// Compressed variants of static files, keyed by `StaticAsset::cache_key`
+#[derive(Default)]
+pub struct AssetCache {
+	variants: RwLock<HashMap<(String, Encoding), Arc<Vec<u8>>>>,
+}
+impl AssetCache {
+	/// Compressed `content`, `None` if compressing failed
+	fn get(&self, key: &str, content: &[u8], encoding: Encoding) -> Option<Arc<Vec<u8>>> {
+		let key = (key.to_owned(), encoding);
+		if let Some(data) = self.variants.read().ok()?.get(&key) {
+			return Some(data.clone());
+		}
+		let data = match compress(content, encoding) {
+			Ok(data) => Arc::new(data),
+			Err(e) => {
+				println!("Failed to compress static file: {}", e);
+				return None;
+			}
+		};
+		self.variants.write().ok()?.insert(key, data.clone());
+		Some(data)
+	}
+}
+
+/// Files of theme plugins are copied, since hot reload may unload the library before
+/// the response is sent
+pub enum AssetContent {
+	Embedded(&'static [u8]),
+	Owned(Vec<u8>),
+}
+impl AsRef<[u8]> for AssetContent {
+	fn as_ref(&self) -> &[u8] {
+		match self {
+			AssetContent::Embedded(content) => content,
+			AssetContent::Owned(content) => content,
+		}
+	}
+}
+
+/// A static file as response
+pub struct StaticAsset<'r> {
+	pub content: AssetContent,
+	pub mime: Mime,
+	/// Quoted `ETag`
+	pub etag: String,
+	/// Whether the URL contains a fingerprint of content
+	pub immutable: bool,
+	/// Theme, name and hash of content, see `AssetCache`
+	pub cache_key: String,
+	pub cache: &'r AssetCache,
+}
+impl<'r> Responder<'r> for StaticAsset<'r> {
+	fn respond_to(self, req: &Request) -> response::Result<'r> {
+		let mut response = Response::build();
+		response.raw_header("ETag", self.etag.to_owned()).raw_header(
+			"Cache-Control",
+			if self.immutable {
+				CACHE_CONTROL_IMMUTABLE
+			} else {
+				CACHE_CONTROL_REVALIDATE
+			},
+		);
+		let compressible = is_compressible(&self.mime);
+		if compressible {
+			response.raw_header("Vary", "Accept-Encoding");
+		}
+		let not_modified = req
+			.headers()
+			.get_one("If-None-Match")
+			.map(|tags| {
+				tags.split(',')
+					.map(|t| t.trim())
+					.any(|t| t == "*" || t.trim_start_matches("W/") == self.etag)
+			})
+			.unwrap_or(false);
+		if not_modified {
+			return response.status(Status::NotModified).ok();
+		}
+
+		response.header(ContentType::new(
+			self.mime.type_().as_str().to_owned(),
+			self.mime.subtype().as_str().to_owned(),
+		));
+		let encoding = if compressible && self.content.as_ref().len() >= MIN_COMPRESS_SIZE {
+			Encoding::negotiate(req.headers().get_one("Accept-Encoding"))
+		} else {
+			Encoding::Identity
+		};
+		if let Some(name) = encoding.name() {
+			if let Some(data) = self.cache.get(&self.cache_key, self.content.as_ref(), encoding) {
+				return response
+					.raw_header("Content-Encoding", name)
+					.sized_body(Cursor::new(SharedBytes(data)))
+					.ok();
+			}
+		}
+		response.sized_body(Cursor::new(self.content)).ok()
+	}
+}
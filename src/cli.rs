@@ -0,0 +1,375 @@
+//! Command line interface for maintenance tasks, e.g. creating the first admin.
+//!
+//! Uses the same `DATABASE_URL` as the server, except `theme` which needs no database.
+
+use crate::{
+	db::Database,
+	media,
+	models::{
+		self,
+		blob::Blob,
+		file::File,
+		file_derivative::FileDerivative,
+		user::{self, User},
+	},
+	storage::{self, StorageManager},
+};
+#[cfg(feature = "theme-render")]
+use crate::render::{self, RenderFunctions};
+#[cfg(feature = "theme-render")]
+use sohablog_lib::{
+	fixture::{render_all, Fixture},
+	plugin::PluginManager,
+};
+use std::{
+	collections::HashMap,
+	env,
+	io::{self, BufRead, Write},
+};
+#[cfg(feature = "theme-render")]
+use std::path::Path;
+
+const USAGE: &str = "Usage:
+    sohablog [serve]                                   Start the server
+    sohablog user list                                 List all users
+    sohablog user create <username> <email> [--name <name>] [--role <role>] [--password <password>]
+    sohablog user passwd <username> [--password <password>]
+    sohablog user set-role <username> <role>
+    sohablog storage migrate <from> <to> [--keep true]   Move uploaded files between backends
+    sohablog file gc [--delete true] [--min-age-hours <hours>]   List (or delete) files not used by any post
+    sohablog theme render <fixture> <out_dir> [--file <theme>]   Render every page of a theme with fixture data
+
+<role> is one of `admin`, `editor`, `author`, `none`, or a raw permission number.
+Password is read from stdin if `--password` is absent.
+Storage backends are `local`, and `s3` if `SOHABLOG_S3_BUCKET` is set. Source files are deleted after moving unless `--keep true`.
+Files uploaded within `--min-age-hours` (24 by default) are never collected.
+Fixtures are `.json` or `.toml` files, see `fixture.example.toml`. The built-in theme is rendered without `--file`.
+`theme render` needs a build with the `theme-render` feature.";
+
+#[derive(Debug)]
+pub enum Error {
+	Usage,
+	Invalid(String),
+	Model(models::Error),
+	Storage(storage::Error),
+	Media(media::Error),
+	Io(io::Error),
+}
+impl From<models::Error> for Error {
+	fn from(e: models::Error) -> Self {
+		Error::Model(e)
+	}
+}
+impl From<storage::Error> for Error {
+	fn from(e: storage::Error) -> Self {
+		Error::Storage(e)
+	}
+}
+impl From<media::Error> for Error {
+	fn from(e: media::Error) -> Self {
+		Error::Media(e)
+	}
+}
+impl From<io::Error> for Error {
+	fn from(e: io::Error) -> Self {
+		Error::Io(e)
+	}
+}
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Splits `--key value` options from positional arguments
+fn parse_options(args: &[String]) -> Result<(Vec<&str>, Vec<(&str, &str)>)> {
+	let mut positional = Vec::new();
+	let mut options = Vec::new();
+	let mut iter = args.iter();
+	while let Some(arg) = iter.next() {
+		if arg.starts_with("--") {
+			let value = iter.next().ok_or(Error::Usage)?;
+			options.push((&arg[2..], value.as_str()));
+		} else {
+			positional.push(arg.as_str());
+		}
+	}
+	Ok((positional, options))
+}
+
+fn option<'a>(options: &[(&str, &'a str)], key: &str) -> Option<&'a str> {
+	options.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+fn parse_role(s: &str) -> Result<i32> {
+	user::ROLES
+		.iter()
+		.find(|(name, _)| *name == s)
+		.map(|(_, perm)| *perm)
+		.or_else(|| s.parse::<i32>().ok())
+		.ok_or_else(|| Error::Invalid(format!("Unknown role `{}`", s)))
+}
+
+fn role_name(permission: i32) -> String {
+	user::ROLES
+		.iter()
+		.find(|(_, perm)| *perm == permission)
+		.map(|(name, _)| name.to_string())
+		.unwrap_or_else(|| permission.to_string())
+}
+
+fn read_password(given: Option<&str>) -> Result<String> {
+	let password = match given {
+		Some(p) => p.to_owned(),
+		None => {
+			print!("Password: ");
+			io::stdout().flush()?;
+			let mut line = String::new();
+			io::stdin().lock().read_line(&mut line)?;
+			line.trim_end_matches(|c| c == '\r' || c == '\n').to_owned()
+		}
+	};
+	if password.len() < 6 {
+		return Err(Error::Invalid(String::from(
+			"Password should be at least 6 characters",
+		)));
+	}
+	Ok(password)
+}
+
+fn user_command(db: &Database, args: &[String]) -> Result<()> {
+	let (positional, options) = parse_options(args)?;
+	match positional.as_slice() {
+		["list"] => {
+			println!("{:>6}  {:<24} {:<32} {}", "ID", "USERNAME", "EMAIL", "ROLE");
+			for u in User::find_all(db)? {
+				println!(
+					"{:>6}  {:<24} {:<32} {}",
+					u.id,
+					u.username,
+					u.email,
+					role_name(u.permission)
+				);
+			}
+		}
+		["create", username, email] => {
+			let permission = parse_role(option(&options, "role").unwrap_or("admin"))?;
+			let password = read_password(option(&options, "password"))?;
+			let u = User::create(
+				db,
+				username,
+				email,
+				option(&options, "name").unwrap_or(username),
+				&password,
+				permission,
+			)?;
+			println!("User `{}` created with id {}", u.username, u.id);
+		}
+		["passwd", username] => {
+			let u = User::find_by_username(db, username)?;
+			let password = read_password(option(&options, "password"))?;
+			u.set_password_hash(db, &User::generate_password_hash(&password)?)?;
+			println!("Password of `{}` updated", u.username);
+		}
+		["set-role", username, role] => {
+			let u = User::find_by_username(db, username)?;
+			u.set_permission(db, parse_role(role)?)?;
+			println!("Role of `{}` set to `{}`", u.username, role);
+		}
+		_ => return Err(Error::Usage),
+	}
+	Ok(())
+}
+
+fn storage_manager() -> Result<StorageManager> {
+	Ok(StorageManager::from_env(
+		&env::var("SOHABLOG_UPLOAD_DIR").unwrap_or(String::from("upload/")),
+		&env::var("SOHABLOG_UPLOAD_ROUTE").unwrap_or(String::from("/static/upload")),
+	)?)
+}
+
+fn storage_command(db: &Database, args: &[String]) -> Result<()> {
+	let (positional, options) = parse_options(args)?;
+	match positional.as_slice() {
+		["migrate", from, to] => {
+			if from == to {
+				return Err(Error::Invalid(String::from("Source and target are the same")));
+			}
+			let manager = storage_manager()?;
+			let source = manager.backend(from)?;
+			manager.backend(to)?;
+			let keep = option(&options, "keep") == Some("true");
+			let (mut moved, mut failed) = (0, 0);
+			// files with the same content share objects, which are copied only once
+			let mut copied: HashMap<String, String> = HashMap::new();
+			for file in File::find_all(db)? {
+				let (backend, path) = StorageManager::parse_key(&file.key)?;
+				if backend != *from {
+					continue;
+				}
+				let result = (|| -> Result<String> {
+					for derivative in FileDerivative::find_by_file_id(db, file.id)? {
+						let key = match copied.get(&derivative.key) {
+							Some(key) => key.to_owned(),
+							None => {
+								let key = manager.copy_to(&derivative.key, to)?;
+								if !keep {
+									manager.delete(&derivative.key)?;
+								}
+								copied.insert(derivative.key.to_owned(), key.to_owned());
+								key
+							}
+						};
+						derivative.set_key(db, &key)?;
+					}
+					let key = match copied.get(&file.key) {
+						Some(key) => key.to_owned(),
+						None => {
+							let key = manager.copy_to(&file.key, to)?;
+							Blob::replace_key(db, &file.key, &key)?;
+							if !keep {
+								source.delete(path)?;
+							}
+							copied.insert(file.key.to_owned(), key.to_owned());
+							key
+						}
+					};
+					file.set_key(db, &key)?;
+					Ok(key)
+				})();
+				match result {
+					Ok(key) => {
+						println!("{} -> {}", file.key, key);
+						moved += 1;
+					}
+					Err(e) => {
+						eprintln!("Failed to move {}: {:?}", file.key, e);
+						failed += 1;
+					}
+				}
+			}
+			println!("{} files moved, {} failed", moved, failed);
+			if failed > 0 {
+				return Err(Error::Invalid(String::from(
+					"Some files were not moved, run again to retry",
+				)));
+			}
+		}
+		_ => return Err(Error::Usage),
+	}
+	Ok(())
+}
+
+fn file_command(db: &Database, args: &[String]) -> Result<()> {
+	let (positional, options) = parse_options(args)?;
+	match positional.as_slice() {
+		["gc"] => {
+			let min_age = match option(&options, "min-age-hours") {
+				Some(s) => s
+					.parse::<i64>()
+					.map_err(|_| Error::Invalid(format!("Invalid hours `{}`", s)))?,
+				None => 24,
+			};
+			let delete = option(&options, "delete") == Some("true");
+			let manager = storage_manager()?;
+			let orphans = media::find_orphans(db, chrono::Duration::hours(min_age))?;
+			for file in &orphans {
+				println!("{}\t{}\t{}\t{}", file.id, file.time, file.key, file.filename);
+				if delete {
+					media::remove_file(db, &manager, file)?;
+				}
+			}
+			if delete {
+				println!("{} files deleted", orphans.len());
+			} else {
+				println!(
+					"{} files are not used, run with `--delete true` to delete them",
+					orphans.len()
+				);
+			}
+		}
+		_ => return Err(Error::Usage),
+	}
+	Ok(())
+}
+
+#[cfg(feature = "theme-render")]
+fn theme_command(args: &[String]) -> Result<()> {
+	let (positional, options) = parse_options(args)?;
+	match positional.as_slice() {
+		["render", fixture, out_dir] => {
+			let fixture = Fixture::load(Path::new(fixture)).map_err(Error::Invalid)?;
+			let mut plugin_manager = PluginManager::new();
+			if let Some(file) = option(&options, "file") {
+				#[cfg(feature = "wasm-plugin")]
+				let result = match file.ends_with(".wasm") {
+					true => plugin_manager.load_wasm(file),
+					false => unsafe { plugin_manager.load(file) },
+				};
+				#[cfg(not(feature = "wasm-plugin"))]
+				let result = unsafe { plugin_manager.load(file) };
+				result.map_err(|e| Error::Invalid(format!("Failed to load `{}`: {}", file, e)))?;
+			}
+			let theme = plugin_manager.themes().into_iter().next();
+			if option(&options, "file").is_some() && theme.is_none() {
+				return Err(Error::Invalid(String::from("The file is not a theme")));
+			}
+			let written = render_all(
+				theme,
+				&render::theme::BUILTIN,
+				fixture,
+				Box::new(RenderFunctions::default()),
+				Path::new(out_dir),
+			)?;
+			for path in written {
+				println!("{}", path.display());
+			}
+		}
+		_ => return Err(Error::Usage),
+	}
+	Ok(())
+}
+
+#[cfg(not(feature = "theme-render"))]
+fn theme_command(_args: &[String]) -> Result<()> {
+	Err(Error::Invalid(String::from(
+		"Built without theme rendering, rebuild with `--features theme-render`",
+	)))
+}
+
+fn exit_code(result: Result<()>) -> i32 {
+	match result {
+		Ok(_) => 0,
+		Err(Error::Usage) => {
+			eprintln!("{}", USAGE);
+			2
+		}
+		Err(Error::Invalid(s)) => {
+			eprintln!("{}", s);
+			1
+		}
+		Err(e) => {
+			eprintln!("{:?}", e);
+			1
+		}
+	}
+}
+
+/// Runs the command in `args` (without program name), returns exit code
+pub fn run(args: &[String]) -> i32 {
+	let command: fn(&Database, &[String]) -> Result<()> = match args.first().map(|s| s.as_str()) {
+		Some("user") => user_command,
+		Some("storage") => storage_command,
+		Some("file") => file_command,
+		Some("theme") => return exit_code(theme_command(&args[1..])),
+		_ => {
+			eprintln!("{}", USAGE);
+			return 2;
+		}
+	};
+	let mut db = Database::new(&env::var("DATABASE_URL").unwrap());
+	let result = match db.init() {
+		Ok(_) => command(&db, &args[1..]),
+		Err(e) => Err(Error::Invalid(format!(
+			"Met an error while initializing database: {}",
+			e
+		))),
+	};
+	exit_code(result)
+}
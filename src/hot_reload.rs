@@ -0,0 +1,126 @@
+//! Reloads plugins and themes when files in `plugin_dir` change, only attached in development.
+
+use crate::{
+	db::Database,
+	models::setting::{PluginSettings, Setting},
+	plugin::SharedPluginManager,
+	util::*,
+};
+use rocket::{
+	fairing::{Fairing, Info as FairingInfo, Kind as FairingKind},
+	request::{Request, State},
+	Data,
+};
+use std::{
+	fs::read_dir,
+	panic::{self, AssertUnwindSafe},
+	path::PathBuf,
+	sync::Mutex,
+	time::{Duration, Instant, SystemTime},
+};
+
+/// Directory is checked at most this often, on incoming requests
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// Path, modified time and size of files in the directory
+type Snapshot = Vec<(PathBuf, SystemTime, u64)>;
+
+fn snapshot(dir: &str) -> Snapshot {
+	let mut files: Snapshot = match read_dir(dir) {
+		Ok(entries) => entries
+			.filter_map(|entry| {
+				let entry = entry.ok()?;
+				let metadata = entry.metadata().ok()?;
+				match metadata.is_file() {
+					true => Some((entry.path(), metadata.modified().ok()?, metadata.len())),
+					false => None,
+				}
+			})
+			.collect(),
+		Err(_) => Vec::new(),
+	};
+	files.sort();
+	files
+}
+
+struct WatchState {
+	last_check: Instant,
+	/// Files when plugins were loaded
+	loaded: Snapshot,
+	/// Files at the last check
+	seen: Snapshot,
+}
+
+pub struct PluginReloader(Mutex<WatchState>);
+impl PluginReloader {
+	pub fn new(plugin_dir: &str) -> Self {
+		let files = snapshot(plugin_dir);
+		Self(Mutex::new(WatchState {
+			last_check: Instant::now(),
+			loaded: files.clone(),
+			seen: files,
+		}))
+	}
+
+	/// Plugins are reloaded once files stop changing between two checks, so a library being
+	/// written is not loaded. Requests in progress hold the manager, reloading waits for a
+	/// check when none is.
+	fn check(&self, request: &Request) {
+		let mut state = self.0.lock().unwrap();
+		if state.last_check.elapsed() < INTERVAL {
+			return;
+		}
+		state.last_check = Instant::now();
+		let config = match request.guard::<State<SystemConfig>>().succeeded() {
+			Some(config) => config.inner(),
+			None => return,
+		};
+		let files = snapshot(&config.plugin_dir);
+		if files == state.loaded || files != state.seen {
+			state.seen = files;
+			return;
+		}
+		let shared = match request.guard::<State<SharedPluginManager>>().succeeded() {
+			Some(shared) => shared.inner(),
+			None => return,
+		};
+		let mut manager = match shared.try_write() {
+			Ok(manager) => manager,
+			Err(_) => return,
+		};
+		println!("Files in `{}` changed, reloading plugins", config.plugin_dir);
+		let db = request.guard::<State<Box<Database>>>().succeeded();
+		if let Some(db) = &db {
+			match Setting::disabled_plugins(db) {
+				Ok(disabled) => manager.set_disabled(disabled),
+				Err(e) => println!("Failed to load disabled plugins: {:?}", e),
+			}
+		}
+		match panic::catch_unwind(AssertUnwindSafe(|| manager.reload(config))) {
+			Ok(Ok(_)) => (),
+			Ok(Err(e)) => println!("Failed to read `{}`: {}", config.plugin_dir, e),
+			Err(_) => println!("Plugin panicked while reloading"),
+		}
+		state.loaded = files;
+
+		let settings = request.guard::<State<PluginSettings>>().succeeded();
+		if let (Some(db), Some(settings)) = (db, settings) {
+			match settings.reload(&db, &manager) {
+				Ok(_) => settings.notify(&manager),
+				Err(e) => println!("Failed to load plugin settings: {:?}", e),
+			}
+		}
+	}
+}
+impl Fairing for PluginReloader {
+	fn info(&self) -> FairingInfo {
+		FairingInfo {
+			name: "Plugin Hot Reload",
+			kind: FairingKind::Request,
+		}
+	}
+
+	fn on_request(&self, request: &mut Request, _: &Data) {
+		self.check(request);
+	}
+}
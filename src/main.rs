@@ -11,26 +11,38 @@
 #[macro_use]
 extern crate diesel;
 
-pub use sohablog_lib::{interfaces, plugin, types, utils};
+pub use sohablog_lib::{http, interfaces, plugin, types, utils};
 
+mod assets;
+mod cli;
 mod db;
+mod hot_reload;
+mod media;
 mod models;
+mod oidc;
 #[macro_use]
 mod render;
 mod routes;
 mod schema;
+mod storage;
+mod upload;
 mod util;
 
 fn main() {
 	use crate::db::Database;
 	use crate::routes as router;
 	use crate::util::*;
-	use rocket::{config::Config as RocketConfig, fairing::AdHoc, routes, http::Method};
-	use rocket_contrib::serve::StaticFiles;
+	use rocket::{catchers, config::Config as RocketConfig, fairing::AdHoc, routes, http::Method};
 	use sohablog_lib::plugin::PluginManager;
+	use std::sync::RwLock;
 	use std::env;
 
 	dotenv::dotenv().ok();
+	let args: Vec<String> = env::args().skip(1).collect();
+	if args.first().filter(|&s| s != "serve").is_some() {
+		std::process::exit(cli::run(&args));
+	}
+
 	let rocket_config = RocketConfig::active().unwrap();
 	let mut plugin_manager = PluginManager::new();
 
@@ -46,26 +58,89 @@ fn main() {
 		real_ip_header: env::var("SOHABLOG_REAL_IP_HEADER").ok(),
 		csrf_cookie_name: env::var("SOHABLOG_CSRF_COOKIE_NAME").ok(),
 		is_prod: rocket_config.environment.is_prod(),
-		theme_name: String::from("my-notebook"),
+		theme_name: String::from(render::theme::BUILTIN_NAME),
+		plugin_hot_reload: env::var("SOHABLOG_PLUGIN_HOT_RELOAD")
+			.map(|s| s == "1" || s == "true")
+			.unwrap_or(false),
+		site_url: env::var("SOHABLOG_SITE_URL").ok(),
 	};
 	
 	let robots_txt = util::RobotsTxt::new(get_robot_txt(&system_config.robots_txt_path));
 	std::fs::create_dir_all(&system_config.upload_dir).unwrap();
-	plugin_manager
-		.load_from_dir(&system_config.plugin_dir)
-		.unwrap();
+	let storage_manager =
+		storage::StorageManager::from_env(&system_config.upload_dir, &system_config.upload_route)
+			.unwrap();
+	#[cfg(feature = "wasm-plugin")]
+	{
+		let mut limits = sohablog_lib::wasm::WasmLimits::default();
+		if let Some(fuel) = env::var("SOHABLOG_WASM_FUEL").ok().and_then(|s| s.parse().ok()) {
+			limits.fuel = fuel;
+		}
+		if let Some(memory) = env::var("SOHABLOG_WASM_MEMORY").ok().and_then(|s| s.parse().ok()) {
+			limits.memory = memory;
+		}
+		if let Some(ms) = env::var("SOHABLOG_WASM_TIME_MS").ok().and_then(|s| s.parse().ok()) {
+			limits.time = std::time::Duration::from_millis(ms);
+		}
+		plugin_manager.set_wasm_limits(limits);
+	}
+	if system_config.plugin_hot_reload && system_config.is_prod {
+		println!("Plugin hot reload is meant for development, not production");
+	}
+	plugin_manager.set_hot_reload(system_config.plugin_hot_reload);
 
 	match db.init() {
 		Ok(_) => {
-			rocket::ignite()
+			use models::setting::Setting;
+			let mut disabled_plugins = Setting::disabled_plugins(&db).unwrap_or_else(|e| {
+				println!("Failed to load disabled plugins: {:?}", e);
+				Vec::new()
+			});
+			plugin_manager.set_disabled(disabled_plugins.clone());
+			if let Some(name) = plugin_manager.recover_from_crash(&system_config.plugin_dir) {
+				if !disabled_plugins.contains(&name) {
+					disabled_plugins.push(name);
+				}
+				if let Err(e) = Setting::set_disabled_plugins(&db, &disabled_plugins) {
+					println!("Failed to save disabled plugins: {:?}", e);
+				}
+			}
+			plugin_manager
+				.load_from_dir(&system_config.plugin_dir)
+				.unwrap();
+			plugin_manager.init_plugins(&system_config);
+
+			let active_theme = Setting::get(&db, models::setting::KEY_THEME)
+				.unwrap_or_else(|e| {
+					println!("Failed to load active theme: {:?}", e);
+					None
+				})
+				.unwrap_or_else(|| system_config.theme_name.to_owned());
+			let plugin_settings = models::setting::PluginSettings::load(&db, &plugin_manager)
+				.unwrap_or_else(|e| {
+					println!("Failed to load plugin settings: {:?}", e);
+					Default::default()
+				});
+			plugin_settings.notify(&plugin_manager);
+			let plugin_reloader = match system_config.plugin_hot_reload {
+				true => Some(hot_reload::PluginReloader::new(&system_config.plugin_dir)),
+				false => None,
+			};
+			let rocket = rocket::ignite()
 				.mount("/", routes![
 					router::root::index,
 					router::post::post_show,
 					router::root::page_show,
+					router::post::password,
+					router::archive::search,
+					router::archive::month,
+					router::archive::category,
+					router::archive::tag,
 					router::user::login_get,
 					router::user::login_post,
+					router::user::oidc_login,
+					router::user::oidc_callback,
 					router::comment::new_content_comment,
-					router::admin::root::generate_password_hash,
 					router::admin::root::index,
 					router::admin::post::list,
 					router::admin::post::new_get,
@@ -77,13 +152,37 @@ fn main() {
 					router::admin::category::update,
 					router::admin::file::upload,
 					router::admin::file::find_by_content,
-					router::admin::file::delete_by_id
+					router::admin::file::find_by_draft,
+					router::admin::file::delete_by_id,
+					router::admin::file::library,
+					router::admin::file::bulk_delete,
+					router::admin::file::relink,
+					router::admin::file::orphans,
+					router::admin::token::list,
+					router::admin::token::new,
+					router::admin::token::revoke,
+					router::admin::audit::list,
+					router::admin::audit::export,
+					router::admin::appearance::list,
+					router::admin::appearance::activate,
+					router::admin::appearance::preview,
+					router::admin::appearance::stop_preview,
+					router::admin::settings::list,
+					router::admin::settings::edit_get,
+					router::admin::settings::edit_post,
+					router::admin::plugin::list,
+					router::admin::plugin::set_enabled,
+					router::admin::plugin::page_get,
+					router::admin::plugin::page_post
+				])
+				.mount("/", router::plugin::PluginRoutes::routes())
+				.register(catchers![
+					router::error::forbidden,
+					router::error::not_found,
+					router::error::internal_error
 				])
 				.mount("/", vec![rocket::Route::new(Method::Get, "/robots.txt", robots_txt)])
-				.mount(
-					&system_config.upload_route,
-					StaticFiles::from(&system_config.upload_dir),
-				)
+				.mount(&system_config.upload_route, routes![router::upload::serve])
 				.mount(
 					"/static/",
 					routes!(router::static_file::system, router::static_file::theme),
@@ -97,8 +196,19 @@ fn main() {
 				.attach(CSRFTokenValidation(None))
 				.manage(Box::new(db))
 				.manage(system_config)
-				.manage(plugin_manager)
-				.launch();
+				.manage(RwLock::new(plugin_manager))
+				.manage(render::theme::ActiveTheme::new(active_theme))
+				.manage(plugin_settings)
+				.manage(storage_manager)
+				.manage(assets::AssetCache::default())
+				.manage(media::ImageConfig::from_env())
+				.manage(upload::UploadConfig::from_env())
+				.manage(oidc::OidcClient::from_env());
+			match plugin_reloader {
+				Some(reloader) => rocket.attach(reloader),
+				None => rocket,
+			}
+			.launch();
 		}
 		Err(e) => println!("Met an error while initializing database: {}", e),
 	};
@@ -0,0 +1,486 @@
+//! Processing and housekeeping of uploaded files.
+//!
+//! Metadata segments of JPEG and PNG uploads are removed without decoding pixels, so EXIF and
+//! other metadata (like GPS location) never reach storage, only the EXIF orientation is kept.
+//! Resized copies for `srcset` are decoded and generated in a background worker.
+//! Other formats (e.g. animated GIF) are kept as is.
+//!
+//! Identical bytes are stored once, see `models::blob::Blob`.
+
+use crate::{
+	db::Database,
+	models::{
+		self,
+		blob::Blob,
+		file::File,
+		file_derivative::{FileDerivative, NewFileDerivative},
+	},
+	storage::{self, StorageManager},
+};
+use chrono::{Duration, Utc};
+use data_encoding::HEXLOWER;
+use image::{
+	jpeg::JPEGDecoder, png::PNGDecoder, DynamicImage, FilterType, ImageDecoder, ImageFormat,
+	ImageOutputFormat,
+};
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+use std::{
+	env, fmt,
+	io::Cursor,
+	sync::{mpsc, Mutex},
+	thread,
+};
+
+#[derive(Debug)]
+pub enum Error {
+	Image(image::ImageError),
+	Storage(storage::Error),
+	Model(models::Error),
+	/// More pixels than `ImageConfig::max_pixels`
+	TooLarge,
+}
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::Image(e) => write!(f, "Image error: {}", e),
+			Error::Storage(e) => write!(f, "{}", e),
+			Error::Model(e) => write!(f, "{:?}", e),
+			Error::TooLarge => write!(f, "Image has too many pixels"),
+		}
+	}
+}
+impl From<image::ImageError> for Error {
+	fn from(e: image::ImageError) -> Self {
+		Error::Image(e)
+	}
+}
+impl From<storage::Error> for Error {
+	fn from(e: storage::Error) -> Self {
+		Error::Storage(e)
+	}
+}
+impl From<models::Error> for Error {
+	fn from(e: models::Error) -> Self {
+		Error::Model(e)
+	}
+}
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone)]
+pub struct ImageConfig {
+	/// Widths of derivatives, only those smaller than the original are generated
+	pub sizes: Vec<u32>,
+	/// Also generates WebP derivatives, needs `webp` feature
+	pub webp: bool,
+	pub jpeg_quality: u8,
+	/// Images with more pixels are rejected before decoding
+	pub max_pixels: u64,
+}
+impl ImageConfig {
+	pub fn from_env() -> Self {
+		Self {
+			sizes: env::var("SOHABLOG_IMAGE_SIZES")
+				.unwrap_or(String::from("320,640,1280"))
+				.split(',')
+				.filter_map(|s| s.trim().parse::<u32>().ok())
+				.filter(|&w| w > 0)
+				.collect(),
+			webp: cfg!(feature = "webp")
+				&& env::var("SOHABLOG_IMAGE_WEBP").map(|s| s == "true").unwrap_or(false),
+			jpeg_quality: env::var("SOHABLOG_IMAGE_JPEG_QUALITY")
+				.ok()
+				.and_then(|s| s.parse::<u8>().ok())
+				.filter(|&q| q > 0 && q <= 100)
+				.unwrap_or(85),
+			max_pixels: env::var("SOHABLOG_IMAGE_MAX_PIXELS")
+				.ok()
+				.and_then(|s| s.parse::<u64>().ok())
+				.unwrap_or(40_000_000),
+		}
+	}
+}
+
+/// An image ready for storing, pixels are not decoded yet
+pub struct Image {
+	pub format: ImageFormat,
+	/// Encoded image without metadata
+	pub data: Vec<u8>,
+	/// As shown, i.e. after EXIF orientation is applied
+	pub width: u32,
+	pub height: u32,
+}
+impl Image {
+	pub fn mime(&self) -> &'static str {
+		mime_of(self.format)
+	}
+
+	pub fn dimensions(&self) -> (u32, u32) {
+		(self.width, self.height)
+	}
+
+	/// Decodes pixels, rotated as EXIF says
+	fn decode(&self) -> Result<DynamicImage> {
+		let image = image::load_from_memory_with_format(&self.data, self.format)?;
+		Ok(match self.format {
+			ImageFormat::JPEG => apply_orientation(image, exif_orientation(&self.data)),
+			_ => image,
+		})
+	}
+}
+
+fn mime_of(format: ImageFormat) -> &'static str {
+	match format {
+		ImageFormat::JPEG => "image/jpeg",
+		_ => "image/png",
+	}
+}
+
+fn extension_of(format: ImageFormat) -> &'static str {
+	match format {
+		ImageFormat::JPEG => "jpg",
+		_ => "png",
+	}
+}
+
+/// Formats which are processed, others are stored untouched
+pub fn detect(data: &[u8]) -> Option<ImageFormat> {
+	match image::guess_format(data) {
+		Ok(format @ ImageFormat::JPEG) | Ok(format @ ImageFormat::PNG) => Some(format),
+		_ => None,
+	}
+}
+
+/// Value of EXIF `Orientation` tag, 1 if absent
+fn exif_orientation(data: &[u8]) -> u32 {
+	exif::Reader::new()
+		.read_from_container(&mut Cursor::new(data))
+		.ok()
+		.and_then(|exif| {
+			exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+				.and_then(|field| field.value.get_uint(0))
+		})
+		.unwrap_or(1)
+}
+
+/// Rotates pixels as EXIF says, since the tag is dropped when encoding
+fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+	match orientation {
+		2 => image.fliph(),
+		3 => image.rotate180(),
+		4 => image.flipv(),
+		5 => image.rotate90().fliph(),
+		6 => image.rotate90(),
+		7 => image.rotate270().fliph(),
+		8 => image.rotate270(),
+		_ => image,
+	}
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat, config: &ImageConfig) -> Result<Vec<u8>> {
+	let mut data = Vec::new();
+	let output = match format {
+		ImageFormat::JPEG => ImageOutputFormat::JPEG(config.jpeg_quality),
+		_ => ImageOutputFormat::PNG,
+	};
+	image.write_to(&mut data, output)?;
+	Ok(data)
+}
+
+#[cfg(feature = "webp")]
+fn encode_webp(image: &DynamicImage, config: &ImageConfig) -> Option<Vec<u8>> {
+	use image::GenericImageView;
+	let rgba = image.to_rgba();
+	let encoded = webp::Encoder::from_rgba(&rgba, image.width(), image.height())
+		.encode(f32::from(config.jpeg_quality));
+	Some(encoded.to_vec())
+}
+#[cfg(not(feature = "webp"))]
+fn encode_webp(_image: &DynamicImage, _config: &ImageConfig) -> Option<Vec<u8>> {
+	None
+}
+
+/// Read from the header, without decoding the image
+fn dimensions_of(data: &[u8], format: ImageFormat) -> Result<(u64, u64)> {
+	Ok(match format {
+		ImageFormat::JPEG => JPEGDecoder::new(Cursor::new(data))?.dimensions(),
+		_ => PNGDecoder::new(Cursor::new(data))?.dimensions(),
+	})
+}
+
+fn malformed(format: ImageFormat) -> Error {
+	Error::Image(image::ImageError::FormatError(format!("Malformed {:?}", format)))
+}
+
+/// `APP1` segment with an EXIF block holding only `orientation`
+fn jpeg_orientation_segment(orientation: u32) -> Vec<u8> {
+	let mut segment = vec![0xff, 0xe1, 0, 34];
+	segment.extend_from_slice(b"Exif\0\0MM\0\x2a\0\0\0\x08"); // big-endian TIFF, IFD at 8
+	segment.extend_from_slice(&[0, 1, 0x01, 0x12, 0, 3, 0, 0, 0, 1]); // one SHORT, `Orientation`
+	segment.extend_from_slice(&[0, orientation as u8, 0, 0, 0, 0, 0, 0]); // value, no next IFD
+	segment
+}
+
+/// Copies segments before the image data, except `APPn` and comments. `APP0` (JFIF), `APP14`
+/// (Adobe color transform) and ICC profiles are kept as they change how pixels look.
+fn strip_jpeg(data: &[u8]) -> Result<Vec<u8>> {
+	let orientation = exif_orientation(data);
+	let mut out = Vec::with_capacity(data.len());
+	out.extend_from_slice(&data[..2]); // SOI
+	let mut pos = 2;
+	let mut orientation_written = orientation == 1;
+	loop {
+		if pos + 4 > data.len() || data[pos] != 0xff {
+			return Err(malformed(ImageFormat::JPEG));
+		}
+		let marker = data[pos + 1];
+		if marker == 0xff {
+			pos += 1; // fill byte
+			continue;
+		}
+		if marker != 0xe0 && !orientation_written {
+			out.extend_from_slice(&jpeg_orientation_segment(orientation));
+			orientation_written = true;
+		}
+		if marker == 0xda {
+			// start of scan, the rest is image data
+			out.extend_from_slice(&data[pos..]);
+			return Ok(out);
+		}
+		let end = pos + 2 + ((data[pos + 2] as usize) << 8 | data[pos + 3] as usize);
+		if end < pos + 4 || end > data.len() {
+			return Err(malformed(ImageFormat::JPEG));
+		}
+		let segment = &data[pos..end];
+		let keep = match marker {
+			0xe0 | 0xee => true,
+			0xe2 => segment[4..].starts_with(b"ICC_PROFILE\0"),
+			0xe1..=0xef | 0xfe => false,
+			_ => true,
+		};
+		if keep {
+			out.extend_from_slice(segment);
+		}
+		pos = end;
+	}
+}
+
+/// PNG chunks which affect decoding or how pixels look, text, time and EXIF chunks are dropped
+const PNG_KEPT_CHUNKS: &[&[u8; 4]] = &[
+	b"IHDR", b"PLTE", b"IDAT", b"IEND", b"tRNS", b"gAMA", b"cHRM", b"sRGB", b"iCCP", b"sBIT",
+	b"pHYs", b"bKGD", b"acTL", b"fcTL", b"fdAT",
+];
+
+fn strip_png(data: &[u8]) -> Result<Vec<u8>> {
+	let mut out = Vec::with_capacity(data.len());
+	out.extend_from_slice(&data[..8]); // signature
+	let mut pos = 8;
+	while pos < data.len() {
+		if pos + 12 > data.len() {
+			return Err(malformed(ImageFormat::PNG));
+		}
+		let length = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]);
+		let end = pos + 12 + length as usize;
+		if end > data.len() {
+			return Err(malformed(ImageFormat::PNG));
+		}
+		let kind = &data[pos + 4..pos + 8];
+		if PNG_KEPT_CHUNKS.iter().any(|k| &k[..] == kind) {
+			out.extend_from_slice(&data[pos..end]);
+		}
+		pos = end;
+		if kind == b"IEND" {
+			break;
+		}
+	}
+	Ok(out)
+}
+
+/// Removes metadata without decoding pixels, so it's cheap enough for the request thread.
+/// Returns `None` if `data` is not a processed format.
+pub fn strip_metadata(data: &[u8], config: &ImageConfig) -> Result<Option<Image>> {
+	let format = match detect(data) {
+		Some(f) => f,
+		None => return Ok(None),
+	};
+	let (width, height) = dimensions_of(data, format)?;
+	if width.saturating_mul(height) > config.max_pixels {
+		return Err(Error::TooLarge);
+	}
+	let (data, rotated) = match format {
+		ImageFormat::JPEG => (strip_jpeg(data)?, exif_orientation(data) >= 5),
+		_ => (strip_png(data)?, false),
+	};
+	let (width, height) = match rotated {
+		true => (height as u32, width as u32),
+		false => (width as u32, height as u32),
+	};
+	Ok(Some(Image {
+		format: format,
+		data: data,
+		width: width,
+		height: height,
+	}))
+}
+
+/// Path of a derivative, like `201906/<uuid>_640w.jpg`
+fn derivative_path(path: &str, width: u32, extension: &str) -> String {
+	let stem = match path.rfind('.') {
+		Some(idx) if !path[idx..].contains('/') => &path[..idx],
+		_ => path,
+	};
+	format!("{}_{}w.{}", stem, width, extension)
+}
+
+/// Generates and saves resized copies of `file`, `image` is decoded here
+pub fn generate_derivatives(
+	db: &Database,
+	storage: &StorageManager,
+	config: &ImageConfig,
+	file: &File,
+	image: &Image,
+) -> Result<Vec<FileDerivative>> {
+	let (_, path) = StorageManager::parse_key(&file.key)?;
+	let (width, height) = image.dimensions();
+	let pixels = image.decode()?;
+	let mut derivatives = Vec::new();
+	let mut save = |data: &[u8], w: u32, h: u32, extension: &str, mime: &str| -> Result<()> {
+		let key = storage.put_beside(
+			&file.key,
+			&derivative_path(path, w, extension),
+			data,
+			Some(mime),
+		)?;
+		derivatives.push(FileDerivative::insert(
+			db,
+			NewFileDerivative {
+				file: file.id,
+				key: key,
+				width: w as i32,
+				height: h as i32,
+				mime: mime.to_owned(),
+			},
+		)?);
+		Ok(())
+	};
+
+	let mut sizes: Vec<u32> = config.sizes.iter().cloned().filter(|&w| w < width).collect();
+	sizes.sort();
+	sizes.dedup();
+	for w in sizes {
+		let h = ((u64::from(height) * u64::from(w)) / u64::from(width)).max(1) as u32;
+		let resized = pixels.resize_exact(w, h, FilterType::Lanczos3);
+		save(
+			&encode(&resized, image.format, config)?,
+			w,
+			h,
+			extension_of(image.format),
+			image.mime(),
+		)?;
+		if config.webp {
+			if let Some(data) = encode_webp(&resized, config) {
+				save(&data, w, h, "webp", "image/webp")?;
+			}
+		}
+	}
+	if config.webp {
+		if let Some(data) = encode_webp(&pixels, config) {
+			save(&data, width, height, "webp", "image/webp")?;
+		}
+	}
+	Ok(derivatives)
+}
+
+lazy_static! {
+	/// Jobs run one by one in a background thread, so uploads don't resize many images at once
+	static ref DERIVATIVE_WORKER: Mutex<mpsc::Sender<Box<dyn FnOnce() + Send>>> = {
+		let (sender, receiver) = mpsc::channel::<Box<dyn FnOnce() + Send>>();
+		thread::spawn(move || {
+			for job in receiver {
+				job();
+			}
+		});
+		Mutex::new(sender)
+	};
+}
+
+/// Queues `generate_derivatives` for the background worker, errors are printed
+pub fn generate_derivatives_in_background(
+	db: &Database,
+	storage: &StorageManager,
+	config: &ImageConfig,
+	file: File,
+	image: Image,
+) {
+	let (db, storage, config) = (db.clone(), storage.clone(), config.clone());
+	let job = Box::new(move || {
+		if let Err(e) = generate_derivatives(&db, &storage, &config, &file, &image) {
+			println!("Failed to generate derivatives of file {}: {}", file.id, e);
+		}
+	});
+	if DERIVATIVE_WORKER.lock().unwrap().send(job).is_err() {
+		println!("Derivative worker is gone");
+	}
+}
+
+/// Saves `data` into storage unless identical bytes were saved before.
+/// Returns `(key, hash, is_new)`, derivatives are only needed to be generated if `is_new`.
+pub fn store_deduplicated(
+	db: &Database,
+	storage: &StorageManager,
+	path: &str,
+	data: &[u8],
+	mime: &str,
+) -> Result<(String, String, bool)> {
+	let hash = HEXLOWER.encode(&Sha256::digest(data));
+	if let Some(blob) = Blob::acquire(db, &hash)? {
+		return Ok((blob.key, hash, false));
+	}
+	let key = storage.put(path, data, Some(mime))?;
+	let blob = Blob::create_or_acquire(db, &hash, &key)?;
+	if blob.key != key {
+		// another upload of the same bytes won the race
+		storage.delete(&key)?;
+		return Ok((blob.key, hash, false));
+	}
+	Ok((key, hash, true))
+}
+
+/// Lets `file` use derivatives of an earlier file with the same content
+pub fn share_derivatives(db: &Database, file: &File) -> Result<()> {
+	let hash = match &file.hash {
+		Some(h) => h,
+		None => return Ok(()),
+	};
+	if let Some(origin) = File::find_by_hash(db, hash)?
+		.into_iter()
+		.find(|f| f.id != file.id)
+	{
+		FileDerivative::copy_to_file(db, origin.id, file.id)?;
+	}
+	Ok(())
+}
+
+/// Deletes a file from database, and its object with resized copies from storage
+/// if no other file has the same content.
+pub fn remove_file(db: &Database, storage: &StorageManager, file: &File) -> Result<()> {
+	let derivatives = FileDerivative::find_by_file_id(db, file.id)?;
+	file.delete(db)?;
+	let unused_key = match &file.hash {
+		Some(hash) => Blob::release(db, hash)?,
+		None => Some(file.key.to_owned()),
+	};
+	if let Some(key) = unused_key {
+		for derivative in derivatives {
+			storage.delete(&derivative.key)?;
+		}
+		storage.delete(&key)?;
+	}
+	Ok(())
+}
+
+/// Files not linked to any content, and not mentioned in any content or draft.
+/// Files uploaded within `min_age` are skipped, since the post may not be saved yet.
+pub fn find_orphans(db: &Database, min_age: Duration) -> Result<Vec<File>> {
+	Ok(File::find_unreferenced(db, Utc::now() - min_age)?)
+}
@@ -0,0 +1,162 @@
+use super::{
+	login_history::{LoginHistory, LoginMethod, LoginResult},
+	user::{User, UserStatus},
+	Error, Result,
+};
+use crate::{db::Database, schema::*, utils::*};
+use chrono::{DateTime, Utc};
+use data_encoding::HEXLOWER;
+use diesel::prelude::*;
+use ipnetwork::IpNetwork;
+use rocket::{request::Request, State};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Every generated token starts with this, so it's easy to recognize in scripts and logs
+pub const TOKEN_PREFIX: &str = "sbt_";
+
+#[derive(Identifiable, Debug, Queryable, Associations, Clone)]
+#[primary_key(id)]
+#[table_name = "api_token"]
+#[belongs_to(User, foreign_key = "user")]
+pub struct ApiToken {
+	pub id: i32,
+	pub user: i32,
+	pub name: String,
+	pub token_hash: String,
+	pub scope: i32,
+	pub created_at: DateTime<Utc>,
+	pub expires_at: Option<DateTime<Utc>>,
+	pub last_used_at: Option<DateTime<Utc>>,
+	pub last_used_ip: Option<IpNetwork>,
+}
+#[derive(Insertable, Debug)]
+#[table_name = "api_token"]
+pub struct NewApiToken {
+	pub user: i32,
+	pub name: String,
+	pub token_hash: String,
+	pub scope: i32,
+	pub expires_at: Option<DateTime<Utc>>,
+}
+impl ApiToken {
+	insert!(api_token, NewApiToken);
+	find_pk!(api_token);
+	find_one_by!(api_token, find_by_token_hash, token_hash as &str);
+	delete!();
+
+	pub fn find_by_user_id(db: &Database, user_id: i32) -> Result<Vec<Self>> {
+		api_token::table
+			.filter(api_token::user.eq(user_id))
+			.order(api_token::id.desc())
+			.load::<Self>(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	pub fn hash(token: &str) -> String {
+		HEXLOWER.encode(&Sha256::digest(token.as_bytes()))
+	}
+
+	/// Creates a token for `user`, returns the saved row and the plain token.
+	/// Only the hash is saved, so the plain token can't be shown again.
+	/// `scope` will be limited to what `user` has.
+	pub fn create(
+		db: &Database,
+		user: &User,
+		name: String,
+		scope: i32,
+		expires_at: Option<DateTime<Utc>>,
+	) -> Result<(Self, String)> {
+		let token = format!(
+			"{}{}{}",
+			TOKEN_PREFIX,
+			Uuid::new_v4().to_simple(),
+			Uuid::new_v4().to_simple()
+		);
+		let row = Self::insert(
+			db,
+			NewApiToken {
+				user: user.id,
+				name: name,
+				token_hash: Self::hash(&token),
+				scope: (scope | super::user::PERM_LOGIN) & user.permission,
+				expires_at: expires_at,
+			},
+		)?;
+		Ok((row, token))
+	}
+
+	pub fn is_expired(&self) -> bool {
+		self.expires_at.map(|t| t <= Utc::now()).unwrap_or(false)
+	}
+
+	pub fn touch(&self, db: &Database, ip: Option<IpNetwork>) -> Result<()> {
+		diesel::update(self)
+			.set((
+				api_token::last_used_at.eq(Utc::now()),
+				api_token::last_used_ip.eq(ip),
+			))
+			.execute(&db.conn()?)?;
+		Ok(())
+	}
+
+	/// Finds the owner of a plain token.
+	/// The returned user's permission is limited by token's `scope`.
+	pub fn authenticate(db: &Database, token: &str, ip: Option<IpNetwork>) -> Result<(User, Self)> {
+		let token = Self::find_by_token_hash(db, Self::hash(token).as_str())?;
+		if token.is_expired() {
+			return Err(Error::NotFound);
+		}
+		let mut user = User::find(db, token.user)?;
+		if user.status != UserStatus::Normal {
+			return Err(Error::NotFound);
+		}
+		user.permission &= token.scope;
+		token.touch(db, ip)?;
+		Ok((user, token))
+	}
+}
+
+/// Returns the token in `Authorization: Bearer <token>` header
+pub fn bearer_token<'r>(request: &'r Request) -> Option<&'r str> {
+	request.headers().get_one("Authorization").and_then(|h| {
+		let mut parts = h.trim().splitn(2, ' ');
+		match (parts.next(), parts.next()) {
+			(Some(scheme), Some(token)) if scheme.eq_ignore_ascii_case("Bearer") => {
+				Some(token.trim())
+			}
+			_ => None,
+		}
+	})
+}
+
+/// Result of bearer token authentication, cached in request-local cache so it only hits database once
+pub struct BearerAuth(pub Option<(User, ApiToken)>);
+pub fn bearer_auth<'a>(request: &'a Request) -> &'a BearerAuth {
+	request.local_cache(|| {
+		BearerAuth(bearer_token(request).and_then(|token| {
+			let db = request.guard::<State<Box<Database>>>().succeeded()?;
+			let ip = request
+				.guard::<VisitorIP>()
+				.succeeded()
+				.map(|ip| ip.to_ipnetwork());
+			let (user, token) = ApiToken::authenticate(&db, token, ip).ok()?;
+			if token.last_used_ip != ip {
+				// Only records token use from a new address, or every API call would be logged
+				let user_agent = request.headers().get_one("User-Agent");
+				if let Err(e) = LoginHistory::record(
+					&db,
+					Some(&user),
+					Some(&user.username),
+					ip,
+					user_agent,
+					LoginResult::Success,
+					LoginMethod::Token,
+				) {
+					println!("Failed to write login history: {:?}", e);
+				}
+			}
+			Some((user, token))
+		}))
+	})
+}
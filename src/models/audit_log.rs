@@ -0,0 +1,165 @@
+use super::{user::User, Error, Result};
+use crate::{db::Database, schema::*, utils::*};
+use chrono::{DateTime, Utc};
+use diesel::{pg::Pg, prelude::*};
+use ipnetwork::IpNetwork;
+use rocket::{
+	request::{FromRequest, Outcome, Request},
+	State,
+};
+use serde_json::{json, Value};
+
+pub const ACTION_LOGIN: &str = "user.login";
+pub const ACTION_LOGIN_FAILED: &str = "user.login_failed";
+pub const ACTION_POST_CREATE: &str = "post.create";
+pub const ACTION_POST_UPDATE: &str = "post.update";
+pub const ACTION_CATEGORY_CREATE: &str = "category.create";
+pub const ACTION_CATEGORY_UPDATE: &str = "category.update";
+pub const ACTION_COMMENT_SET_STATUS: &str = "comment.set_status";
+pub const ACTION_FILE_UPLOAD: &str = "file.upload";
+pub const ACTION_FILE_DELETE: &str = "file.delete";
+pub const ACTION_FILE_RELINK: &str = "file.relink";
+pub const ACTION_TOKEN_CREATE: &str = "api_token.create";
+pub const ACTION_TOKEN_REVOKE: &str = "api_token.revoke";
+pub const ACTION_THEME_ACTIVATE: &str = "setting.theme";
+pub const ACTION_PLUGIN_SETTINGS: &str = "setting.plugin";
+pub const ACTION_PLUGIN_ENABLE: &str = "setting.plugin_enable";
+
+/// All actions, used for filtering in admin panel
+pub const ACTIONS: &[&str] = &[
+	ACTION_LOGIN,
+	ACTION_LOGIN_FAILED,
+	ACTION_POST_CREATE,
+	ACTION_POST_UPDATE,
+	ACTION_CATEGORY_CREATE,
+	ACTION_CATEGORY_UPDATE,
+	ACTION_COMMENT_SET_STATUS,
+	ACTION_FILE_UPLOAD,
+	ACTION_FILE_DELETE,
+	ACTION_FILE_RELINK,
+	ACTION_TOKEN_CREATE,
+	ACTION_TOKEN_REVOKE,
+	ACTION_THEME_ACTIVATE,
+	ACTION_PLUGIN_SETTINGS,
+	ACTION_PLUGIN_ENABLE,
+];
+
+#[derive(Identifiable, Debug, Queryable, Associations, Clone)]
+#[primary_key(id)]
+#[table_name = "audit_log"]
+#[belongs_to(User, foreign_key = "user")]
+pub struct AuditLog {
+	pub id: i32,
+	pub user: Option<i32>,
+	pub ip: Option<IpNetwork>,
+	pub action: String,
+	pub target_type: Option<String>,
+	pub target_id: Option<i32>,
+	pub detail: Option<String>,
+	pub time: DateTime<Utc>,
+}
+#[derive(Insertable, Debug)]
+#[table_name = "audit_log"]
+pub struct NewAuditLog {
+	pub user: Option<i32>,
+	pub ip: Option<IpNetwork>,
+	pub action: String,
+	pub target_type: Option<String>,
+	pub target_id: Option<i32>,
+	pub detail: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct AuditLogFilter {
+	pub user: Option<i32>,
+	pub action: Option<String>,
+	pub target_type: Option<String>,
+}
+impl AuditLogFilter {
+	fn query<'a>(&'a self) -> audit_log::BoxedQuery<'a, Pg> {
+		let mut query = audit_log::table.into_boxed();
+		if let Some(user) = self.user {
+			query = query.filter(audit_log::user.eq(user));
+		}
+		if let Some(action) = &self.action {
+			query = query.filter(audit_log::action.eq(action));
+		}
+		if let Some(target_type) = &self.target_type {
+			query = query.filter(audit_log::target_type.eq(target_type));
+		}
+		query
+	}
+}
+
+impl AuditLog {
+	insert!(audit_log, NewAuditLog);
+
+	pub fn count(db: &Database, filter: &AuditLogFilter) -> Result<i64> {
+		filter
+			.query()
+			.count()
+			.get_result(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	/// `range` is `None` for all rows, used by exporting
+	pub fn find_by_filter(
+		db: &Database,
+		range: Option<(i32, i32)>,
+		filter: &AuditLogFilter,
+	) -> Result<Vec<Self>> {
+		let mut query = filter
+			.query()
+			.order((audit_log::time.desc(), audit_log::id.desc()));
+		if let Some((min, max)) = range {
+			query = query.offset(min.into()).limit((max - min).into());
+		}
+		query.load::<Self>(&db.conn()?).map_err(Error::from)
+	}
+}
+
+/// Request guard for writing audit logs with visitor's IP
+pub struct Audit {
+	db: Box<Database>,
+	ip: Option<IpNetwork>,
+}
+impl Audit {
+	/// Failing to write audit log doesn't fail the request, the error is printed instead.
+	pub fn log(
+		&self,
+		user: Option<&User>,
+		action: &str,
+		target: Option<(&str, i32)>,
+		before: Option<Value>,
+		after: Option<Value>,
+	) {
+		let detail = if before.is_none() && after.is_none() {
+			None
+		} else {
+			Some(json!({ "before": before, "after": after }).to_string())
+		};
+		let new = NewAuditLog {
+			user: user.map(|u| u.id),
+			ip: self.ip,
+			action: action.to_owned(),
+			target_type: target.map(|(t, _)| t.to_owned()),
+			target_id: target.map(|(_, id)| id),
+			detail: detail,
+		};
+		if let Err(e) = AuditLog::insert(&self.db, new) {
+			println!("Failed to write audit log: {:?}", e);
+		}
+	}
+}
+impl<'a, 'r> FromRequest<'a, 'r> for Audit {
+	type Error = ();
+	fn from_request(request: &'a Request<'r>) -> Outcome<Self, ()> {
+		Outcome::Success(Self {
+			db: request.guard::<State<Box<Database>>>()?.inner().clone(),
+			ip: request
+				.guard::<VisitorIP>()
+				.succeeded()
+				.map(|ip| ip.to_ipnetwork()),
+		})
+	}
+}
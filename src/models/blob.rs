@@ -0,0 +1,77 @@
+use super::{Error, Result};
+use crate::{db::Database, schema::*, utils::*};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+
+/// A stored object shared by files with identical content, deleted from storage when
+/// no file refers to it.
+#[derive(Identifiable, Debug, Queryable, Clone)]
+#[primary_key(id)]
+#[table_name = "blob"]
+pub struct Blob {
+	pub id: i32,
+	pub hash: String,
+	pub key: String,
+	pub ref_count: i32,
+	pub time: DateTime<Utc>,
+}
+#[derive(Insertable, Debug)]
+#[table_name = "blob"]
+pub struct NewBlob<'a> {
+	pub hash: &'a str,
+	pub key: &'a str,
+}
+impl Blob {
+	/// Adds a reference to the object of `hash`, `None` if there's no such object
+	pub fn acquire(db: &Database, hash: &str) -> Result<Option<Self>> {
+		diesel::update(blob::table.filter(blob::hash.eq(hash)))
+			.set(blob::ref_count.eq(blob::ref_count + 1))
+			.get_result::<Self>(&db.conn()?)
+			.optional()
+			.map_err(Error::from)
+	}
+
+	/// Records a newly stored object. If another upload stored the same bytes meanwhile,
+	/// a reference to that one is added and returned instead.
+	pub fn create_or_acquire(db: &Database, hash: &str, key: &str) -> Result<Self> {
+		diesel::insert_into(blob::table)
+			.values(NewBlob {
+				hash: hash,
+				key: key,
+			})
+			.on_conflict(blob::hash)
+			.do_update()
+			.set(blob::ref_count.eq(blob::ref_count + 1))
+			.get_result::<Self>(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	/// Removes a reference, returns the key if nothing refers to the object anymore
+	/// and it should be deleted from storage.
+	pub fn release(db: &Database, hash: &str) -> Result<Option<String>> {
+		let conn = db.conn()?;
+		conn.transaction::<_, diesel::result::Error, _>(|| {
+			diesel::update(blob::table.filter(blob::hash.eq(hash)))
+				.set(blob::ref_count.eq(blob::ref_count - 1))
+				.execute(&conn)?;
+			diesel::delete(
+				blob::table
+					.filter(blob::hash.eq(hash))
+					.filter(blob::ref_count.le(0)),
+			)
+			.get_result::<Self>(&conn)
+			.optional()
+		})
+		.map(|deleted| deleted.map(|b| b.key))
+		.map_err(Error::from)
+	}
+
+	/// Used when objects are moved to another storage backend
+	pub fn replace_key(db: &Database, old: &str, new: &str) -> Result<()> {
+		diesel::update(blob::table.filter(blob::key.eq(old)))
+			.set(blob::key.eq(new))
+			.execute(&db.conn()?)
+			.map(|_| ())
+			.map_err(Error::from)
+	}
+}
@@ -1,4 +1,4 @@
-use super::{Error, IntoInterface, RepositoryWrapper, Result};
+use super::{Error, IntoInterface, Repository, RepositoryWrapper, Result};
 use crate::{db::Database, schema::*, utils::*};
 use diesel::prelude::*;
 use serde_derive::*;
@@ -27,6 +27,11 @@ impl Category {
 		query = query.order(category::order.desc());
 		query.load::<Self>(&db.conn()?).map_err(Error::from)
 	}
+
+	/// Fields recorded in audit log
+	pub fn audit_summary(&self) -> serde_json::Value {
+		serde_json::to_value(self).unwrap_or_default()
+	}
 }
 impl PartialEq for Category {
 	fn eq(&self, other: &Self) -> bool {
@@ -35,7 +40,7 @@ impl PartialEq for Category {
 }
 
 use crate::interfaces::models::Category as CategoryInterface;
-impl CategoryInterface for RepositoryWrapper<Category, Box<Database>> {
+impl CategoryInterface for RepositoryWrapper<Category, Repository> {
 	fn id(&self) -> i32 {
 		self.0.id
 	}
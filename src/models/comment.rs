@@ -2,7 +2,7 @@ use diesel::prelude::*;
 use serde_derive::*;
 use ipnetwork::IpNetwork;
 
-use super::{content::Content, user::User, Error, IntoInterface, RepositoryWrapper, Result};
+use super::{content::Content, user::User, Error, IntoInterface, Repository, RepositoryWrapper, Result};
 use crate::{db::Database, schema::*, utils::*};
 use chrono::{DateTime, Local, Utc};
 
@@ -114,6 +114,15 @@ impl Comment {
 			.map_err(Error::from)
 	}
 
+	/// Fields recorded in audit log
+	pub fn audit_summary(&self) -> serde_json::Value {
+		serde_json::json!({
+			"status": self.status,
+			"content": self.content,
+			"author_name": self.author_name,
+		})
+	}
+
 	pub fn get_content(&self, db: &Database) -> Result<Content> {
 		Content::find(db, self.content)
 	}
@@ -147,7 +156,7 @@ impl Comment {
 use crate::interfaces::models::{
 	Author as AuthorInterface, Comment as CommentInterface, Content as ContentInterface,
 };
-impl CommentInterface for RepositoryWrapper<Comment, Box<Database>> {
+impl CommentInterface for RepositoryWrapper<Comment, Repository> {
 	fn id(&self) -> i32 {
 		self.0.id
 	}
@@ -249,7 +258,7 @@ impl AuthorInterface for Author {
 	}
 }
 impl IntoInterface<Box<dyn AuthorInterface>> for Author {
-	fn into_interface(self, _: &Box<Database>) -> Box<dyn AuthorInterface> {
+	fn into_interface(self, _: &Repository) -> Box<dyn AuthorInterface> {
 		Box::new(self) as Box<dyn AuthorInterface>
 	}
 }
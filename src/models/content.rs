@@ -6,12 +6,24 @@ use chrono::{DateTime, Local, Utc};
 use super::{
 	category::Category,
 	comment::Comment,
+	file::File,
 	tag::{AssocTagContent, Tag},
 	user::User,
-	Error, IntoInterface, RepositoryWrapper, Result,
+	Error, IntoInterface, Repository, RepositoryWrapper, Result,
 };
 use crate::{db::Database, schema::*, utils::*};
 
+/// Conditions of listed posts besides status, `None` matches everything
+#[derive(Debug, Default)]
+pub struct PostFilter {
+	/// Matched case-insensitively in title and content
+	pub keyword: Option<String>,
+	/// `[start, end)` of publish time
+	pub time_range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+	pub category: Option<i32>,
+	pub tag: Option<i32>,
+}
+
 #[derive(Debug, Queryable, Associations, Clone, Serialize, Identifiable, AsChangeset)]
 #[changeset_options(treat_none_as_null = "true")]
 #[table_name = "content"]
@@ -39,6 +51,7 @@ pub struct Content {
 	pub allow_comment: bool,
 	pub allow_feed: bool,
 	pub parent: Option<i32>,
+	pub featured_file: Option<i32>,
 }
 impl Content {
 	insert!(content, NewContent);
@@ -77,6 +90,74 @@ impl Content {
 		query.load::<Self>(&db.conn()?).map_err(Error::from)
 	}
 
+	/// Posts matching `filter`, newest first
+	pub fn find_filtered_posts(
+		db: &Database,
+		(min, max): (i32, i32),
+		status: &Vec<ContentStatus>,
+		filter: &PostFilter,
+	) -> Result<Vec<Self>> {
+		Self::filter_posts(status, filter)
+			.order(content::time.desc())
+			.offset(min.into())
+			.limit((max - min).into())
+			.load::<Self>(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	pub fn count_filtered_posts(
+		db: &Database,
+		status: &Vec<ContentStatus>,
+		filter: &PostFilter,
+	) -> Result<i64> {
+		Self::filter_posts(status, filter)
+			.count()
+			.get_result(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	fn filter_posts<'a>(
+		status: &Vec<ContentStatus>,
+		filter: &PostFilter,
+	) -> content::BoxedQuery<'a, diesel::pg::Pg> {
+		let mut query = content::table
+			.into_boxed()
+			.filter(content::type_.eq(ContentType::Article))
+			.filter(content::status.eq_any(status.to_owned()));
+		if let Some(keyword) = &filter.keyword {
+			let pattern = format!(
+				"%{}%",
+				keyword
+					.replace('\\', "\\\\")
+					.replace('%', "\\%")
+					.replace('_', "\\_")
+			);
+			// content of protected posts is not searched, so it can't be guessed
+			let in_content = content::content_
+				.ilike(pattern.to_owned())
+				.and(content::view_password.is_null());
+			query = query.filter(content::title.ilike(pattern).or(in_content));
+		}
+		if let Some((start, end)) = filter.time_range {
+			query = query
+				.filter(content::time.ge(start))
+				.filter(content::time.lt(end));
+		}
+		if let Some(category) = filter.category {
+			query = query.filter(content::category.eq(category));
+		}
+		if let Some(tag) = filter.tag {
+			query = query.filter(
+				content::id.eq_any(
+					assoc_tag_content::table
+						.filter(assoc_tag_content::tag.eq(tag))
+						.select(assoc_tag_content::content),
+				),
+			);
+		}
+		query
+	}
+
 	pub fn get_tags(&self, db: &Database) -> Result<Vec<Tag>> {
 		let assocs = AssocTagContent::find_by_content_id(db, self.id)?;
 		Tag::find_by_id(db, assocs.iter().map(|t| t.tag).collect::<Vec<i32>>())
@@ -116,10 +197,35 @@ impl Content {
 		}
 	}
 
+	pub fn get_featured_file(&self, db: &Database) -> Result<Option<File>> {
+		match self.featured_file {
+			Some(id) => match File::find(db, id) {
+				Ok(f) => Ok(Some(f)),
+				Err(Error::NotFound) => Ok(None),
+				Err(e) => Err(e),
+			},
+			None => Ok(None),
+		}
+	}
+
 	pub fn get_user(&self, db: &Database) -> Result<User> {
 		User::find(db, self.user.unwrap()) // FIXME: not safe
 	}
 
+	/// Fields recorded in audit log, body is omitted
+	pub fn audit_summary(&self) -> serde_json::Value {
+		serde_json::json!({
+			"title": self.title,
+			"slug": self.slug,
+			"status": self.status,
+			"category": self.category,
+			"time": self.time,
+			"content_length": self.content.len(),
+			"has_draft": self.draft_content.is_some(),
+			"featured_file": self.featured_file,
+		})
+	}
+
 	pub fn user_has_access(&self, user: Option<&User>) -> bool {
 		match user {
 			Some(_) => self.status.is_visible_to_logged_in(),
@@ -127,6 +233,15 @@ impl Content {
 		}
 	}
 
+	/// Drops the text of a protected post or page for visitors without the password, so themes
+	/// can't show it in excerpts
+	pub fn lock(&mut self) {
+		if self.view_password.is_some() {
+			self.content.clear();
+			self.draft_content = None;
+		}
+	}
+
 	// -- methods for posts --
 	/// is_prev: true-> prev_post, false-> next_post
 	pub fn find_neighbor_post(
@@ -164,9 +279,9 @@ impl Content {
 
 use crate::interfaces::models::{
 	Category as CategoryInterface, Comment as CommentInterface, Content as ContentInterface,
-	Tag as TagInterface, User as UserInterface,
+	File as FileInterface, Tag as TagInterface, User as UserInterface,
 };
-impl ContentInterface for RepositoryWrapper<Content, Box<Database>> {
+impl ContentInterface for RepositoryWrapper<Content, Repository> {
 	fn id(&self) -> i32 {
 		self.0.id
 	}
@@ -206,6 +321,9 @@ impl ContentInterface for RepositoryWrapper<Content, Box<Database>> {
 	fn category_id(&self) -> Option<i32> {
 		self.0.category
 	}
+	fn is_protected(&self) -> bool {
+		self.0.view_password.is_some()
+	}
 
 	fn user(&self) -> Box<dyn UserInterface> {
 		self.0.get_user(&self.1).unwrap().into_interface(&self.1)
@@ -232,11 +350,21 @@ impl ContentInterface for RepositoryWrapper<Content, Box<Database>> {
 			.collect::<Vec<String>>()
 	}
 	fn get_neighbor_post(&self, prev: bool) -> Option<Box<dyn ContentInterface>> {
-		self.0.find_neighbor_post(&self.1, prev, 1).unwrap().into_interface(&self.1)
+		self.0
+			.find_neighbor_post(&self.1, prev, 1)
+			.unwrap()
+			.map(|mut post| {
+				post.lock();
+				post
+			})
+			.into_interface(&self.1)
 	}
 	fn get_parent_comments(&self) -> Vec<Box<dyn CommentInterface>> {
 		Comment::find_parents_by_content_id(&self.1, self.0.id).unwrap().into_interface(&self.1)
 	}
+	fn featured_image(&self) -> Option<Box<dyn FileInterface>> {
+		self.0.get_featured_file(&self.1).unwrap().into_interface(&self.1)
+	}
 }
 create_into_interface!(dyn ContentInterface, Content);
 
@@ -1,6 +1,11 @@
-use super::{Error, Result};
-use crate::{db::Database, schema::*};
-use diesel::prelude::*;
+use super::{file_derivative::FileDerivative, Error, Repository, RepositoryWrapper, Result};
+use crate::{
+	db::Database,
+	schema::*,
+	types::{ContentStatus, EnumType},
+	utils::*,
+};
+use diesel::{dsl::sql, pg::Pg, prelude::*, sql_types::Bool};
 use serde_derive::*;
 use chrono::{DateTime, Utc};
 
@@ -14,7 +19,46 @@ pub struct File {
 	pub content: Option<i32>,
 	pub user: i32,
 	pub time: DateTime<Utc>,
+	pub width: Option<i32>,
+	pub height: Option<i32>,
+	pub mime: String,
+	pub size: i64,
+	pub hash: Option<String>,
+	/// Set if uploaded before the post was saved, see `link_draft`
+	#[serde(skip_serializing)]
+	pub draft_token: Option<String>,
 }
+/// Filters of media library, `None` fields are not filtered
+#[derive(Debug, Default)]
+pub struct FileFilter {
+	pub user: Option<i32>,
+	/// `Some(None)` for files not linked to any content
+	pub content: Option<Option<i32>>,
+	/// Part of original filename, case insensitive
+	pub filename: Option<String>,
+}
+impl FileFilter {
+	fn query<'a>(&'a self) -> file::BoxedQuery<'a, Pg> {
+		let mut query = file::table.into_boxed();
+		if let Some(user) = self.user {
+			query = query.filter(file::user.eq(user));
+		}
+		match self.content {
+			Some(Some(content)) => query = query.filter(file::content.eq(content)),
+			Some(None) => query = query.filter(file::content.is_null()),
+			None => (),
+		}
+		if let Some(filename) = &self.filename {
+			let escaped = filename
+				.replace('\\', "\\\\")
+				.replace('%', "\\%")
+				.replace('_', "\\_");
+			query = query.filter(file::filename.ilike(format!("%{}%", escaped)));
+		}
+		query
+	}
+}
+
 #[derive(Insertable, Debug)]
 #[table_name = "file"]
 pub struct NewFile {
@@ -22,6 +66,10 @@ pub struct NewFile {
 	pub filename: String,
 	pub user: i32,
 	pub content: Option<i32>,
+	pub mime: String,
+	pub size: i64,
+	pub hash: Option<String>,
+	pub draft_token: Option<String>,
 }
 impl File {
 	insert!(file, NewFile);
@@ -29,23 +77,188 @@ impl File {
 	delete!();
 	find_by!(file, find_by_content_id, content as i32);
 	find_by!(file, find_by_user_id, user as i32);
+	find_by!(file, find_by_hash, hash as &str);
 
-	pub fn new(key: String, filename: String, user_id: i32, content_id: Option<i32>) -> NewFile {
+	pub fn new(
+		key: String,
+		filename: String,
+		user_id: i32,
+		content_id: Option<i32>,
+		mime: String,
+		size: i64,
+		hash: Option<String>,
+	) -> NewFile {
 		NewFile {
 			key: key,
 			filename: filename,
 			content: content_id,
 			user: user_id,
+			mime: mime,
+			size: size,
+			hash: hash,
+			draft_token: None,
 		}
 	}
 
-	pub fn create(
+	pub fn count_by_filter(db: &Database, filter: &FileFilter) -> Result<i64> {
+		filter
+			.query()
+			.count()
+			.get_result(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	/// Newest first
+	pub fn find_by_filter(
 		db: &Database,
-		key: String,
-		filename: String,
-		user_id: i32,
-		content_id: Option<i32>,
-	) -> Result<Self> {
-		Self::insert(db, Self::new(key, filename, user_id, content_id))
+		(min, max): (i32, i32),
+		filter: &FileFilter,
+	) -> Result<Vec<Self>> {
+		filter
+			.query()
+			.order((file::time.desc(), file::id.desc()))
+			.offset(min.into())
+			.limit((max - min).into())
+			.load::<Self>(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	pub fn find_by_ids(db: &Database, ids: &[i32]) -> Result<Vec<Self>> {
+		file::table
+			.filter(file::id.eq_any(ids))
+			.load::<Self>(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	pub fn set_content(&self, db: &Database, content_id: Option<i32>) -> Result<()> {
+		diesel::update(self)
+			.set(file::content.eq(content_id))
+			.execute(&db.conn()?)
+			.map(|_| ())
+			.map_err(Error::from)
+	}
+
+	/// Files uploaded by `user_id` for an unsaved post
+	pub fn find_by_draft_token(db: &Database, token: &str, user_id: i32) -> Result<Vec<Self>> {
+		file::table
+			.filter(file::draft_token.eq(token))
+			.filter(file::user.eq(user_id))
+			.filter(file::content.is_null())
+			.order(file::id.asc())
+			.load::<Self>(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	/// Links files uploaded for an unsaved post to it once saved, returns the count
+	pub fn link_draft(db: &Database, token: &str, user_id: i32, content_id: i32) -> Result<usize> {
+		diesel::update(
+			file::table
+				.filter(file::draft_token.eq(token))
+				.filter(file::user.eq(user_id))
+				.filter(file::content.is_null()),
+		)
+		.set((
+			file::content.eq(content_id),
+			file::draft_token.eq(None::<String>),
+		))
+		.execute(&db.conn()?)
+		.map_err(Error::from)
+	}
+
+	/// Files uploaded before `before`, neither linked to nor mentioned in any content not deleted.
+	/// Mentions are found by the path without extension, so links to resized copies count too.
+	pub fn find_unreferenced(db: &Database, before: DateTime<Utc>) -> Result<Vec<Self>> {
+		let referenced = format!(
+			r#"EXISTS (SELECT 1 FROM "content" c WHERE c."status" <> {} AND (
+				c."id" = "file"."content"
+				OR strpos(c."content", {stem}) > 0
+				OR strpos(coalesce(c."draft_content", ''), {stem}) > 0
+			))"#,
+			ContentStatus::Deleted.number(),
+			stem = r#"regexp_replace(substr("file"."key", strpos("file"."key", ':') + 1), '\.[^.]*$', '')"#,
+		);
+		file::table
+			.filter(file::time.le(before))
+			.filter(sql::<Bool>(&format!("NOT {}", referenced)))
+			.order(file::id.asc())
+			.load::<Self>(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	pub fn find_all(db: &Database) -> Result<Vec<Self>> {
+		file::table
+			.order(file::id.asc())
+			.load::<Self>(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	/// Finds the first file having one of `keys`
+	pub fn find_by_keys(db: &Database, keys: &[String]) -> Result<Self> {
+		file::table
+			.filter(file::key.eq_any(keys))
+			.first::<Self>(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	pub fn is_image(&self) -> bool {
+		self.width.is_some() && self.height.is_some()
+	}
+
+	pub fn set_dimensions(&self, db: &Database, width: i32, height: i32) -> Result<()> {
+		diesel::update(self)
+			.set((file::width.eq(width), file::height.eq(height)))
+			.execute(&db.conn()?)
+			.map(|_| ())
+			.map_err(Error::from)
+	}
+
+	pub fn set_key(&self, db: &Database, key: &str) -> Result<()> {
+		diesel::update(self)
+			.set(file::key.eq(key))
+			.execute(&db.conn()?)
+			.map(|_| ())
+			.map_err(Error::from)
+	}
+
+	/// Fields recorded in audit log
+	pub fn audit_summary(&self) -> serde_json::Value {
+		serde_json::to_value(self).unwrap_or_default()
+	}
+}
+
+use crate::interfaces::models::{File as FileInterface, ImageVariant};
+impl FileInterface for RepositoryWrapper<File, Repository> {
+	fn id(&self) -> i32 {
+		self.0.id
+	}
+	fn filename(&self) -> &String {
+		&self.0.filename
+	}
+	fn mime(&self) -> &String {
+		&self.0.mime
+	}
+	fn url(&self) -> String {
+		self.1.storage.url(&self.0.key).unwrap_or_default()
+	}
+	fn width(&self) -> Option<i32> {
+		self.0.width
+	}
+	fn height(&self) -> Option<i32> {
+		self.0.height
+	}
+	fn derivatives(&self) -> Vec<ImageVariant> {
+		FileDerivative::find_by_file_id(&self.1, self.0.id)
+			.unwrap()
+			.into_iter()
+			.filter_map(|d| {
+				Some(ImageVariant {
+					url: self.1.storage.url(&d.key).ok()?,
+					width: d.width,
+					height: d.height,
+					mime: d.mime,
+				})
+			})
+			.collect()
 	}
 }
+create_into_interface!(dyn FileInterface, File);
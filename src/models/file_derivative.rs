@@ -0,0 +1,108 @@
+use super::{file::File, Error, Result};
+use crate::{db::Database, schema::*, utils::*};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde_derive::*;
+
+/// A resized copy of an uploaded image, saved in the same backend as the original
+#[derive(Identifiable, Debug, Queryable, Associations, Clone, Serialize)]
+#[primary_key(id)]
+#[table_name = "file_derivative"]
+#[belongs_to(File, foreign_key = "file")]
+pub struct FileDerivative {
+	pub id: i32,
+	pub file: i32,
+	pub key: String,
+	pub width: i32,
+	pub height: i32,
+	pub mime: String,
+	pub time: DateTime<Utc>,
+}
+#[derive(Insertable, Debug)]
+#[table_name = "file_derivative"]
+pub struct NewFileDerivative {
+	pub file: i32,
+	pub key: String,
+	pub width: i32,
+	pub height: i32,
+	pub mime: String,
+}
+impl FileDerivative {
+	insert!(file_derivative, NewFileDerivative);
+
+	pub fn find_by_file_id(db: &Database, file_id: i32) -> Result<Vec<Self>> {
+		file_derivative::table
+			.filter(file_derivative::file.eq(file_id))
+			.order(file_derivative::width.asc())
+			.load::<Self>(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	/// Derivatives of all `file_ids`, each file's ordered by width
+	pub fn find_by_file_ids(db: &Database, file_ids: &[i32]) -> Result<Vec<Self>> {
+		file_derivative::table
+			.filter(file_derivative::file.eq_any(file_ids))
+			.order((file_derivative::file.asc(), file_derivative::width.asc()))
+			.load::<Self>(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	/// Shares derivatives of `from` with `to`, for files with identical content.
+	/// Copies `to` already has are skipped.
+	pub fn copy_to_file(db: &Database, from: i32, to: i32) -> Result<Vec<Self>> {
+		let rows: Vec<NewFileDerivative> = Self::find_by_file_id(db, from)?
+			.into_iter()
+			.map(|d| NewFileDerivative {
+				file: to,
+				key: d.key,
+				width: d.width,
+				height: d.height,
+				mime: d.mime,
+			})
+			.collect();
+		diesel::insert_into(file_derivative::table)
+			.values(&rows)
+			.on_conflict_do_nothing()
+			.get_results(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	/// File having one of `keys` with its derivatives narrowest first, in one query
+	pub fn find_with_file_by_keys(db: &Database, keys: &[String]) -> Result<Option<(File, Vec<Self>)>> {
+		let rows = file::table
+			.left_join(file_derivative::table)
+			.filter(file::key.eq_any(keys))
+			.order((file::id.asc(), file_derivative::width.asc()))
+			.load::<(File, Option<Self>)>(&db.conn()?)
+			.map_err(Error::from)?;
+		let file_id = match rows.first() {
+			Some((file, _)) => file.id,
+			None => return Ok(None),
+		};
+		let mut found = None;
+		let mut derivatives = Vec::new();
+		for (file, derivative) in rows.into_iter().filter(|(f, _)| f.id == file_id) {
+			derivatives.extend(derivative);
+			found.get_or_insert(file);
+		}
+		Ok(found.map(|file| (file, derivatives)))
+	}
+
+	/// Derivative with its original file, by any of `keys`
+	pub fn find_by_keys(db: &Database, keys: &[String]) -> Result<Option<(Self, File)>> {
+		file_derivative::table
+			.inner_join(file::table)
+			.filter(file_derivative::key.eq_any(keys))
+			.first::<(Self, File)>(&db.conn()?)
+			.optional()
+			.map_err(Error::from)
+	}
+
+	pub fn set_key(&self, db: &Database, key: &str) -> Result<()> {
+		diesel::update(self)
+			.set(file_derivative::key.eq(key))
+			.execute(&db.conn()?)
+			.map(|_| ())
+			.map_err(Error::from)
+	}
+}
@@ -0,0 +1,133 @@
+use super::{user::User, Error, Result};
+use crate::{db::Database, schema::*, utils::*};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use ipnetwork::IpNetwork;
+
+/// Prefix lengths treated as the same network, i.e. /24 for IPv4 and /64 for IPv6
+const NETWORK_PREFIX_V4: u8 = 24;
+const NETWORK_PREFIX_V6: u8 = 64;
+
+/// How many previous successful logins are compared when looking for a new network
+const NETWORK_LOOKBACK: i64 = 100;
+
+/// Width of `username` column, longer usernames tried are cut so the attempt is still recorded
+const USERNAME_MAX_LENGTH: usize = 64;
+
+#[derive(Identifiable, Debug, Queryable, Associations, Clone)]
+#[primary_key(id)]
+#[table_name = "login_history"]
+#[belongs_to(User, foreign_key = "user")]
+pub struct LoginHistory {
+	pub id: i32,
+	pub user: Option<i32>,
+	pub username: Option<String>,
+	pub ip: Option<IpNetwork>,
+	pub user_agent: Option<String>,
+	pub result: LoginResult,
+	pub method: LoginMethod,
+	pub new_network: bool,
+	pub time: DateTime<Utc>,
+}
+#[derive(Insertable, Debug)]
+#[table_name = "login_history"]
+pub struct NewLoginHistory {
+	pub user: Option<i32>,
+	pub username: Option<String>,
+	pub ip: Option<IpNetwork>,
+	pub user_agent: Option<String>,
+	pub result: LoginResult,
+	pub method: LoginMethod,
+	pub new_network: bool,
+}
+
+/// Whether `a` and `b` are in the same /24 (IPv4) or /64 (IPv6) network
+fn same_network(a: &IpNetwork, b: &IpNetwork) -> bool {
+	let prefix = match a {
+		IpNetwork::V4(_) => NETWORK_PREFIX_V4,
+		IpNetwork::V6(_) => NETWORK_PREFIX_V6,
+	};
+	IpNetwork::new(a.ip(), prefix)
+		.map(|net| net.contains(b.ip()))
+		.unwrap_or(false)
+}
+
+impl LoginHistory {
+	insert!(login_history, NewLoginHistory);
+
+	/// Latest entries of a user, `result` is `None` for all results
+	pub fn find_recent_by_user(
+		db: &Database,
+		user_id: i32,
+		result: Option<LoginResult>,
+		limit: i64,
+	) -> Result<Vec<Self>> {
+		let mut query = login_history::table
+			.filter(login_history::user.eq(user_id))
+			.into_boxed();
+		if let Some(result) = result {
+			query = query.filter(login_history::result.eq(result));
+		}
+		query
+			.order((login_history::time.desc(), login_history::id.desc()))
+			.limit(limit)
+			.load::<Self>(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	/// Whether `ip` is in a network `user` has never logged in from.
+	/// The first login of a user is not treated as from a new network.
+	pub fn is_new_network(db: &Database, user: &User, ip: &IpNetwork) -> Result<bool> {
+		let previous =
+			Self::find_recent_by_user(db, user.id, Some(LoginResult::Success), NETWORK_LOOKBACK)?;
+		let ips: Vec<&IpNetwork> = previous.iter().filter_map(|h| h.ip.as_ref()).collect();
+		Ok(ips.len() > 0 && !ips.iter().any(|prev| same_network(prev, ip)))
+	}
+
+	/// Records a login attempt, `user` is `None` if nobody is matched
+	pub fn record(
+		db: &Database,
+		user: Option<&User>,
+		username: Option<&str>,
+		ip: Option<IpNetwork>,
+		user_agent: Option<&str>,
+		result: LoginResult,
+		method: LoginMethod,
+	) -> Result<Self> {
+		let new_network = match (user, &ip) {
+			(Some(user), Some(ip)) if result == LoginResult::Success => {
+				Self::is_new_network(db, user, ip)?
+			}
+			_ => false,
+		};
+		Self::insert(
+			db,
+			NewLoginHistory {
+				user: user.map(|u| u.id),
+				username: username.map(|s| s.chars().take(USERNAME_MAX_LENGTH).collect()),
+				ip: ip,
+				user_agent: user_agent.map(|s| s.to_owned()),
+				result: result,
+				method: method,
+				new_network: new_network,
+			},
+		)
+	}
+
+	pub fn method_name(&self) -> &'static str {
+		match self.method {
+			LoginMethod::Password => "password",
+			LoginMethod::Totp => "TOTP",
+			LoginMethod::Oidc => "OpenID Connect",
+			LoginMethod::Token => "API token",
+		}
+	}
+
+	pub fn ip_string(&self) -> String {
+		self.ip
+			.map(|ip| ip.ip().to_string())
+			.unwrap_or_else(|| String::from("unknown"))
+	}
+}
+
+pub use crate::types::{LoginMethod, LoginResult};
@@ -41,32 +41,46 @@ pub type Result<T> = std::result::Result<T, Error>;
 
 pub struct RepositoryWrapper<T, D>(pub T, pub D);
 
+/// Handles interfaces need besides their row, to load related rows and to make file URLs.
+/// Derefs to the database, so it's passed to model functions as is.
+#[derive(Clone)]
+pub struct Repository {
+	pub db: Box<crate::db::Database>,
+	pub storage: crate::storage::StorageManager,
+}
+impl std::ops::Deref for Repository {
+	type Target = crate::db::Database;
+	fn deref(&self) -> &Self::Target {
+		&self.db
+	}
+}
+
 pub trait IntoInterface<T>: Sized {
-	fn into_interface(self, db: &Box<crate::db::Database>) -> T;
+	fn into_interface(self, repository: &Repository) -> T;
 }
 impl<T, E> IntoInterface<Option<T>> for Option<E>
 where
 	E: IntoInterface<T>,
 {
-	fn into_interface(self, db: &Box<crate::db::Database>) -> Option<T> {
-		self.map(|c| c.into_interface(db))
+	fn into_interface(self, repository: &Repository) -> Option<T> {
+		self.map(|c| c.into_interface(repository))
 	}
 }
 impl<T, E> IntoInterface<Vec<T>> for Vec<E>
 where
 	E: IntoInterface<T>,
 {
-	fn into_interface(self, db: &Box<crate::db::Database>) -> Vec<T> {
+	fn into_interface(self, repository: &Repository) -> Vec<T> {
 		self.into_iter()
-			.map(|c| c.into_interface(db))
+			.map(|c| c.into_interface(repository))
 			.collect::<Vec<T>>()
 	}
 }
 macro_rules! create_into_interface {
 	($type:ty, $origin:ty) => {
 		impl crate::models::IntoInterface<Box<$type>> for $origin {
-			fn into_interface(self, db: &Box<Database>) -> Box<$type> {
-				Box::new(RepositoryWrapper(self, db.clone())) as Box<$type>
+			fn into_interface(self, repository: &crate::models::Repository) -> Box<$type> {
+				Box::new(RepositoryWrapper(self, repository.clone())) as Box<$type>
 			}
 		}
 	};
@@ -179,9 +193,15 @@ macro_rules! find_by {
 	};
 }
 
+pub mod api_token;
+pub mod audit_log;
+pub mod blob;
 pub mod category;
 pub mod comment;
 pub mod content;
 pub mod file;
+pub mod file_derivative;
+pub mod login_history;
+pub mod setting;
 pub mod tag;
 pub mod user;
@@ -0,0 +1,182 @@
+use super::{Error, Result};
+use crate::{
+	db::Database,
+	plugin::{PluginManager, PluginType},
+	schema::*,
+	utils::*,
+};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use sohablog_lib::settings::Settings;
+use std::{collections::HashMap, sync::RwLock};
+
+/// Name of the active theme
+pub const KEY_THEME: &str = "theme";
+/// Identities of plugins and themes which are not loaded, as a JSON array
+pub const KEY_DISABLED_PLUGINS: &str = "disabled_plugins";
+
+/// Prefix of keys declared by a plugin or theme, their settings are stored as `<prefix><key>`.
+/// Length of the identity is included, so `a.b` with key `c` can't collide with `a` with `b.c`.
+pub fn namespace(r#type: PluginType, identity: &str) -> String {
+	match r#type {
+		PluginType::Plugin => format!("plugin.{}:{}.", identity.len(), identity),
+		PluginType::Theme => format!("theme.{}:{}.", identity.len(), identity),
+	}
+}
+
+/// A site setting changed in admin panel, stored as text
+#[derive(Identifiable, Debug, Queryable, Clone)]
+#[primary_key(key)]
+#[table_name = "setting"]
+pub struct Setting {
+	pub key: String,
+	pub value: String,
+	pub modified_at: DateTime<Utc>,
+}
+#[derive(Insertable, Debug)]
+#[table_name = "setting"]
+pub struct NewSetting<'a> {
+	pub key: &'a str,
+	pub value: &'a str,
+}
+impl Setting {
+	/// `None` if the setting has never been saved
+	pub fn get(db: &Database, key: &str) -> Result<Option<String>> {
+		setting::table
+			.filter(setting::key.eq(key))
+			.select(setting::value)
+			.first::<String>(&db.conn()?)
+			.optional()
+			.map_err(Error::from)
+	}
+
+	pub fn set(db: &Database, key: &str, value: &str) -> Result<()> {
+		Self::upsert(&db.conn()?, key, value).map_err(Error::from)
+	}
+
+	/// Like `set`, but nothing is saved if any of them fails
+	pub fn set_all(db: &Database, values: &[(String, String)]) -> Result<()> {
+		let conn = db.conn()?;
+		conn.transaction::<_, diesel::result::Error, _>(|| {
+			for (key, value) in values {
+				Self::upsert(&conn, key, value)?;
+			}
+			Ok(())
+		})
+		.map_err(Error::from)
+	}
+
+	fn upsert(conn: &PgConnection, key: &str, value: &str) -> QueryResult<()> {
+		diesel::insert_into(setting::table)
+			.values(NewSetting {
+				key: key,
+				value: value,
+			})
+			.on_conflict(setting::key)
+			.do_update()
+			.set((
+				setting::value.eq(value),
+				setting::modified_at.eq(Utc::now()),
+			))
+			.execute(conn)
+			.map(|_| ())
+	}
+
+	pub fn disabled_plugins(db: &Database) -> Result<Vec<String>> {
+		Ok(Self::get(db, KEY_DISABLED_PLUGINS)?
+			.and_then(|value| serde_json::from_str(&value).ok())
+			.unwrap_or_default())
+	}
+
+	pub fn set_disabled_plugins(db: &Database, identities: &[String]) -> Result<()> {
+		Self::set(db, KEY_DISABLED_PLUGINS, &serde_json::json!(identities).to_string())
+	}
+
+	/// Settings whose key starts with `prefix`, keyed without the prefix
+	pub fn find_by_prefix(db: &Database, prefix: &str) -> Result<HashMap<String, String>> {
+		let pattern = format!(
+			"{}%",
+			prefix
+				.replace('\\', "\\\\")
+				.replace('%', "\\%")
+				.replace('_', "\\_")
+		);
+		setting::table
+			.filter(setting::key.like(pattern))
+			.select((setting::key, setting::value))
+			.load::<(String, String)>(&db.conn()?)
+			.map(|rows| {
+				rows.into_iter()
+					.map(|(key, value)| (key[prefix.len()..].to_owned(), value))
+					.collect()
+			})
+			.map_err(Error::from)
+	}
+}
+
+/// Values of every plugin and theme declaring settings, keyed by `namespace`
+#[derive(Default)]
+pub struct PluginSettings(RwLock<HashMap<String, Settings>>);
+impl PluginSettings {
+	/// Stored values are checked against the schemas of loaded plugins
+	fn read_all(
+		db: &Database,
+		plugin_manager: &PluginManager,
+	) -> Result<HashMap<String, Settings>> {
+		let mut all = HashMap::new();
+		for (r#type, identity, schema) in plugin_manager.settings_schemas() {
+			let ns = namespace(r#type, &identity);
+			let stored = Setting::find_by_prefix(db, &ns)?;
+			all.insert(ns, Settings::new(&schema, &stored));
+		}
+		Ok(all)
+	}
+
+	pub fn load(db: &Database, plugin_manager: &PluginManager) -> Result<Self> {
+		Ok(Self(RwLock::new(Self::read_all(db, plugin_manager)?)))
+	}
+
+	/// After plugins are reloaded, since their schemas may have changed
+	pub fn reload(&self, db: &Database, plugin_manager: &PluginManager) -> Result<()> {
+		*self.0.write().unwrap() = Self::read_all(db, plugin_manager)?;
+		Ok(())
+	}
+
+	/// Calls `Plugin::settings_changed` of every plugin declaring settings
+	pub fn notify(&self, plugin_manager: &PluginManager) {
+		for (r#type, name, _) in plugin_manager.settings_schemas() {
+			if r#type == PluginType::Plugin {
+				plugin_manager.settings_changed(&name, &self.get(r#type, &name));
+			}
+		}
+	}
+
+	/// Empty if the plugin declares no settings
+	pub fn get(&self, r#type: PluginType, identity: &str) -> Settings {
+		self.0
+			.read()
+			.unwrap()
+			.get(&namespace(r#type, identity))
+			.cloned()
+			.unwrap_or_default()
+	}
+
+	/// Values must be validated, they are stored before the cache is updated
+	pub fn save(
+		&self,
+		db: &Database,
+		r#type: PluginType,
+		identity: &str,
+		settings: Settings,
+	) -> Result<()> {
+		let ns = namespace(r#type, identity);
+		let values: Vec<(String, String)> = settings
+			.values()
+			.iter()
+			.map(|(key, value)| (format!("{}{}", ns, key), value.to_owned()))
+			.collect();
+		Setting::set_all(db, &values)?;
+		self.0.write().unwrap().insert(ns, settings);
+		Ok(())
+	}
+}
@@ -1,4 +1,4 @@
-use super::{Error, RepositoryWrapper, Result};
+use super::{Error, Repository, RepositoryWrapper, Result};
 use crate::{db::Database, schema::*, utils::*};
 use diesel::prelude::*;
 use serde_derive::*;
@@ -18,6 +18,7 @@ pub struct NewTag {
 impl Tag {
 	insert!(tag, NewTag);
 	find_pk!(tag);
+	find_one_by!(tag, find_one_by_name, name as &str);
 
 	pub fn new(name: &str) -> NewTag {
 		NewTag {
@@ -50,7 +51,7 @@ impl Tag {
 }
 
 use crate::interfaces::models::Tag as TagInterface;
-impl TagInterface for RepositoryWrapper<Tag, Box<Database>> {
+impl TagInterface for RepositoryWrapper<Tag, Repository> {
 	fn name(&self) -> &String {
 		&self.0.name
 	}
@@ -2,23 +2,45 @@ use diesel::prelude::*;
 use serde_derive::*;
 use chrono::{DateTime, Local, Utc};
 
-use super::{Error, RepositoryWrapper, Result};
+use super::{Error, Repository, RepositoryWrapper, Result};
 use crate::{db::Database, schema::*, utils::*};
 
 use bcrypt;
 
-#[allow(dead_code)]
-pub const PERM_LOGIN: i32 = 1 << 0; // login only
-#[allow(dead_code)]
-pub const PERM_POST_VIEW: i32 = 1 << 1; // view all posts (such as hidden post)
-#[allow(dead_code)]
-pub const PERM_POST_EDIT: i32 = 1 << 2; // create & edit post
-#[allow(dead_code)]
-pub const PERM_POST_DELETE: i32 = 1 << 3; // delete post
-#[allow(dead_code)]
-pub const PERM_CATEGORY_MANAGE: i32 = 1 << 4; // manage category
-#[allow(dead_code)]
-pub const PERM_COMMENT_MANAGE: i32 = 1 << 5; // manage category
+pub use crate::types::{
+	PERM_ALL, PERM_AUDIT_VIEW, PERM_CATEGORY_MANAGE, PERM_COMMENT_MANAGE, PERM_FILE_MANAGE,
+	PERM_LOGIN, PERM_POST_DELETE, PERM_POST_EDIT, PERM_POST_VIEW, PERM_SETTINGS_MANAGE,
+};
+
+/// Permission presets, used by command line interface
+pub const ROLES: &[(&str, i32)] = &[
+	("admin", PERM_ALL),
+	(
+		"editor",
+		PERM_LOGIN
+			| PERM_POST_VIEW
+			| PERM_POST_EDIT
+			| PERM_POST_DELETE
+			| PERM_CATEGORY_MANAGE
+			| PERM_COMMENT_MANAGE
+			| PERM_FILE_MANAGE,
+	),
+	("author", PERM_LOGIN | PERM_POST_VIEW | PERM_POST_EDIT),
+	("none", 0),
+];
+
+/// Permission bits with readable names, in the order shown in admin panel
+pub const PERMISSIONS: &[(i32, &str)] = &[
+	(PERM_LOGIN, "Login"),
+	(PERM_POST_VIEW, "View posts"),
+	(PERM_POST_EDIT, "Edit posts"),
+	(PERM_POST_DELETE, "Delete posts"),
+	(PERM_CATEGORY_MANAGE, "Manage categories"),
+	(PERM_COMMENT_MANAGE, "Manage comments"),
+	(PERM_AUDIT_VIEW, "View audit log"),
+	(PERM_FILE_MANAGE, "Manage all files"),
+	(PERM_SETTINGS_MANAGE, "Manage settings"),
+];
 
 #[derive(Identifiable, Debug, Queryable, Clone, Serialize)]
 #[primary_key(id)]
@@ -38,11 +60,67 @@ pub struct User {
 	pub modified_at: DateTime<Utc>,
 	pub last_login_time: DateTime<Utc>,
 	pub status: UserStatus,
+	pub oidc_subject: Option<String>,
 }
 impl User {
 	insert!(user, NewUser);
 	find_pk!(user);
 	find_one_by!(user, find_by_username, username as &str);
+	find_one_by!(user, find_by_username_lower, username_lower as &str);
+	find_one_by!(user, find_by_email_lower, email_lower as &str);
+	find_one_by!(user, find_by_oidc_subject, oidc_subject as &str);
+
+	pub fn find_by_ids(db: &Database, ids: Vec<i32>) -> Result<Vec<Self>> {
+		user::table
+			.filter(user::id.eq_any(ids))
+			.load::<Self>(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	pub fn find_all(db: &Database) -> Result<Vec<Self>> {
+		user::table
+			.order(user::id.asc())
+			.load::<Self>(&db.conn()?)
+			.map_err(Error::from)
+	}
+
+	pub fn create(
+		db: &Database,
+		username: &str,
+		email: &str,
+		name: &str,
+		password: &str,
+		permission: i32,
+	) -> Result<Self> {
+		Self::insert(
+			db,
+			NewUser {
+				username: username.to_owned(),
+				email: email.to_owned(),
+				username_lower: username.to_lowercase(),
+				email_lower: email.to_lowercase(),
+				password_hash: Self::generate_password_hash(password)?,
+				name: name.to_owned(),
+				permission: permission,
+			},
+		)
+	}
+
+	pub fn set_permission(&self, db: &Database, permission: i32) -> Result<()> {
+		diesel::update(self)
+			.set(user::permission.eq(permission))
+			.execute(&db.conn()?)
+			.map(|_| ())
+			.map_err(Error::from)
+	}
+
+	pub fn update_last_login_time(&self, db: &Database) -> Result<()> {
+		diesel::update(self)
+			.set(user::last_login_time.eq(Utc::now()))
+			.execute(&db.conn()?)
+			.map(|_| ())
+			.map_err(Error::from)
+	}
 
 	pub fn generate_password_hash(pwd: &str) -> Result<String> {
 		bcrypt::hash(pwd, 12).map_err(Error::from)
@@ -56,6 +134,14 @@ impl User {
 			.map_err(Error::from)
 	}
 
+	pub fn set_oidc_subject(&self, db: &Database, subject: Option<&str>) -> Result<()> {
+		diesel::update(self)
+			.set(user::oidc_subject.eq(subject))
+			.execute(&db.conn()?)
+			.map(|_| ())
+			.map_err(Error::from)
+	}
+
 	pub fn verify_password_hash(&self, pwd: &str) -> bool {
 		bcrypt::verify(pwd, self.password_hash.as_ref()).unwrap_or(false)
 	}
@@ -71,6 +157,18 @@ impl User {
 		}
 	}
 
+	/// Unlike `has_permission`, every bit of `perm` is needed
+	pub fn has_all_permissions(&self, perm: i32) -> bool {
+		(self.permission & perm) == perm
+	}
+
+	pub fn check_all_permissions(&self, perm: i32) -> Result<()> {
+		match self.has_all_permissions(perm) {
+			true => Ok(()),
+			false => Err(Error::UserHasNoPermission),
+		}
+	}
+
 	pub fn to_session_info(&self) -> UserSessionInfo {
 		UserSessionInfo {
 			id: self.id,
@@ -80,7 +178,7 @@ impl User {
 }
 
 use crate::interfaces::models::User as UserInterface;
-impl UserInterface for RepositoryWrapper<User, Box<Database>> {
+impl UserInterface for RepositoryWrapper<User, Repository> {
 	fn id(&self) -> i32 {
 		self.0.id
 	}
@@ -133,7 +231,17 @@ use rocket::outcome::IntoOutcome;
 use rocket::request::{FromRequest, Outcome};
 impl<'a, 'r> FromRequest<'a, 'r> for User {
 	type Error = ();
+	/// Requests with `Authorization: Bearer` header are authenticated by API token only,
+	/// otherwise session cookie is used.
 	fn from_request(request: &'a rocket::request::Request<'r>) -> Outcome<User, ()> {
+		use super::api_token;
+		if api_token::bearer_token(request).is_some() {
+			return match &api_token::bearer_auth(request).0 {
+				Some((user, _)) => Outcome::Success(user.clone()),
+				None => Outcome::Failure((rocket::http::Status::Unauthorized, ())),
+			};
+		}
+
 		let db = request.guard::<rocket::State<Box<Database>>>()?;
 		let session: SessionInfo = request.guard::<SessionInfo>()?;
 		session
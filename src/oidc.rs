@@ -0,0 +1,587 @@
+//! OpenID Connect login (authorization code flow with PKCE).
+//!
+//! The ID token is taken straight from the token endpoint over a direct
+//! connection, so per OpenID Connect Core 1.0 section 3.1.3.7 the TLS server
+//! validation is used in place of checking the token signature. Its issuer and
+//! audience are still checked against the configuration, and so is the issuer
+//! of the discovered metadata.
+
+use crate::{
+	db::Database,
+	models::{
+		self,
+		user::{NewUser, User, UserStatus},
+	},
+};
+use chrono::Utc;
+use data_encoding::BASE64URL_NOPAD;
+use reqwest::Url;
+use serde_derive::*;
+use sha2::{Digest, Sha256};
+use std::{env, fmt, sync::RwLock, time::Duration};
+use uuid::Uuid;
+
+/// Name of the private cookie which keeps `AuthorizationState` between redirect and callback
+pub const STATE_COOKIE_NAME: &str = "oidc_state";
+
+#[derive(Debug)]
+pub enum Error {
+	Http(reqwest::Error),
+	SerdeJson(serde_json::Error),
+	Model(models::Error),
+	Invalid(&'static str),
+}
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::Http(_) => write!(f, "Failed to contact identity provider"),
+			Error::SerdeJson(_) => write!(f, "Invalid response from identity provider"),
+			Error::Model(_) => write!(f, "Database error"),
+			Error::Invalid(reason) => write!(f, "{}", reason),
+		}
+	}
+}
+impl From<reqwest::Error> for Error {
+	fn from(e: reqwest::Error) -> Self {
+		Error::Http(e)
+	}
+}
+impl From<serde_json::Error> for Error {
+	fn from(e: serde_json::Error) -> Self {
+		Error::SerdeJson(e)
+	}
+}
+impl From<models::Error> for Error {
+	fn from(e: models::Error) -> Self {
+		Error::Model(e)
+	}
+}
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub struct OidcConfig {
+	/// Issuer URL, `{issuer}/.well-known/openid-configuration` will be used for discovery
+	pub issuer: String,
+	pub client_id: String,
+	pub client_secret: String,
+	/// Full URL of `routes::user::oidc_callback`, must be registered on the provider
+	pub redirect_url: String,
+	/// Permission for users created on first login, no user will be created if `None`
+	pub default_permission: Option<i32>,
+}
+impl OidcConfig {
+	/// Returns `None` if OIDC login is not configured
+	pub fn from_env() -> Option<Self> {
+		Some(Self {
+			issuer: env::var("SOHABLOG_OIDC_ISSUER").ok()?,
+			client_id: env::var("SOHABLOG_OIDC_CLIENT_ID").ok()?,
+			client_secret: env::var("SOHABLOG_OIDC_CLIENT_SECRET").unwrap_or_default(),
+			redirect_url: env::var("SOHABLOG_OIDC_REDIRECT_URL").ok()?,
+			default_permission: env::var("SOHABLOG_OIDC_DEFAULT_PERMISSION")
+				.ok()
+				.and_then(|s| s.parse::<i32>().ok()),
+		})
+	}
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+	issuer: String,
+	authorization_endpoint: String,
+	token_endpoint: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+	id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+	Single(String),
+	Multiple(Vec<String>),
+}
+impl Audience {
+	fn contains(&self, client_id: &str) -> bool {
+		match self {
+			Audience::Single(s) => s == client_id,
+			Audience::Multiple(v) => v.iter().any(|s| s == client_id),
+		}
+	}
+
+	fn is_multiple(&self) -> bool {
+		match self {
+			Audience::Single(_) => false,
+			Audience::Multiple(v) => v.len() > 1,
+		}
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+	iss: String,
+	aud: Audience,
+	/// Authorized party, required to be us when there are several audiences
+	azp: Option<String>,
+	exp: i64,
+	nonce: Option<String>,
+	pub sub: String,
+	pub email: Option<String>,
+	pub email_verified: Option<bool>,
+	pub preferred_username: Option<String>,
+	pub name: Option<String>,
+}
+
+/// Kept in a private cookie between redirecting to the provider and the callback
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthorizationState {
+	pub state: String,
+	pub nonce: String,
+	pub code_verifier: String,
+}
+impl AuthorizationState {
+	fn new() -> Self {
+		Self {
+			state: Uuid::new_v4().to_simple().to_string(),
+			nonce: Uuid::new_v4().to_simple().to_string(),
+			code_verifier: format!(
+				"{}{}",
+				Uuid::new_v4().to_simple(),
+				Uuid::new_v4().to_simple()
+			),
+		}
+	}
+
+	fn code_challenge(&self) -> String {
+		BASE64URL_NOPAD.encode(&Sha256::digest(self.code_verifier.as_bytes()))
+	}
+}
+
+pub struct OidcClient {
+	config: OidcConfig,
+	http: reqwest::Client,
+	/// Discovered lazily, so the blog still starts when the provider is down
+	metadata: RwLock<Option<ProviderMetadata>>,
+}
+impl OidcClient {
+	pub fn new(config: OidcConfig) -> Self {
+		Self {
+			config: config,
+			http: reqwest::Client::builder()
+				.timeout(Duration::from_secs(10))
+				.build()
+				.expect("Failed to create HTTP client"),
+			metadata: RwLock::new(None),
+		}
+	}
+
+	pub fn from_env() -> Option<Self> {
+		OidcConfig::from_env().map(Self::new)
+	}
+
+	fn metadata(&self) -> Result<ProviderMetadata> {
+		if let Some(m) = self.metadata.read().unwrap().as_ref() {
+			return Ok(m.clone());
+		}
+		let url = format!(
+			"{}/.well-known/openid-configuration",
+			self.config.issuer.trim_end_matches('/')
+		);
+		let mut res = self.http.get(&url).send()?.error_for_status()?;
+		let metadata: ProviderMetadata = serde_json::from_str(&res.text()?)?;
+		// a provider may only speak for its own issuer, see OpenID Connect Discovery 1.0 section 4.3
+		if metadata.issuer != self.config.issuer {
+			return Err(Error::Invalid("Identity provider issuer mismatch"));
+		}
+		*self.metadata.write().unwrap() = Some(metadata.clone());
+		Ok(metadata)
+	}
+
+	/// Returns the URL to redirect to, and the state which should be checked in callback
+	pub fn authorization_url(&self) -> Result<(String, AuthorizationState)> {
+		let metadata = self.metadata()?;
+		let state = AuthorizationState::new();
+		let url = Url::parse_with_params(
+			&metadata.authorization_endpoint,
+			&[
+				("response_type", "code"),
+				("scope", "openid email profile"),
+				("client_id", self.config.client_id.as_str()),
+				("redirect_uri", self.config.redirect_url.as_str()),
+				("state", state.state.as_str()),
+				("nonce", state.nonce.as_str()),
+				("code_challenge", state.code_challenge().as_str()),
+				("code_challenge_method", "S256"),
+			],
+		)
+		.map_err(|_| Error::Invalid("Invalid authorization endpoint"))?;
+		Ok((url.into_string(), state))
+	}
+
+	/// Exchanges `code` for an ID token and validates its claims
+	pub fn exchange_code(&self, code: &str, state: &AuthorizationState) -> Result<IdTokenClaims> {
+		let metadata = self.metadata()?;
+		let mut res = self
+			.http
+			.post(&metadata.token_endpoint)
+			.form(&[
+				("grant_type", "authorization_code"),
+				("code", code),
+				("redirect_uri", self.config.redirect_url.as_str()),
+				("client_id", self.config.client_id.as_str()),
+				("client_secret", self.config.client_secret.as_str()),
+				("code_verifier", state.code_verifier.as_str()),
+			])
+			.send()?
+			.error_for_status()?;
+		let token: TokenResponse = serde_json::from_str(&res.text()?)?;
+
+		let payload = token
+			.id_token
+			.split('.')
+			.nth(1)
+			.ok_or(Error::Invalid("Malformed ID token"))?;
+		let payload = BASE64URL_NOPAD
+			.decode(payload.trim_end_matches('=').as_bytes())
+			.map_err(|_| Error::Invalid("Malformed ID token"))?;
+		let claims: IdTokenClaims = serde_json::from_slice(&payload)?;
+
+		if claims.iss != self.config.issuer {
+			return Err(Error::Invalid("ID token issuer mismatch"));
+		}
+		if !claims.aud.contains(&self.config.client_id)
+			|| (claims.aud.is_multiple() && claims.azp.as_ref() != Some(&self.config.client_id))
+		{
+			return Err(Error::Invalid("ID token audience mismatch"));
+		}
+		if claims.exp <= Utc::now().timestamp() {
+			return Err(Error::Invalid("ID token expired"));
+		}
+		if claims.nonce.as_ref() != Some(&state.nonce) {
+			return Err(Error::Invalid("ID token nonce mismatch"));
+		}
+		Ok(claims)
+	}
+
+	/// Finds the user by subject, then by verified email (and binds the subject to it).
+	/// A new user will be created if `default_permission` is set. Deleted users are rejected
+	/// like in password login.
+	pub fn map_user(&self, db: &Database, claims: &IdTokenClaims) -> Result<User> {
+		let active = |u: User| match u.status {
+			UserStatus::Normal => Ok(u),
+			_ => Err(Error::Invalid("This account is disabled")),
+		};
+		match User::find_by_oidc_subject(db, &claims.sub) {
+			Ok(u) => return active(u),
+			Err(models::Error::NotFound) => (),
+			Err(e) => return Err(e.into()),
+		}
+		let verified_email = claims
+			.email
+			.as_ref()
+			.filter(|_| claims.email_verified.unwrap_or(false))
+			.map(|s| s.trim().to_lowercase());
+		if let Some(email) = &verified_email {
+			match User::find_by_email_lower(db, email) {
+				Ok(u) => {
+					let u = active(u)?;
+					u.set_oidc_subject(db, Some(&claims.sub))?;
+					return Ok(u);
+				}
+				Err(models::Error::NotFound) => (),
+				Err(e) => return Err(e.into()),
+			}
+		}
+
+		let permission = self
+			.config
+			.default_permission
+			.ok_or(Error::Invalid("No account is linked to this identity"))?;
+		let email = verified_email.ok_or(Error::Invalid(
+			"A verified email is required to create an account",
+		))?;
+		let base_username = claims
+			.preferred_username
+			.to_owned()
+			.unwrap_or_else(|| email.split('@').next().unwrap_or("user").to_owned())
+			.chars()
+			.filter(|c| c.is_alphanumeric() || *c == '_' || *c == '-')
+			.take(56)
+			.collect::<String>();
+		let base_username = if base_username.is_empty() {
+			String::from("user")
+		} else {
+			base_username
+		};
+		let mut username = base_username.to_owned();
+		let mut suffix = 1;
+		while User::find_by_username_lower(db, &username.to_lowercase()).is_ok() {
+			suffix += 1;
+			username = format!("{}-{}", base_username, suffix);
+		}
+
+		let user = User::insert(
+			db,
+			NewUser {
+				username_lower: username.to_lowercase(),
+				name: claims.name.to_owned().unwrap_or(username.to_owned()),
+				username: username,
+				email_lower: email.to_owned(),
+				email: email,
+				password_hash: String::new(), // password login is impossible for this user
+				permission: permission,
+			},
+		)?;
+		user.set_oidc_subject(db, Some(&claims.sub))?;
+		Ok(User::find(db, user.id)?)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::{json, Value};
+	use std::{
+		collections::HashMap,
+		io::{BufRead, BufReader, Read, Write},
+		net::TcpListener,
+		sync::{Arc, Mutex},
+		thread,
+	};
+
+	const CLIENT_ID: &str = "sohablog";
+
+	/// Discovery and token endpoints on a local port, which records bodies posted to it
+	struct MockIssuer {
+		url: String,
+		bodies: Arc<Mutex<Vec<String>>>,
+	}
+	impl MockIssuer {
+		/// `metadata_issuer` and `claims` of the ID token are made from the URL of the mock
+		fn start<I, C>(metadata_issuer: I, claims: C) -> Self
+		where
+			I: FnOnce(&str) -> String,
+			C: FnOnce(&str) -> Value,
+		{
+			let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+			let url = format!("http://{}", listener.local_addr().unwrap());
+			let metadata = json!({
+				"issuer": metadata_issuer(&url),
+				"authorization_endpoint": format!("{}/authorize", url),
+				"token_endpoint": format!("{}/token", url),
+			});
+			let id_token = format!(
+				"{}.{}.signature",
+				BASE64URL_NOPAD.encode(br#"{"alg":"RS256"}"#),
+				BASE64URL_NOPAD.encode(claims(&url).to_string().as_bytes())
+			);
+			let token = json!({ "id_token": id_token, "token_type": "Bearer" });
+			let bodies = Arc::new(Mutex::new(Vec::new()));
+			let recorded = bodies.clone();
+			thread::spawn(move || {
+				for stream in listener.incoming() {
+					let mut stream = stream.unwrap();
+					let mut reader = BufReader::new(stream.try_clone().unwrap());
+					let mut request_line = String::new();
+					reader.read_line(&mut request_line).unwrap();
+					let mut length = 0;
+					loop {
+						let mut line = String::new();
+						reader.read_line(&mut line).unwrap();
+						let line = line.trim_end().to_lowercase();
+						if line.is_empty() {
+							break;
+						}
+						if line.starts_with("content-length:") {
+							length = line["content-length:".len()..].trim().parse().unwrap();
+						}
+					}
+					let mut body = vec![0; length];
+					reader.read_exact(&mut body).unwrap();
+					recorded
+						.lock()
+						.unwrap()
+						.push(String::from_utf8(body).unwrap());
+
+					let (status, body) = match request_line.split(' ').nth(1).unwrap_or("") {
+						"/.well-known/openid-configuration" => ("200 OK", metadata.to_string()),
+						"/token" => ("200 OK", token.to_string()),
+						_ => ("404 Not Found", String::from("{}")),
+					};
+					let _ = write!(
+						stream,
+						"HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+						status,
+						body.len(),
+						body
+					);
+				}
+			});
+			Self {
+				url: url,
+				bodies: bodies,
+			}
+		}
+
+		/// ID token of `claims` changed by `f`
+		fn with_claims<F: FnOnce(&mut Value)>(state: &AuthorizationState, f: F) -> Self {
+			let nonce = state.nonce.to_owned();
+			Self::start(
+				|url| url.to_owned(),
+				move |url| {
+					let mut claims = json!({
+						"iss": url,
+						"aud": CLIENT_ID,
+						"exp": Utc::now().timestamp() + 300,
+						"nonce": nonce,
+						"sub": "subject",
+						"email": "soha@example.com",
+						"email_verified": true,
+					});
+					f(&mut claims);
+					claims
+				},
+			)
+		}
+
+		fn client(&self) -> OidcClient {
+			OidcClient::new(OidcConfig {
+				issuer: self.url.to_owned(),
+				client_id: String::from(CLIENT_ID),
+				client_secret: String::from("secret"),
+				redirect_url: String::from("https://blog.example.com/user/login/oidc/callback"),
+				default_permission: None,
+			})
+		}
+	}
+
+	fn rejected(result: Result<IdTokenClaims>, reason: &str) {
+		match result {
+			Err(Error::Invalid(r)) => assert_eq!(r, reason),
+			other => panic!("Expected `{}`, got {:?}", reason, other),
+		}
+	}
+
+	#[test]
+	fn code_challenge_is_s256_of_verifier() {
+		let state = AuthorizationState {
+			state: String::new(),
+			nonce: String::new(),
+			code_verifier: String::from("dBjftJeZ4CVP-mJ92IfOuQoxa6wuYLHKvUstkAd2Q1Y"),
+		};
+		assert_eq!(
+			state.code_challenge(),
+			"EukaeoczB1kXWoB5mlfzRAQ6YQn_66Ks2QeNpzHbF4s"
+		);
+	}
+
+	#[test]
+	fn authorization_url_uses_discovered_endpoint_and_pkce() {
+		let mock = MockIssuer::start(|url| url.to_owned(), |_| Value::Null);
+		let (url, state) = mock.client().authorization_url().unwrap();
+		let url = Url::parse(&url).unwrap();
+		assert_eq!(
+			url.as_str().split('?').next(),
+			Some(format!("{}/authorize", mock.url).as_str())
+		);
+		let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+		assert_eq!(params["client_id"], CLIENT_ID);
+		assert_eq!(params["state"], state.state);
+		assert_eq!(params["nonce"], state.nonce);
+		assert_eq!(params["code_challenge"], state.code_challenge());
+		assert_eq!(params["code_challenge_method"], "S256");
+		assert!(!params.contains_key("code_verifier"));
+	}
+
+	#[test]
+	fn discovery_rejects_another_issuer() {
+		let mock = MockIssuer::start(
+			|_| String::from("https://evil.example.com"),
+			|_| Value::Null,
+		);
+		match mock.client().authorization_url() {
+			Err(Error::Invalid(r)) => assert_eq!(r, "Identity provider issuer mismatch"),
+			other => panic!(
+				"Expected issuer mismatch, got {:?}",
+				other.map(|(url, _)| url)
+			),
+		}
+	}
+
+	#[test]
+	fn callback_sends_code_verifier_and_accepts_valid_token() {
+		let state = AuthorizationState::new();
+		let mock = MockIssuer::with_claims(&state, |_| ());
+		let claims = mock.client().exchange_code("the-code", &state).unwrap();
+		assert_eq!(claims.sub, "subject");
+		assert_eq!(
+			claims.email.as_ref().map(|s| s.as_str()),
+			Some("soha@example.com")
+		);
+
+		let bodies = mock.bodies.lock().unwrap();
+		let form: HashMap<String, String> =
+			Url::parse(&format!("http://x/?{}", bodies.last().unwrap()))
+				.unwrap()
+				.query_pairs()
+				.into_owned()
+				.collect();
+		assert_eq!(form["grant_type"], "authorization_code");
+		assert_eq!(form["code"], "the-code");
+		assert_eq!(form["code_verifier"], state.code_verifier);
+		assert_eq!(form["client_id"], CLIENT_ID);
+	}
+
+	#[test]
+	fn callback_rejects_another_issuer() {
+		let state = AuthorizationState::new();
+		let mock =
+			MockIssuer::with_claims(&state, |c| c["iss"] = json!("https://evil.example.com"));
+		rejected(
+			mock.client().exchange_code("code", &state),
+			"ID token issuer mismatch",
+		);
+	}
+
+	#[test]
+	fn callback_rejects_another_audience() {
+		let state = AuthorizationState::new();
+		let mock = MockIssuer::with_claims(&state, |c| c["aud"] = json!("someone-else"));
+		rejected(
+			mock.client().exchange_code("code", &state),
+			"ID token audience mismatch",
+		);
+
+		let mock =
+			MockIssuer::with_claims(&state, |c| c["aud"] = json!([CLIENT_ID, "someone-else"]));
+		rejected(
+			mock.client().exchange_code("code", &state),
+			"ID token audience mismatch",
+		);
+
+		let mock = MockIssuer::with_claims(&state, |c| {
+			c["aud"] = json!([CLIENT_ID, "someone-else"]);
+			c["azp"] = json!(CLIENT_ID);
+		});
+		assert!(mock.client().exchange_code("code", &state).is_ok());
+	}
+
+	#[test]
+	fn callback_rejects_expired_token_and_another_nonce() {
+		let state = AuthorizationState::new();
+		let mock =
+			MockIssuer::with_claims(&state, |c| c["exp"] = json!(Utc::now().timestamp() - 1));
+		rejected(
+			mock.client().exchange_code("code", &state),
+			"ID token expired",
+		);
+
+		let mock = MockIssuer::with_claims(&state, |c| c["nonce"] = json!("replayed"));
+		rejected(
+			mock.client().exchange_code("code", &state),
+			"ID token nonce mismatch",
+		);
+	}
+}
@@ -1,5 +1,12 @@
 pub use sohablog_lib::render::*;
+use crate::{
+	db::Database,
+	models::file_derivative::FileDerivative,
+	storage::StorageManager,
+};
 use comrak::{self, ComrakOptions};
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
 use std::io::{Result as IoResult, Write};
 
 /// Options for `comrak` which is a Markdown parser
@@ -21,11 +28,82 @@ const COMRAK_OPTIONS: ComrakOptions = ComrakOptions {
 	ext_description_lists: true,
 };
 
-#[derive(Default, Debug)]
-pub struct RenderFunctions;
+lazy_static! {
+	static ref IMG_TAG: Regex = Regex::new(r#"<img src="([^"]+)"([^>]*)>"#).unwrap();
+}
+
+#[derive(Default)]
+pub struct RenderFunctions {
+	/// For finding resized copies of uploaded images, no `srcset` is emitted without it
+	images: Option<(Box<Database>, StorageManager)>,
+}
+impl RenderFunctions {
+	pub fn with_images(db: Box<Database>, storage: StorageManager) -> Self {
+		Self {
+			images: Some((db, storage)),
+		}
+	}
+
+	/// Adds `srcset` to images in rendered HTML, and wraps them with `<picture>` if WebP copies exist
+	fn add_srcset(&self, html: String) -> String {
+		if self.images.is_none() {
+			return html;
+		}
+		IMG_TAG.replace_all(&html, |caps: &Captures| {
+			let url = caps[1].replace("&amp;", "&");
+			let (srcset, webp_srcset) = self.srcsets(&url);
+			let mut img = match srcset {
+				Some(srcset) => format!(r#"<img src="{}" srcset="{}"{}>"#, &caps[1], srcset, &caps[2]),
+				None => caps[0].to_owned(),
+			};
+			if let Some(srcset) = webp_srcset {
+				img = format!(
+					r#"<picture><source type="image/webp" srcset="{}" />{}</picture>"#,
+					srcset, img
+				);
+			}
+			img
+		})
+		.into_owned()
+	}
+
+	/// `srcset` of an uploaded image at `url` without and with WebP, from one query
+	fn srcsets(&self, url: &str) -> (Option<String>, Option<String>) {
+		let (db, storage) = match self.images.as_ref() {
+			Some(images) => images,
+			None => return (None, None),
+		};
+		let (file, derivatives) =
+			match FileDerivative::find_with_file_by_keys(db, &storage.keys_from_url(url)) {
+				Ok(Some(found)) if found.0.is_image() => found,
+				_ => return (None, None),
+			};
+		let srcset = |webp: bool| {
+			let mut items: Vec<(i32, String)> = derivatives
+				.iter()
+				.filter(|d| (d.mime == "image/webp") == webp)
+				.filter_map(|d| storage.url(&d.key).ok().map(|u| (d.width, u)))
+				.collect();
+			if items.is_empty() {
+				return None;
+			}
+			if !webp {
+				items.push((file.width?, url.to_owned()));
+			}
+			Some(
+				items
+					.iter()
+					.map(|(w, u)| format!("{} {}w", u.replace('"', "%22"), w))
+					.collect::<Vec<String>>()
+					.join(", "),
+			)
+		};
+		(srcset(false), srcset(true))
+	}
+}
 impl RenderHelper for RenderFunctions {
 	fn markdown_to_html(&self, s: &str) -> String {
-		comrak::markdown_to_html(s, &COMRAK_OPTIONS)
+		self.add_srcset(comrak::markdown_to_html(s, &COMRAK_OPTIONS))
 	}
 	fn nl2br(&self, s: &str) -> String {
 		s.replace("\r\n", "\n").replace("\r", "\n").replace("\n", "<br />")
@@ -56,6 +134,13 @@ impl RenderHelper for RenderFunctions {
 			)
 		)
 	}
+	fn srcset(&self, url: &str, webp: bool) -> Option<String> {
+		let (srcset, webp_srcset) = self.srcsets(url);
+		match webp {
+			true => webp_srcset,
+			false => srcset,
+		}
+	}
 }
 
 /// call wrapped function and write them as HTML
@@ -87,13 +172,180 @@ macro_rules! render {
 }
 
 pub mod theme {
-	use crate::{theme::templates, util::GlobalContext};
+	use crate::{
+		models::user::{self, User},
+		plugin::{
+			Archive, PluginManager, PluginMetadata, PluginType, Theme, THEME_TRAIT_VERSION,
+		},
+		theme::templates,
+		util::GlobalContext,
+	};
 	use sohablog_lib::{
 		interfaces::models::{Author, Content},
 		render::RenderResult,
-		utils::{Page, StaticFile, TemplateContext},
+		utils::{Page, SessionInfo, StaticFile, TemplateContext},
+	};
+	use std::{
+		io::{Result, Write},
+		sync::RwLock,
 	};
-	use std::io::Result;
+
+	/// Name of the theme compiled into the server, used if no theme plugin has this identity
+	pub const BUILTIN_NAME: &str = "my-notebook";
+	pub const BUILTIN_DESCRIPTION: &str = "Theme shipped with SOHABlog";
+
+	/// Name of the theme shown to visitors, can be changed in admin without restart
+	pub struct ActiveTheme(RwLock<String>);
+	impl ActiveTheme {
+		pub fn new(name: String) -> Self {
+			Self(RwLock::new(name))
+		}
+
+		pub fn get(&self) -> String {
+			self.0.read().unwrap().to_owned()
+		}
+
+		pub fn set(&self, name: String) {
+			*self.0.write().unwrap() = name;
+		}
+	}
+
+	pub fn exists(plugin_manager: &PluginManager, name: &String) -> bool {
+		name == BUILTIN_NAME || plugin_manager.get_theme(name).is_some()
+	}
+
+	/// Theme previewed by the session if it's still allowed and loaded, or the active one
+	pub fn resolve(
+		active: &ActiveTheme,
+		plugin_manager: &PluginManager,
+		user: Option<&User>,
+		session_info: &SessionInfo,
+	) -> String {
+		session_info
+			.preview_theme
+			.as_ref()
+			.filter(|_| user.map_or(false, |u| u.has_permission(user::PERM_SETTINGS_MANAGE)))
+			.filter(|name| exists(plugin_manager, name))
+			.map(|name| name.to_owned())
+			.unwrap_or_else(|| active.get())
+	}
+
+	/// Templates under `templates/theme`, also used by theme plugins for pages they don't render
+	pub struct BuiltinTheme;
+	pub static BUILTIN: BuiltinTheme = BuiltinTheme;
+	impl PluginMetadata for BuiltinTheme {
+		fn plugin_version(&self) -> u32 {
+			THEME_TRAIT_VERSION
+		}
+		fn name(&self) -> &'static str {
+			BUILTIN_NAME
+		}
+		fn description(&self) -> &'static str {
+			BUILTIN_DESCRIPTION
+		}
+		fn version(&self) -> &'static str {
+			env!("CARGO_PKG_VERSION")
+		}
+		fn r#type(&self) -> PluginType {
+			PluginType::Theme
+		}
+	}
+	impl Theme for BuiltinTheme {
+		fn identity(&self) -> &'static str {
+			BUILTIN_NAME
+		}
+		fn post_list(
+			&self,
+			out: &mut dyn Write,
+			ctx: &TemplateContext,
+			title: &str,
+			page: Page,
+			posts: Vec<Box<dyn Content>>,
+		) -> Result<()> {
+			templates::post_list(out, ctx, title, page, posts)
+		}
+		fn post_show(
+			&self,
+			out: &mut dyn Write,
+			ctx: &TemplateContext,
+			title: &str,
+			post: Box<dyn Content>,
+			previous_author: Option<Box<dyn Author>>,
+		) -> Result<()> {
+			templates::post_show(out, ctx, title, post, previous_author)
+		}
+		fn static_file(&self, name: &str) -> Option<Box<dyn StaticFile>> {
+			templates::statics::StaticFile::get(name).map(|f| Box::new(f) as Box<dyn StaticFile>)
+		}
+		fn page_show(
+			&self,
+			out: &mut dyn Write,
+			ctx: &TemplateContext,
+			title: &str,
+			page: Box<dyn Content>,
+			previous_author: Option<Box<dyn Author>>,
+		) -> Result<()> {
+			templates::page_show(out, ctx, title, page, previous_author)
+		}
+		fn error_page(
+			&self,
+			out: &mut dyn Write,
+			ctx: &TemplateContext,
+			status: u16,
+			message: &str,
+		) -> Result<()> {
+			templates::error(out, ctx, status, message)
+		}
+		fn search_result(
+			&self,
+			out: &mut dyn Write,
+			ctx: &TemplateContext,
+			query: &str,
+			page: Page,
+			posts: Vec<Box<dyn Content>>,
+		) -> Result<()> {
+			templates::search(out, ctx, query, page, posts)
+		}
+		fn archive(
+			&self,
+			out: &mut dyn Write,
+			ctx: &TemplateContext,
+			archive: &Archive,
+			page: Page,
+			posts: Vec<Box<dyn Content>>,
+		) -> Result<()> {
+			templates::archive(out, ctx, archive, page, posts)
+		}
+		fn password_form(
+			&self,
+			out: &mut dyn Write,
+			ctx: &TemplateContext,
+			title: &str,
+			action: &str,
+			wrong_password: bool,
+		) -> Result<()> {
+			templates::password_form(out, ctx, title, action, wrong_password)
+		}
+	}
+
+	/// Theme of the request, the built-in one if the theme plugin is not loaded
+	fn current<'a>(ctx: &'a GlobalContext) -> &'a dyn Theme {
+		match ctx.plugin_manager.get_theme(&ctx.theme_name) {
+			Some(theme) => theme.as_ref(),
+			None => &BUILTIN,
+		}
+	}
+
+	/// Renders with the theme of the request
+	fn render<F>(ctx: &GlobalContext, f: F) -> Result<RenderResult>
+	where
+		F: FnOnce(&dyn Theme, &mut dyn Write, &TemplateContext) -> Result<()>,
+	{
+		let theme_context: TemplateContext = ctx.get_template_context();
+		let mut buf: Vec<u8> = vec![];
+		f(current(ctx), &mut buf, &theme_context)?;
+		Ok(RenderResult(buf))
+	}
 
 	pub fn post_list(
 		ctx: &GlobalContext,
@@ -101,17 +353,9 @@ pub mod theme {
 		page: Page,
 		posts: Vec<Box<dyn Content>>,
 	) -> Result<RenderResult> {
-		let theme_name = &ctx.system_config.theme_name;
-		let theme_context: TemplateContext = ctx.get_template_context();
-		Ok(
-			if let Some(theme) = &ctx.plugin_manager.get_theme(theme_name) {
-				let mut buf: Vec<u8> = vec![];
-				theme.post_list(&mut buf, &theme_context, title, page, posts)?;
-				RenderResult(buf)
-			} else {
-				render!(templates::post_list, &theme_context, title, page, posts)
-			},
-		)
+		render(ctx, |theme, out, tctx| {
+			theme.post_list(out, tctx, title, page, posts)
+		})
 	}
 
 	pub fn post_show(
@@ -120,33 +364,74 @@ pub mod theme {
 		post: Box<dyn Content>,
 		previous_author: Option<Box<dyn Author>>,
 	) -> Result<RenderResult> {
-		let theme_name = &ctx.system_config.theme_name;
-		let theme_context: TemplateContext = ctx.get_template_context();
-		Ok(
-			if let Some(theme) = &ctx.plugin_manager.get_theme(theme_name) {
-				let mut buf: Vec<u8> = vec![];
-				theme.post_show(&mut buf, &theme_context, title, post, previous_author)?;
-				RenderResult(buf)
-			} else {
-				render!(
-					templates::post_show,
-					&theme_context,
-					title,
-					post,
-					previous_author
-				)
-			},
-		)
+		render(ctx, |theme, out, tctx| {
+			theme.post_show(out, tctx, title, post, previous_author)
+		})
+	}
+
+	pub fn page_show(
+		ctx: &GlobalContext,
+		title: &str,
+		page: Box<dyn Content>,
+		previous_author: Option<Box<dyn Author>>,
+	) -> Result<RenderResult> {
+		render(ctx, |theme, out, tctx| {
+			theme.page_show(out, tctx, title, page, previous_author)
+		})
+	}
+
+	pub fn error_page(ctx: &GlobalContext, status: u16, message: &str) -> Result<RenderResult> {
+		render(ctx, |theme, out, tctx| {
+			theme.error_page(out, tctx, status, message)
+		})
+	}
+
+	pub fn search_result(
+		ctx: &GlobalContext,
+		query: &str,
+		page: Page,
+		posts: Vec<Box<dyn Content>>,
+	) -> Result<RenderResult> {
+		render(ctx, |theme, out, tctx| {
+			theme.search_result(out, tctx, query, page, posts)
+		})
+	}
+
+	pub fn archive(
+		ctx: &GlobalContext,
+		archive: &Archive,
+		page: Page,
+		posts: Vec<Box<dyn Content>>,
+	) -> Result<RenderResult> {
+		render(ctx, |theme, out, tctx| {
+			theme.archive(out, tctx, archive, page, posts)
+		})
+	}
+
+	pub fn password_form(
+		ctx: &GlobalContext,
+		title: &str,
+		action: &str,
+		wrong_password: bool,
+	) -> Result<RenderResult> {
+		render(ctx, |theme, out, tctx| {
+			theme.password_form(out, tctx, title, action, wrong_password)
+		})
+	}
+
+	pub enum ThemeStatic {
+		/// Of the built-in theme, its name contains a hash of content
+		Builtin(&'static templates::statics::StaticFile),
+		/// Of a theme plugin, which may be unloaded once the plugin manager is released
+		Plugin(Box<dyn StaticFile>),
 	}
 
-	pub fn get_static(ctx: &GlobalContext, name: &str) -> Option<Box<dyn StaticFile>> {
-		let theme_name = &ctx.system_config.theme_name;
+	pub fn get_static(ctx: &GlobalContext, name: &str) -> Option<ThemeStatic> {
+		let theme_name = &ctx.theme_name;
 		if let Some(theme) = &ctx.plugin_manager.get_theme(theme_name) {
-			theme.static_file(name)
-		} else if let Some(f) = templates::statics::StaticFile::get(name) {
-			Some(Box::new(f) as Box<dyn StaticFile>)
+			theme.static_file(name).map(ThemeStatic::Plugin)
 		} else {
-			None
+			templates::statics::StaticFile::get(name).map(ThemeStatic::Builtin)
 		}
 	}
 }
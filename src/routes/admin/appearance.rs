@@ -0,0 +1,133 @@
+use super::super::error::Error;
+use crate::{
+	models::{
+		audit_log::{self, Audit},
+		setting::{self, Setting},
+		user::{self, User},
+	},
+	render::{
+		theme::{self, ActiveTheme},
+		RenderResult,
+	},
+	templates,
+	util::*,
+};
+use rocket::{http::Cookies, request::LenientForm, response::Redirect, State};
+use rocket_codegen::*;
+
+/// A theme which can be activated, shown in admin
+pub struct ThemeInfo {
+	pub name: String,
+	pub description: String,
+	pub version: String,
+	pub is_builtin: bool,
+}
+
+fn themes(gctx: &GlobalContext) -> Vec<ThemeInfo> {
+	let mut themes = vec![ThemeInfo {
+		name: String::from(theme::BUILTIN_NAME),
+		description: String::from(theme::BUILTIN_DESCRIPTION),
+		version: String::from(env!("CARGO_PKG_VERSION")),
+		is_builtin: true,
+	}];
+	for t in gctx.plugin_manager.themes() {
+		// a theme plugin with the same identity replaces the built-in one
+		if t.identity() == theme::BUILTIN_NAME {
+			themes.remove(0);
+		}
+		themes.push(ThemeInfo {
+			name: String::from(t.identity()),
+			description: String::from(t.description()),
+			version: String::from(t.version()),
+			is_builtin: false,
+		});
+	}
+	themes
+}
+
+#[get("/admin/appearance")]
+pub fn list(
+	gctx: GlobalContext,
+	current_user: User,
+	active: State<ActiveTheme>,
+) -> Result<RenderResult, Error> {
+	current_user.check_permission(user::PERM_SETTINGS_MANAGE)?;
+	let preview = match gctx.is_previewing_theme() {
+		true => Some(gctx.theme_name.to_owned()),
+		false => None,
+	};
+	Ok(render!(
+		templates::admin::appearance,
+		&gctx.get_template_context(),
+		themes(&gctx),
+		&active.get(),
+		preview
+	))
+}
+
+#[derive(Default, FromForm, Debug)]
+pub struct ThemeForm {
+	pub theme: String,
+}
+
+/// Takes effect for every visitor immediately, and is kept after restart
+#[post("/admin/appearance/activate", data = "<form>")]
+pub fn activate(
+	mut gctx: GlobalContext,
+	mut cookies: Cookies,
+	current_user: User,
+	_csrf: CSRFTokenValidation,
+	form: LenientForm<ThemeForm>,
+	active: State<ActiveTheme>,
+	audit: Audit,
+) -> Result<Redirect, Error> {
+	current_user.check_permission(user::PERM_SETTINGS_MANAGE)?;
+	if !theme::exists(&gctx.plugin_manager, &form.theme) {
+		return Err(Error::BadRequest("Theme is not loaded."));
+	}
+	let before = active.get();
+	Setting::set(&gctx.db, setting::KEY_THEME, &form.theme)?;
+	active.set(form.theme.to_owned());
+	audit.log(
+		Some(&current_user),
+		audit_log::ACTION_THEME_ACTIVATE,
+		None,
+		Some(serde_json::json!({ "theme": before })),
+		Some(serde_json::json!({ "theme": form.theme })),
+	);
+	if gctx.session_info.preview_theme.is_some() {
+		gctx.session_info.preview_theme = None;
+		gctx.session_info.persist(&mut cookies, &gctx.system_config);
+	}
+	Ok(Redirect::to(uri!(list)))
+}
+
+/// Only the current session sees the theme, until preview is stopped
+#[post("/admin/appearance/preview", data = "<form>")]
+pub fn preview(
+	mut gctx: GlobalContext,
+	mut cookies: Cookies,
+	current_user: User,
+	_csrf: CSRFTokenValidation,
+	form: LenientForm<ThemeForm>,
+) -> Result<Redirect, Error> {
+	current_user.check_permission(user::PERM_SETTINGS_MANAGE)?;
+	if !theme::exists(&gctx.plugin_manager, &form.theme) {
+		return Err(Error::BadRequest("Theme is not loaded."));
+	}
+	gctx.session_info.preview_theme = Some(form.theme.to_owned());
+	gctx.session_info.persist(&mut cookies, &gctx.system_config);
+	Ok(Redirect::to("/"))
+}
+
+#[post("/admin/appearance/preview/stop")]
+pub fn stop_preview(
+	mut gctx: GlobalContext,
+	mut cookies: Cookies,
+	_current_user: User,
+	_csrf: CSRFTokenValidation,
+) -> Redirect {
+	gctx.session_info.preview_theme = None;
+	gctx.session_info.persist(&mut cookies, &gctx.system_config);
+	Redirect::to(uri!(list))
+}
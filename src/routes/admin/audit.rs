@@ -0,0 +1,197 @@
+use super::super::{error::Error, Page};
+use crate::{
+	db::Database,
+	models::{
+		audit_log::{self, AuditLog, AuditLogFilter},
+		user::{self, User},
+	},
+	render::RenderResult,
+	templates,
+	util::*,
+};
+use chrono::Local;
+use rocket::{
+	http::{uri::Uri, ContentType},
+	response::Response,
+};
+use rocket_codegen::*;
+use std::{collections::HashMap, io::Cursor};
+
+pub const ITEMS_PER_PAGE: i32 = 50;
+
+/// Filters from query string, empty values are ignored
+#[derive(Debug, Default)]
+pub struct FilterQuery {
+	pub action: Option<String>,
+	pub username: Option<String>,
+	pub target_type: Option<String>,
+}
+impl FilterQuery {
+	fn new(
+		action: Option<String>,
+		username: Option<String>,
+		target_type: Option<String>,
+	) -> Self {
+		let non_empty = |s: Option<String>| {
+			s.map(|s| s.trim().to_owned())
+				.filter(|s| s.len() > 0)
+		};
+		Self {
+			action: non_empty(action),
+			username: non_empty(username),
+			target_type: non_empty(target_type),
+		}
+	}
+
+	/// Returns `None` if user in filter doesn't exist, so nothing will match
+	fn to_filter(&self, db: &Database) -> Result<Option<AuditLogFilter>, Error> {
+		let user = match &self.username {
+			Some(name) => match User::find_by_username(db, name) {
+				Ok(u) => Some(u.id),
+				Err(crate::models::Error::NotFound) => return Ok(None),
+				Err(e) => return Err(e.into()),
+			},
+			None => None,
+		};
+		Ok(Some(AuditLogFilter {
+			user: user,
+			action: self.action.to_owned(),
+			target_type: self.target_type.to_owned(),
+		}))
+	}
+
+	/// Query string without `page`, like `action=user.login&username=soha`
+	pub fn to_query_string(&self) -> String {
+		[
+			("action", &self.action),
+			("username", &self.username),
+			("target_type", &self.target_type),
+		]
+		.iter()
+		.filter_map(|(k, v)| {
+			v.as_ref()
+				.map(|v| format!("{}={}", k, Uri::percent_encode(v)))
+		})
+		.collect::<Vec<String>>()
+		.join("&")
+	}
+
+	pub fn is_action(&self, action: &str) -> bool {
+		self.action.as_ref().map(|s| s.as_str()) == Some(action)
+	}
+}
+
+/// Attaches username to each row
+fn with_usernames(
+	db: &Database,
+	logs: Vec<AuditLog>,
+) -> Result<Vec<(AuditLog, Option<String>)>, Error> {
+	let mut ids: Vec<i32> = logs.iter().filter_map(|l| l.user).collect();
+	ids.sort();
+	ids.dedup();
+	let names: HashMap<i32, String> = User::find_by_ids(db, ids)?
+		.into_iter()
+		.map(|u| (u.id, u.username))
+		.collect();
+	Ok(logs
+		.into_iter()
+		.map(|l| {
+			let name = l.user.and_then(|id| names.get(&id).cloned());
+			(l, name)
+		})
+		.collect())
+}
+
+#[get("/admin/audit?<page>&<action>&<username>&<target_type>")]
+pub fn list(
+	gctx: GlobalContext,
+	page: Option<Page>,
+	action: Option<String>,
+	username: Option<String>,
+	target_type: Option<String>,
+	current_user: User,
+) -> Result<RenderResult, Error> {
+	current_user.check_permission(user::PERM_AUDIT_VIEW)?;
+	let mut page = page.unwrap_or_default();
+	let query = FilterQuery::new(action, username, target_type);
+	let logs = match query.to_filter(&gctx.db)? {
+		Some(filter) => {
+			page.calc_total(
+				AuditLog::count(&gctx.db, &filter)? as i32,
+				ITEMS_PER_PAGE,
+			);
+			AuditLog::find_by_filter(&gctx.db, Some(page.range(ITEMS_PER_PAGE)), &filter)?
+		}
+		None => {
+			page.calc_total(0, ITEMS_PER_PAGE);
+			Vec::new()
+		}
+	};
+
+	Ok(render!(
+		templates::admin::audit::list,
+		&gctx.get_template_context(),
+		page,
+		&query,
+		audit_log::ACTIONS,
+		with_usernames(&gctx.db, logs)?
+	))
+}
+
+/// Escapes a CSV field, and prevents it from being treated as formula by spreadsheet programs
+fn csv_field(s: &str) -> String {
+	let s = if s.starts_with(|c| c == '=' || c == '+' || c == '-' || c == '@') {
+		format!("'{}", s)
+	} else {
+		s.to_owned()
+	};
+	if s.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+		format!("\"{}\"", s.replace('"', "\"\""))
+	} else {
+		s
+	}
+}
+
+#[get("/admin/audit/export?<action>&<username>&<target_type>")]
+pub fn export(
+	gctx: GlobalContext,
+	action: Option<String>,
+	username: Option<String>,
+	target_type: Option<String>,
+	current_user: User,
+) -> Result<Response<'static>, Error> {
+	current_user.check_permission(user::PERM_AUDIT_VIEW)?;
+	let query = FilterQuery::new(action, username, target_type);
+	let logs = match query.to_filter(&gctx.db)? {
+		Some(filter) => AuditLog::find_by_filter(&gctx.db, None, &filter)?,
+		None => Vec::new(),
+	};
+
+	let mut csv = String::from("id,time,user,ip,action,target_type,target_id,detail\r\n");
+	for (log, name) in with_usernames(&gctx.db, logs)? {
+		let fields = [
+			log.id.to_string(),
+			log.time.with_timezone(&Local).to_rfc3339(),
+			name.unwrap_or_default(),
+			log.ip.map(|ip| ip.ip().to_string()).unwrap_or_default(),
+			log.action,
+			log.target_type.unwrap_or_default(),
+			log.target_id.map(|id| id.to_string()).unwrap_or_default(),
+			log.detail.unwrap_or_default(),
+		];
+		csv.push_str(
+			&fields
+				.iter()
+				.map(|f| csv_field(f))
+				.collect::<Vec<String>>()
+				.join(","),
+		);
+		csv.push_str("\r\n");
+	}
+
+	Ok(Response::build()
+		.header(ContentType::new("text", "csv"))
+		.raw_header("Content-Disposition", "attachment; filename=\"audit_log.csv\"")
+		.sized_body(Cursor::new(csv))
+		.finalize())
+}
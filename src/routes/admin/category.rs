@@ -2,6 +2,7 @@ use super::super::error::Error;
 use crate::{
 	db::Database,
 	models::{
+		audit_log::{self, Audit},
 		category::{Category, NewCategory},
 		user::{self, User},
 		IntoInterface,
@@ -19,7 +20,7 @@ pub fn list(gctx: GlobalContext, current_user: User) -> Result<RenderResult, Err
 	Ok(render!(
 		templates::admin::category::list,
 		&gctx.get_template_context(),
-		Category::find_all(&gctx.db)?.into_interface(&gctx.db)
+		Category::find_all(&gctx.db)?.into_interface(&gctx.repository())
 	))
 }
 
@@ -38,6 +39,7 @@ pub fn update(
 	form: LenientForm<PostForm>,
 	current_user: User,
 	_csrf: CSRFTokenValidation,
+	audit: Audit,
 ) -> Result<Redirect, Error> {
 	current_user.check_permission(user::PERM_CATEGORY_MANAGE)?;
 
@@ -68,15 +70,30 @@ pub fn update(
 	match form.target {
 		Some(id) => {
 			let mut cat: Category = Category::find(&db, id)?;
+			let before = cat.audit_summary();
 			cat.slug = new_cat.slug;
 			cat.name = new_cat.name;
 			cat.parent = new_cat.parent;
 			cat.description = new_cat.description;
 			cat.order = new_cat.order;
 			cat.update(&db)?;
+			audit.log(
+				Some(&current_user),
+				audit_log::ACTION_CATEGORY_UPDATE,
+				Some(("category", cat.id)),
+				Some(before),
+				Some(cat.audit_summary()),
+			);
 		}
 		None => {
-			Category::insert(&db, new_cat)?;
+			let cat = Category::insert(&db, new_cat)?;
+			audit.log(
+				Some(&current_user),
+				audit_log::ACTION_CATEGORY_CREATE,
+				Some(("category", cat.id)),
+				None,
+				Some(cat.audit_summary()),
+			);
 		}
 	};
 	Ok(Redirect::to(uri!(list)))
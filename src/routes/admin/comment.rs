@@ -1,6 +1,7 @@
 use super::super::{error::Error, ApiResult, JsonOrNormal, Page};
 use crate::{
 	models::{
+		audit_log::{self, Audit},
 		comment::Comment,
 		user::{self, User},
 		IntoInterface,
@@ -37,7 +38,7 @@ pub fn list(
 		&gctx.get_template_context(),
 		page,
 		status,
-		comments.into_interface(&gctx.db)
+		comments.into_interface(&gctx.repository())
 	))
 }
 
@@ -48,12 +49,21 @@ pub fn set_status(
 	status: i32,
 	current_user: User,
 	_csrf: CSRFTokenValidation,
+	audit: Audit,
 ) -> Result<JsonOrNormal<ApiResult<()>, Redirect>, Error> {
 	current_user.check_permission(user::PERM_COMMENT_MANAGE)?;
 	let status = CommentStatus::try_from(status)?;
 	let mut comment: Comment = Comment::find(&gctx.db, id)?;
+	let before = comment.audit_summary();
 	comment.status = status;
 	comment.update(&gctx.db)?;
+	audit.log(
+		Some(&current_user),
+		audit_log::ACTION_COMMENT_SET_STATUS,
+		Some(("comment", comment.id)),
+		Some(before),
+		Some(comment.audit_summary()),
+	);
 	Ok(JsonOrNormal(
 		ApiResult::new((), None, None),
 		Redirect::to(uri!(
@@ -1,72 +1,144 @@
-use super::super::error::Error;
+use super::super::{error::Error, Page};
 use crate::{
 	db::Database,
+	media::{self, ImageConfig},
 	models::{
+		audit_log::{self, Audit},
 		content::{self, Content},
-		file::File,
-		user::User,
+		file::{File, FileFilter},
+		file_derivative::FileDerivative,
+		user::{self, User},
 	},
+	render::RenderResult,
+	storage::StorageManager,
+	templates,
+	upload::{self, UploadConfig},
 	util::*,
 };
 use multipart::server::{
-	save::{SaveResult, SavedData},
+	save::{PartialReason, SaveResult, SavedData},
 	Multipart,
 };
 use rocket::{
-	http::{ContentType, Status},
-	request::State,
+	http::{uri::Uri, ContentType, Status},
+	request::{FormItems, FromForm, LenientForm, State},
+	response::Redirect,
 	Data,
 };
 use rocket_codegen::*;
 use rocket_contrib::json::Json;
-use std::fs;
-use uuid::Uuid;
+use serde_derive::*;
+use std::{collections::HashMap, fs};
+
+pub const ITEMS_PER_PAGE: i32 = 30;
+
+/// `file` and `related_content_id`, with some room for extra fields from browsers
+const MAX_FIELDS: u32 = 8;
+
+/// File with its public URL, the URL depends on storage backend
+#[derive(Serialize, Debug)]
+pub struct FileJson {
+	#[serde(flatten)]
+	pub file: File,
+	pub url: String,
+}
+impl FileJson {
+	fn new(file: File, storage: &StorageManager) -> Result<Self, Error> {
+		Ok(Self {
+			url: storage.url(&file.key)?,
+			file: file,
+		})
+	}
+}
+
+/// Markdown which shows the file in a post, images are embedded and others are linked
+pub fn markdown_snippet(file: &File, url: &str) -> String {
+	let name = file.filename.replace('[', "\\[").replace(']', "\\]");
+	let url = url.replace('(', "%28").replace(')', "%29");
+	if file.mime.starts_with("image/") {
+		format!("![{}]({})", name, url)
+	} else {
+		format!("[{}]({})", name, url)
+	}
+}
 
 #[get("/admin/file/by-content/<content_id>")]
 pub fn find_by_content(
 	content_id: i32,
 	db: State<Box<Database>>,
+	storage: State<StorageManager>,
 	_user: User,
-) -> Result<Json<Vec<File>>, Error> {
+) -> Result<Json<Vec<FileJson>>, Error> {
 	let content: Content = Content::find(&db, content_id)?;
 	if content.status == content::ContentStatus::Deleted
 		|| content.r#type != content::ContentType::Article
 	{
 		return Err(Error::NotFound);
 	}
-	let list = File::find_by_content_id(&db, content_id)?;
+	let list = File::find_by_content_id(&db, content_id)?
+		.into_iter()
+		.map(|f| FileJson::new(f, &storage))
+		.collect::<Result<Vec<FileJson>, Error>>()?;
+	Ok(Json(list))
+}
+
+/// Draft tokens are generated by the editor, see `admin::post::new_get`
+pub fn is_valid_draft_token(token: &str) -> bool {
+	token.len() >= 16 && token.len() <= 64 && token.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+#[get("/admin/file/by-draft/<token>")]
+pub fn find_by_draft(
+	token: String,
+	db: State<Box<Database>>,
+	storage: State<StorageManager>,
+	current_user: User,
+) -> Result<Json<Vec<FileJson>>, Error> {
+	if !is_valid_draft_token(&token) {
+		return Err(Error::NotFound);
+	}
+	let list = File::find_by_draft_token(&db, &token, current_user.id)?
+		.into_iter()
+		.map(|f| FileJson::new(f, &storage))
+		.collect::<Result<Vec<FileJson>, Error>>()?;
 	Ok(Json(list))
 }
 
-// is CSRFTokenValidation needed?
 #[delete("/admin/file/<id>")]
 pub fn delete_by_id(
 	id: i32,
 	db: State<Box<Database>>,
-	system_config: State<SystemConfig>,
-	_user: User,
+	storage: State<StorageManager>,
+	current_user: User,
+	_csrf: CSRFTokenValidation,
+	audit: Audit,
 ) -> Result<Status, Error> {
 	let file: File = File::find(&db, id)?;
-	match fs::remove_file(&file.key.replace("{upload_dir}", &system_config.upload_dir)) {
-		Err(e) => match e.kind() {
-			std::io::ErrorKind::NotFound => (),
-			_ => return Err(Error::Io(e)),
-		},
-		_ => (),
-	}
-	file.delete(&db)?;
+	check_file_permission(&current_user, &file)?;
+	media::remove_file(&db, &storage, &file)?;
+	audit.log(
+		Some(&current_user),
+		audit_log::ACTION_FILE_DELETE,
+		Some(("file", file.id)),
+		Some(file.audit_summary()),
+		None,
+	);
 	Ok(Status::NoContent)
 }
 
-// is CSRFTokenValidation needed?
+/// Token in body is only found if it's before `file`, AJAX uploads should use `X-CSRF-Token` header
 #[post("/admin/file/upload", data = "<data>")]
 pub fn upload(
 	data: Data,
 	content_type: &ContentType,
-	system_config: State<SystemConfig>,
+	storage: State<StorageManager>,
+	image_config: State<ImageConfig>,
+	upload_config: State<UploadConfig>,
 	db: State<Box<Database>>,
 	current_user: User,
-) -> Result<Json<File>, Error> {
+	_csrf: CSRFTokenValidation,
+	audit: Audit,
+) -> Result<Json<FileJson>, Error> {
 	if !content_type.is_form_data() {
 		return Err(Error::BadRequest("Wrong `Content-Type`"));
 	}
@@ -75,69 +147,373 @@ pub fn upload(
 		.find(|&(k, _)| k == "boundary")
 		.ok_or_else(|| Error::BadRequest("No `boundary`"))?;
 
-	match Multipart::with_body(data.open(), boundary).save().temp() {
+	match Multipart::with_body(data.open(), boundary)
+		.save()
+		.size_limit(upload_config.max_size) // checked while reading, so oversized body is never fully saved
+		.count_limit(MAX_FIELDS)
+		.temp()
+	{
 		SaveResult::Full(entries) => {
-			let content_id = entries.fields.get("related_content_id").and_then(|o| {
-				if let SavedData::Text(s) = &o[0].data {
-					s.parse::<i32>().ok()
-				} else {
-					None
-				}
-			});
-			let filename = entries
+			let text_field = |name: &str| {
+				entries.fields.get(name).and_then(|o| {
+					if let SavedData::Text(s) = &o[0].data {
+						Some(s.trim().to_owned())
+					} else {
+						None
+					}
+				})
+			};
+			let content_id = text_field("related_content_id").and_then(|s| s.parse::<i32>().ok());
+			// for posts not saved yet, see `File::link_draft`
+			let draft_token = match content_id {
+				Some(_) => None,
+				None => text_field("draft_token").filter(|s| is_valid_draft_token(s)),
+			};
+			let field = entries
 				.fields
 				.get("file")
 				.and_then(|o| o.iter().next())
-				.ok_or_else(|| Error::BadRequest("File not found in body"))?
+				.ok_or_else(|| Error::BadRequest("File not found in body"))?;
+			let original_filename = field
 				.headers
 				.filename
-				.clone();
-			let original_filename = filename.as_ref()?.to_owned();
-			let extension = filename
-				.and_then(|s| {
-					s.rsplit('.').next().and_then(|e| {
-						if e.chars().any(|c| !c.is_alphanumeric()) {
-							None
-						} else {
-							Some(format!(".{}", e.to_lowercase()))
-						}
-					})
-				})
-				.unwrap_or_default();
-
-			let year_month = chrono::Utc::now().format("%Y%m");
-			let file_key = format!(
-				"{{upload_dir}}/{}/{}{}",
-				&year_month,
-				Uuid::new_v4(),
-				&extension
-			); // file key like `{upload_dir}/201906/mori.love`, this will be saved to db
-			fs::create_dir_all(format!("{}/{}", &system_config.upload_dir, &year_month))?; // create folder like `{upload_dir}/201906`
-
-			let save_path = file_key.replace("{upload_dir}", &system_config.upload_dir); // file full path for writing contents, like `/path/to/upload/201906/mori.love`
-			match entries.fields.get("file")?[0].data {
-				SavedData::Bytes(ref b) => {
-					fs::write(&save_path, b)?;
-				}
-				SavedData::Text(ref s) => {
-					fs::write(&save_path, s)?;
-				}
-				SavedData::File(ref path, _) => {
-					fs::copy(path, &save_path)?;
-				}
+				.clone()
+				.ok_or_else(|| Error::BadRequest("`file` should be a file"))?;
+			let data = match field.data {
+				SavedData::Bytes(ref b) => b.clone(),
+				SavedData::File(ref temp, _) => fs::read(temp)?,
+				SavedData::Text(_) => return Err(Error::BadRequest("`file` should be a file")),
+			};
+
+			let mime = upload::sniff(&data); // never trust the name or `Content-Type` from client
+			if !upload_config.is_allowed(&current_user, mime) {
+				return Err(Error::HttpStatus(Status::UnsupportedMediaType));
 			}
+			let image = media::strip_metadata(&data, &image_config)?; // `None` if not processed
+			let data = match &image {
+				Some(image) => &image.data,
+				None => &data,
+			};
+			let path = StorageManager::new_path(upload::extension_of(mime)); // path like `201906/mori.love`
+			let (file_key, hash, is_new) =
+				media::store_deduplicated(&db, &storage, &path, data, mime)?; // file key like `local:201906/mori.love`, this will be saved to db
 
-			let file = File::create(
-				&db,
+			let mut new_file = File::new(
 				file_key,
 				original_filename,
 				current_user.id,
 				content_id,
-			)?;
+				mime.to_owned(),
+				data.len() as i64,
+				Some(hash),
+			);
+			new_file.draft_token = draft_token;
+			let mut file = File::insert(&db, new_file)?;
+			if let Some(image) = image {
+				let (width, height) = image.dimensions();
+				let (width, height) = (width as i32, height as i32);
+				file.set_dimensions(&db, width, height)?;
+				file.width = Some(width);
+				file.height = Some(height);
+				if is_new {
+					media::generate_derivatives_in_background(
+						&db,
+						&storage,
+						&image_config,
+						file.clone(),
+						image,
+					);
+				} else {
+					media::share_derivatives(&db, &file)?;
+				}
+			}
+			audit.log(
+				Some(&current_user),
+				audit_log::ACTION_FILE_UPLOAD,
+				Some(("file", file.id)),
+				None,
+				Some(file.audit_summary()),
+			);
 
-			Ok(Json(file))
+			Ok(Json(FileJson::new(file, &storage)?))
+		}
+		SaveResult::Partial(_, PartialReason::SizeLimit) => {
+			Err(Error::HttpStatus(Status::PayloadTooLarge))
 		}
 		SaveResult::Partial(_, _) => Err(Error::HttpStatus(Status::NoContent)), // 204 No Content
 		SaveResult::Error(e) => Err(Error::Io(e)),
 	}
 }
+
+/// Filters from query string of media library, empty values are ignored
+#[derive(Debug, Default)]
+pub struct LibraryQuery {
+	pub q: Option<String>,
+	pub username: Option<String>,
+	/// Content ID, or `none` for files not linked to any content
+	pub content: Option<String>,
+}
+impl LibraryQuery {
+	fn new(q: Option<String>, username: Option<String>, content: Option<String>) -> Self {
+		let non_empty = |s: Option<String>| {
+			s.map(|s| s.trim().to_owned())
+				.filter(|s| s.len() > 0)
+		};
+		Self {
+			q: non_empty(q),
+			username: non_empty(username),
+			content: non_empty(content),
+		}
+	}
+
+	/// Returns `None` if nothing can match, e.g. the user doesn't exist.
+	/// Users who can't manage all files only see their own.
+	fn to_filter(&self, db: &Database, current_user: &User) -> Result<Option<FileFilter>, Error> {
+		let mut user = match &self.username {
+			Some(name) => match User::find_by_username(db, name) {
+				Ok(u) => Some(u.id),
+				Err(crate::models::Error::NotFound) => return Ok(None),
+				Err(e) => return Err(e.into()),
+			},
+			None => None,
+		};
+		if !current_user.has_permission(user::PERM_FILE_MANAGE) {
+			if user.is_some() && user != Some(current_user.id) {
+				return Ok(None);
+			}
+			user = Some(current_user.id);
+		}
+		let content = match self.content.as_ref().map(|s| s.as_str()) {
+			Some("none") => Some(None),
+			Some(s) => Some(Some(
+				s.parse::<i32>()
+					.map_err(|_| Error::BadRequest("`content` should be a number or `none`"))?,
+			)),
+			None => None,
+		};
+		Ok(Some(FileFilter {
+			user: user,
+			content: content,
+			filename: self.q.to_owned(),
+		}))
+	}
+
+	/// Query string without `page`
+	pub fn to_query_string(&self) -> String {
+		[
+			("q", &self.q),
+			("username", &self.username),
+			("content", &self.content),
+		]
+		.iter()
+		.filter_map(|(k, v)| {
+			v.as_ref()
+				.map(|v| format!("{}={}", k, Uri::percent_encode(v)))
+		})
+		.collect::<Vec<String>>()
+		.join("&")
+	}
+}
+
+/// A row in media library
+pub struct LibraryItem {
+	pub file: File,
+	pub url: String,
+	/// Smallest resized copy for preview, the original if there's none
+	pub thumbnail: Option<String>,
+	pub uploader: Option<String>,
+	pub markdown: String,
+}
+
+#[get("/admin/file?<page>&<q>&<username>&<content>")]
+pub fn library(
+	gctx: GlobalContext,
+	storage: State<StorageManager>,
+	page: Option<Page>,
+	q: Option<String>,
+	username: Option<String>,
+	content: Option<String>,
+	current_user: User,
+) -> Result<RenderResult, Error> {
+	let mut page = page.unwrap_or_default();
+	let query = LibraryQuery::new(q, username, content);
+	let files = match query.to_filter(&gctx.db, &current_user)? {
+		Some(filter) => {
+			page.calc_total(
+				File::count_by_filter(&gctx.db, &filter)? as i32,
+				ITEMS_PER_PAGE,
+			);
+			File::find_by_filter(&gctx.db, page.range(ITEMS_PER_PAGE), &filter)?
+		}
+		None => {
+			page.calc_total(0, ITEMS_PER_PAGE);
+			Vec::new()
+		}
+	};
+
+	let mut user_ids: Vec<i32> = files.iter().map(|f| f.user).collect();
+	user_ids.sort();
+	user_ids.dedup();
+	let usernames: HashMap<i32, String> = User::find_by_ids(&gctx.db, user_ids)?
+		.into_iter()
+		.map(|u| (u.id, u.username))
+		.collect();
+	let image_ids: Vec<i32> = files.iter().filter(|f| f.is_image()).map(|f| f.id).collect();
+	let mut thumbnails: HashMap<i32, FileDerivative> = HashMap::new();
+	// ordered by width, so the first one of each file is the smallest
+	for derivative in FileDerivative::find_by_file_ids(&gctx.db, &image_ids)? {
+		if derivative.mime != "image/webp" && !thumbnails.contains_key(&derivative.file) {
+			thumbnails.insert(derivative.file, derivative);
+		}
+	}
+	let mut items = Vec::new();
+	for file in files {
+		let url = storage.url(&file.key)?;
+		let thumbnail = if file.is_image() {
+			match thumbnails.get(&file.id) {
+				Some(d) => Some(storage.url(&d.key)?),
+				None => Some(url.to_owned()),
+			}
+		} else {
+			None
+		};
+		items.push(LibraryItem {
+			markdown: markdown_snippet(&file, &url),
+			uploader: usernames.get(&file.user).cloned(),
+			thumbnail: thumbnail,
+			url: url,
+			file: file,
+		});
+	}
+
+	Ok(render!(
+		templates::admin::file::list,
+		&gctx.get_template_context(),
+		page,
+		&query,
+		items,
+		current_user.has_permission(user::PERM_FILE_MANAGE)
+	))
+}
+
+/// Files uploaded within this are not listed as orphans
+const ORPHAN_MIN_AGE_HOURS: i64 = 24;
+
+#[get("/admin/file/orphans")]
+pub fn orphans(
+	gctx: GlobalContext,
+	storage: State<StorageManager>,
+	current_user: User,
+) -> Result<RenderResult, Error> {
+	current_user.check_permission(user::PERM_FILE_MANAGE)?;
+	let mut items = Vec::new();
+	for file in media::find_orphans(&gctx.db, chrono::Duration::hours(ORPHAN_MIN_AGE_HOURS))? {
+		let url = storage.url(&file.key)?;
+		items.push((file, url));
+	}
+	Ok(render!(
+		templates::admin::file::orphans,
+		&gctx.get_template_context(),
+		items
+	))
+}
+
+/// Files of others can only be changed with `PERM_FILE_MANAGE`
+fn check_file_permission(current_user: &User, file: &File) -> Result<(), Error> {
+	if file.user != current_user.id {
+		current_user.check_permission(user::PERM_FILE_MANAGE)?;
+	}
+	Ok(())
+}
+
+/// `id` appears once for each selected file
+#[derive(Default, Debug)]
+pub struct BulkForm {
+	pub ids: Vec<i32>,
+}
+impl<'f> FromForm<'f> for BulkForm {
+	type Error = ();
+	fn from_form(items: &mut FormItems<'f>, _strict: bool) -> Result<Self, ()> {
+		let mut form = Self::default();
+		for item in items {
+			let (key, value) = item.key_value_decoded();
+			if key == "id" {
+				form.ids.push(value.parse::<i32>().map_err(|_| ())?);
+			}
+		}
+		Ok(form)
+	}
+}
+#[post("/admin/file/_delete", data = "<form>")]
+pub fn bulk_delete(
+	db: State<Box<Database>>,
+	storage: State<StorageManager>,
+	form: LenientForm<BulkForm>,
+	current_user: User,
+	_csrf: CSRFTokenValidation,
+	audit: Audit,
+) -> Result<Redirect, Error> {
+	let files = File::find_by_ids(&db, &form.ids)?;
+	for file in &files {
+		check_file_permission(&current_user, file)?;
+	}
+	for file in files {
+		media::remove_file(&db, &storage, &file)?;
+		audit.log(
+			Some(&current_user),
+			audit_log::ACTION_FILE_DELETE,
+			Some(("file", file.id)),
+			Some(file.audit_summary()),
+			None,
+		);
+	}
+	Ok(Redirect::to("/admin/file"))
+}
+
+#[derive(Default, FromForm, Debug)]
+pub struct RelinkForm {
+	/// Empty for unlinking
+	pub content_id: String,
+}
+#[post("/admin/file/<id>/relink", data = "<form>")]
+pub fn relink(
+	db: State<Box<Database>>,
+	id: i32,
+	form: LenientForm<RelinkForm>,
+	current_user: User,
+	_csrf: CSRFTokenValidation,
+	audit: Audit,
+) -> Result<Redirect, Error> {
+	let file: File = File::find(&db, id)?;
+	check_file_permission(&current_user, &file)?;
+	let content_id = match form.content_id.trim() {
+		"" => None,
+		s => {
+			let id = s
+				.parse::<i32>()
+				.map_err(|_| Error::BadRequest("`content_id` should be a number"))?;
+			Some(id)
+		}
+	};
+	// attaching or detaching changes the post, so it's checked like editing it
+	if content_id.is_some() || file.content.is_some() {
+		current_user.check_permission(user::PERM_POST_EDIT)?;
+	}
+	if let Some(id) = content_id {
+		let content: Content = Content::find(&db, id)?;
+		if content.status == content::ContentStatus::Deleted
+			|| content.r#type != content::ContentType::Article
+		{
+			return Err(Error::NotFound);
+		}
+	}
+	file.set_content(&db, content_id)?;
+	audit.log(
+		Some(&current_user),
+		audit_log::ACTION_FILE_RELINK,
+		Some(("file", file.id)),
+		Some(serde_json::json!({ "content": file.content })),
+		Some(serde_json::json!({ "content": content_id })),
+	);
+	Ok(Redirect::to("/admin/file"))
+}
@@ -3,3 +3,8 @@ pub mod post;
 pub mod category;
 pub mod file;
 pub mod comment;
+pub mod token;
+pub mod audit;
+pub mod plugin;
+pub mod appearance;
+pub mod settings;
@@ -0,0 +1,183 @@
+use super::super::{
+	error::Error,
+	plugin::{read_body, PluginRequest, PluginResponse},
+};
+use crate::{
+	http,
+	models::{
+		audit_log::{self, Audit},
+		setting::Setting,
+		user::{self, User},
+	},
+	plugin::{LoadStatus, PluginType},
+	render::RenderResult,
+	templates,
+	util::*,
+};
+use rocket::{request::LenientForm, response::Redirect, Data};
+use rocket_codegen::*;
+
+/// A file in the plugin directory, shown in admin
+pub struct PluginInfo {
+	pub path: String,
+	/// `None` if metadata couldn't be read
+	pub identity: Option<String>,
+	pub kind: &'static str,
+	pub description: String,
+	pub version: String,
+	/// `Loaded`, `Disabled`, `Failed`, or `Stopped` after a hook panicked
+	pub status: &'static str,
+	pub error: Option<String>,
+	/// Saved state, which takes effect on next start
+	pub enabled: bool,
+}
+
+/// Files in the plugin directory with their metadata, and why they failed to load
+#[get("/admin/plugin")]
+pub fn list(gctx: GlobalContext, current_user: User) -> Result<RenderResult, Error> {
+	current_user.check_permission(user::PERM_SETTINGS_MANAGE)?;
+	let disabled = Setting::disabled_plugins(&gctx.db)?;
+	let stopped: Vec<&str> = gctx
+		.plugin_manager
+		.plugins()
+		.into_iter()
+		.filter(|(_, enabled)| !enabled)
+		.map(|(p, _)| p.name())
+		.collect();
+	let plugins: Vec<PluginInfo> = gctx
+		.plugin_manager
+		.files()
+		.iter()
+		.map(|file| {
+			let metadata = file.metadata.as_ref();
+			let identity = metadata.map(|m| m.identity.to_owned());
+			let (status, error) = match &file.status {
+				LoadStatus::Loaded
+					if metadata.map_or(false, |m| {
+						m.r#type == PluginType::Plugin && stopped.contains(&m.identity.as_str())
+					}) =>
+				{
+					("Stopped", None)
+				}
+				LoadStatus::Loaded => ("Loaded", None),
+				LoadStatus::Disabled => ("Disabled", None),
+				LoadStatus::Failed(e) => ("Failed", Some(e.to_owned())),
+			};
+			PluginInfo {
+				path: file.path.to_owned(),
+				enabled: identity.as_ref().map_or(true, |i| !disabled.contains(i)),
+				identity: identity,
+				kind: match metadata.map(|m| m.r#type) {
+					Some(PluginType::Plugin) => "plugin",
+					Some(PluginType::Theme) => "theme",
+					None => "",
+				},
+				description: metadata.map(|m| m.description.to_owned()).unwrap_or_default(),
+				version: metadata.map(|m| m.version.to_owned()).unwrap_or_default(),
+				status: status,
+				error: error,
+			}
+		})
+		.collect();
+	Ok(render!(
+		templates::admin::plugin::list,
+		&gctx.get_template_context(),
+		plugins,
+		gctx.plugin_manager.crashed(),
+		gctx.system_config.plugin_hot_reload
+	))
+}
+
+#[derive(Default, FromForm, Debug)]
+pub struct EnabledForm {
+	pub identity: String,
+	pub enabled: bool,
+}
+
+/// Takes effect on next start, or next reload in development
+#[post("/admin/plugin/enabled", data = "<form>")]
+pub fn set_enabled(
+	gctx: GlobalContext,
+	current_user: User,
+	_csrf: CSRFTokenValidation,
+	form: LenientForm<EnabledForm>,
+	audit: Audit,
+) -> Result<Redirect, Error> {
+	current_user.check_permission(user::PERM_SETTINGS_MANAGE)?;
+	let mut disabled = Setting::disabled_plugins(&gctx.db)?;
+	let was_enabled = !disabled.contains(&form.identity);
+	disabled.retain(|i| i != &form.identity);
+	if !form.enabled {
+		disabled.push(form.identity.to_owned());
+	}
+	Setting::set_disabled_plugins(&gctx.db, &disabled)?;
+	audit.log(
+		Some(&current_user),
+		audit_log::ACTION_PLUGIN_ENABLE,
+		None,
+		Some(serde_json::json!({ "identity": form.identity, "enabled": was_enabled })),
+		Some(serde_json::json!({ "identity": form.identity, "enabled": form.enabled })),
+	);
+	Ok(Redirect::to(uri!(list)))
+}
+
+/// Admin page `slug` declared by plugin `name`
+#[get("/admin/plugin/<name>/<slug>")]
+pub fn page_get(
+	gctx: GlobalContext,
+	current_user: User,
+	req: PluginRequest,
+	name: String,
+	slug: String,
+) -> Result<PluginResponse, Error> {
+	page(&gctx, &current_user, req, &name, &slug)
+}
+
+#[post("/admin/plugin/<name>/<slug>", data = "<data>")]
+pub fn page_post(
+	gctx: GlobalContext,
+	current_user: User,
+	_csrf: CSRFTokenValidation,
+	req: PluginRequest,
+	name: String,
+	slug: String,
+	data: Data,
+) -> Result<PluginResponse, Error> {
+	let mut req = req;
+	req.request.body = read_body(data).map_err(Error::HttpStatus)?;
+	page(&gctx, &current_user, req, &name, &slug)
+}
+
+fn page(
+	gctx: &GlobalContext,
+	current_user: &User,
+	mut req: PluginRequest,
+	name: &str,
+	slug: &str,
+) -> Result<PluginResponse, Error> {
+	let page = gctx
+		.plugin_manager
+		.find_admin_page(name, slug)
+		.ok_or(Error::NotFound)?;
+	current_user.check_all_permissions(page.permission)?;
+	req.request.path = format!("/{}", slug);
+	let response = gctx
+		.plugin_manager
+		.handle_admin_page(name, slug, &req.request)
+		.sanitize(&req.csrf_token);
+	if response.status != 200 {
+		return Ok(PluginResponse::trusted(response));
+	}
+	let content = String::from_utf8_lossy(&response.body).into_owned();
+	let RenderResult(html) = render!(
+		templates::admin::plugin::page,
+		&gctx.get_template_context(),
+		&page.title,
+		&content
+	);
+	Ok(PluginResponse::trusted(
+		http::Response::new(200)
+			.header("Content-Type", "text/html; charset=utf-8")
+			.body(html),
+	))
+}
@@ -3,11 +3,15 @@ use crate::{
 	db::Database,
 	models::{
 		self,
+		audit_log::{self, Audit},
 		content::{self, Content},
+		file::File,
 		user::{self, User},
 		IntoInterface,
 	},
+	plugin::{ContentDraft, SharedPluginManager},
 	render::RenderResult,
+	routes::admin::file,
 	templates,
 	types::EnumType,
 	util::*,
@@ -15,6 +19,7 @@ use crate::{
 use rocket::{request::LenientForm, response::Redirect, State};
 use rocket_codegen::*;
 use chrono::{NaiveDateTime, Local, offset::TimeZone};
+use uuid::Uuid;
 
 pub const ITEMS_PER_PAGE: i32 = 25;
 
@@ -37,7 +42,7 @@ pub fn list(
 		templates::admin::post::list,
 		&gctx.get_template_context(),
 		page,
-		posts.into_interface(&gctx.db)
+		posts.into_interface(&gctx.repository())
 	))
 }
 
@@ -46,12 +51,15 @@ pub fn new_get(gctx: GlobalContext, current_user: User) -> Result<RenderResult,
 	current_user.check_permission(user::PERM_POST_EDIT)?;
 	let categories = models::category::Category::find_all(&gctx.db)?;
 
+	// attachments uploaded before first save are linked with this, see `File::link_draft`
+	let draft_token = Uuid::new_v4().to_simple().to_string();
 	Ok(render!(
 		templates::admin::post::edit,
 		&gctx.get_template_context(),
 		"New Post",
 		None,
-		categories.into_interface(&gctx.db)
+		categories.into_interface(&gctx.repository()),
+		Some(draft_token.as_str())
 	))
 }
 #[get("/admin/post/<post_id>")]
@@ -76,8 +84,9 @@ pub fn edit_get(
 			post.title.as_ref().unwrap_or(&String::from("Untitled"))
 		)
 		.as_str(),
-		Some(post.into_interface(&gctx.db)),
-		categories.into_interface(&gctx.db)
+		Some(post.into_interface(&gctx.repository())),
+		categories.into_interface(&gctx.repository()),
+		None
 	))
 }
 #[derive(Default, FromForm, Debug)]
@@ -91,13 +100,51 @@ pub struct PostForm {
 	pub tags: Option<String>,
 	pub status: i32,
 	pub save_draft: bool,
+	/// Cover image, should be an image attached to the post
+	pub featured_file: Option<i32>,
+	/// Set for new posts, attachments uploaded with it are linked on save
+	pub draft_token: Option<String>,
+}
+
+/// Checks the cover picked in editor, which is attached to the post, or uploaded by `user_id`
+/// with the draft token if the post is new
+fn featured_file(
+	db: &Database,
+	form: &PostForm,
+	post_id: Option<i32>,
+	user_id: i32,
+) -> Result<Option<i32>, Error> {
+	match form.featured_file {
+		Some(file_id) => {
+			let file: File = File::find(db, file_id)?;
+			let attached = match post_id {
+				Some(id) => file.content == Some(id),
+				None => {
+					file.content.is_none()
+						&& file.user == user_id
+						&& form.draft_token.as_ref().map_or(false, |t| {
+							file::is_valid_draft_token(t) && file.draft_token.as_ref() == Some(t)
+						})
+				}
+			};
+			if !attached || !file.is_image() {
+				return Err(Error::BadRequest(
+					"Cover should be an image attached to the post",
+				));
+			}
+			Ok(Some(file.id))
+		}
+		None => Ok(None),
+	}
 }
 #[post("/admin/post/_edit", data = "<form>")]
 pub fn edit_post(
 	db: State<Box<Database>>,
+	plugin_manager: State<SharedPluginManager>,
 	form: LenientForm<PostForm>,
 	current_user: User,
 	_csrf: CSRFTokenValidation,
+	audit: Audit,
 ) -> Result<Redirect, Error> {
 	current_user.check_permission(user::PERM_POST_EDIT)?;
 	let title = form
@@ -117,6 +164,21 @@ pub fn edit_post(
 		None
 	};
 	let parsed_time = Local.from_local_datetime(&NaiveDateTime::parse_from_str(form.time.as_str(), "%Y-%m-%d %H:%M:%S")?).unwrap().into();
+	let mut draft = ContentDraft {
+		id: form.id,
+		title: title,
+		slug: slug,
+		content: form.content.to_owned(),
+		// a new post saved as draft is not published
+		status: if form.save_draft && form.id.is_none() {
+			content::ContentStatus::Unpublished
+		} else {
+			content::ContentStatus::try_from(form.status)?
+		},
+		is_draft: form.save_draft,
+	};
+	plugin_manager.read().unwrap().filter_content_before_save(&mut draft);
+	let mut was_published = false;
 	let post = match form.id {
 		Some(id) => {
 			let mut post: Content = Content::find(&db, id)?;
@@ -125,57 +187,86 @@ pub fn edit_post(
 			{
 				return Err(Error::NotFound);
 			}
-			post.title = title;
-			post.slug = slug;
-			post.status = content::ContentStatus::try_from(form.status)?;
-			if form.save_draft {
-				post.draft_content = Some(form.content.to_owned());
+			let before = post.audit_summary();
+			was_published = post.status == content::ContentStatus::Normal;
+			post.title = draft.title.to_owned();
+			post.slug = draft.slug.to_owned();
+			post.status = draft.status;
+			if draft.is_draft {
+				post.draft_content = Some(draft.content.to_owned());
 			} else {
-				post.content = form.content.to_owned();
+				post.content = draft.content.to_owned();
 				post.draft_content = None;
 			}
 			post.time = parsed_time;
 			post.category = category;
+			post.featured_file = featured_file(&db, &form, Some(post.id), current_user.id)?;
 			post.update(&db)?;
+			audit.log(
+				Some(&current_user),
+				audit_log::ACTION_POST_UPDATE,
+				Some(("content", post.id)),
+				Some(before),
+				Some(post.audit_summary()),
+			);
 			post
 		}
 		None => {
-			let ctxt = &form.content;
+			// checked before inserting, so a bad cover doesn't leave a half saved post
+			let featured = featured_file(&db, &form, None, current_user.id)?;
+			let ctxt = &draft.content;
 			// TODO: set view_password
 			let content = content::NewContent {
 				user: Some(current_user.id),
 				time: parsed_time,
-				title: title,
-				slug: slug,
-				content: if form.save_draft {
+				title: draft.title.to_owned(),
+				slug: draft.slug.to_owned(),
+				content: if draft.is_draft {
 					String::from("This is an draft.")
 				} else {
 					ctxt.to_owned()
 				},
-				draft_content: if form.save_draft {
+				draft_content: if draft.is_draft {
 					Some(ctxt.to_owned())
 				} else {
 					None
 				},
 				order_level: 0,
 				r#type: content::ContentType::Article,
-				status: if form.save_draft {
-					content::ContentStatus::Unpublished
-				} else {
-					content::ContentStatus::try_from(form.status)?
-				},
+				status: draft.status,
 				allow_comment: true,
 				allow_feed: true,
 				parent: None,
 				view_password: None,
 				category: category,
 			};
-			Content::insert(&db, content)?
+			let mut post = Content::insert(&db, content)?;
+			if let Some(token) = &form.draft_token {
+				if file::is_valid_draft_token(token) {
+					File::link_draft(&db, token, current_user.id, post.id)?;
+				}
+			}
+			if featured.is_some() {
+				post.featured_file = featured;
+				post.update(&db)?;
+			}
+			audit.log(
+				Some(&current_user),
+				audit_log::ACTION_POST_CREATE,
+				Some(("content", post.id)),
+				None,
+				Some(post.audit_summary()),
+			);
+			post
 		}
 	};
 	if let Some(tags) = &form.tags {
 		let tags: Vec<&str> = tags.split(",").map(|s| s.trim()).collect();
 		post.set_tags(&db, tags)?;
 	}
+	if !was_published && !draft.is_draft && post.status == content::ContentStatus::Normal {
+		draft.id = Some(post.id);
+		plugin_manager.read().unwrap().on_publish(post.id, &draft);
+	}
 	Ok(Redirect::to("/admin/post"))
 }
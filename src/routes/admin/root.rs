@@ -1,13 +1,32 @@
 use super::super::error::Error;
-use crate::{models::user::User, render, templates, util::*};
+use crate::{
+	models::{
+		login_history::{LoginHistory, LoginResult},
+		user::User,
+	},
+	render, templates,
+	util::*,
+};
 use rocket_codegen::*;
 
-#[get("/admin")]
-pub fn index(gctx: GlobalContext, _user: User) -> render::RenderResult {
-	render!(templates::admin::index, &gctx.get_template_context())
-}
+/// How many recent successful logins are checked for new network alerts
+const RECENT_LOGINS: i64 = 10;
 
-#[get("/admin/generatePasswordHash?<p>")]
-pub fn generate_password_hash(p: String) -> Result<String, Error> {
-	Ok(User::generate_password_hash(p.as_str())?)
+#[get("/admin")]
+pub fn index(gctx: GlobalContext, user: User) -> Result<render::RenderResult, Error> {
+	let recent = LoginHistory::find_recent_by_user(
+		&gctx.db,
+		user.id,
+		Some(LoginResult::Success),
+		RECENT_LOGINS,
+	)?;
+	// The latest one is the current session
+	let previous_login = recent.get(1).cloned();
+	let alerts: Vec<LoginHistory> = recent.into_iter().filter(|h| h.new_network).collect();
+	Ok(render!(
+		templates::admin::index,
+		&gctx.get_template_context(),
+		previous_login,
+		alerts
+	))
 }
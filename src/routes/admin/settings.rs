@@ -0,0 +1,164 @@
+use super::super::error::Error;
+use crate::{
+	models::{
+		audit_log::{self, Audit},
+		setting::PluginSettings,
+		user::{self, User},
+	},
+	plugin::PluginType,
+	render::RenderResult,
+	templates,
+	util::*,
+};
+use rocket::{
+	request::{FormItems, FromForm, LenientForm},
+	response::Redirect,
+	State,
+};
+use rocket_codegen::*;
+use sohablog_lib::settings::{SettingField, Settings};
+use std::collections::HashMap;
+
+fn parse_type(kind: &str) -> Result<PluginType, Error> {
+	match kind {
+		"plugin" => Ok(PluginType::Plugin),
+		"theme" => Ok(PluginType::Theme),
+		_ => Err(Error::NotFound),
+	}
+}
+
+fn type_name(r#type: PluginType) -> &'static str {
+	match r#type {
+		PluginType::Plugin => "plugin",
+		PluginType::Theme => "theme",
+	}
+}
+
+/// Form fields as `(field, value, error)`
+fn rows(
+	schema: Vec<SettingField>,
+	values: &HashMap<String, String>,
+	errors: &HashMap<String, String>,
+) -> Vec<(SettingField, String, Option<String>)> {
+	schema
+		.into_iter()
+		.map(|field| {
+			let value = values.get(&field.key).cloned().unwrap_or_default();
+			let error = errors.get(&field.key).cloned();
+			(field, value, error)
+		})
+		.collect()
+}
+
+/// Themes and enabled plugins declaring settings, as `(type, identity)`
+#[get("/admin/settings")]
+pub fn list(gctx: GlobalContext, current_user: User) -> Result<RenderResult, Error> {
+	current_user.check_permission(user::PERM_SETTINGS_MANAGE)?;
+	let plugins: Vec<(&str, String)> = gctx
+		.plugin_manager
+		.settings_schemas()
+		.into_iter()
+		.map(|(t, identity, _)| (type_name(t), identity))
+		.collect();
+	Ok(render!(
+		templates::admin::settings::list,
+		&gctx.get_template_context(),
+		plugins
+	))
+}
+
+#[get("/admin/settings/<kind>/<identity>")]
+pub fn edit_get(
+	gctx: GlobalContext,
+	current_user: User,
+	plugin_settings: State<PluginSettings>,
+	kind: String,
+	identity: String,
+) -> Result<RenderResult, Error> {
+	current_user.check_permission(user::PERM_SETTINGS_MANAGE)?;
+	let plugin_type = parse_type(&kind)?;
+	let schema = gctx
+		.plugin_manager
+		.settings_schema(plugin_type, &identity)
+		.ok_or(Error::NotFound)?;
+	let settings = plugin_settings.get(plugin_type, &identity);
+	Ok(render!(
+		templates::admin::settings::edit,
+		&gctx.get_template_context(),
+		&kind,
+		&identity,
+		rows(schema, settings.values(), &HashMap::new())
+	))
+}
+
+/// Every submitted field, an unchecked checkbox is missing
+#[derive(Default, Debug)]
+pub struct SettingsForm(HashMap<String, String>);
+impl<'f> FromForm<'f> for SettingsForm {
+	type Error = ();
+	fn from_form(items: &mut FormItems<'f>, _strict: bool) -> Result<Self, ()> {
+		let mut form = Self::default();
+		for item in items {
+			let (key, value) = item.key_value_decoded();
+			form.0.insert(key, value);
+		}
+		Ok(form)
+	}
+}
+
+/// Nothing is saved unless every field is valid, otherwise the form is shown with errors
+#[post("/admin/settings/<kind>/<identity>", data = "<form>")]
+pub fn edit_post(
+	gctx: GlobalContext,
+	current_user: User,
+	_csrf: CSRFTokenValidation,
+	plugin_settings: State<PluginSettings>,
+	kind: String,
+	identity: String,
+	form: LenientForm<SettingsForm>,
+	audit: Audit,
+) -> Result<Result<Redirect, RenderResult>, Error> {
+	current_user.check_permission(user::PERM_SETTINGS_MANAGE)?;
+	let plugin_type = parse_type(&kind)?;
+	let schema: Vec<SettingField> = gctx
+		.plugin_manager
+		.settings_schema(plugin_type, &identity)
+		.ok_or(Error::NotFound)?;
+
+	let mut values = HashMap::new();
+	let mut errors = HashMap::new();
+	for field in &schema {
+		let submitted = form.0.get(&field.key).map(|s| s.as_str());
+		match field.validate(submitted) {
+			Ok(value) => values.insert(field.key.to_owned(), value),
+			Err(e) => {
+				errors.insert(field.key.to_owned(), e);
+				values.insert(field.key.to_owned(), submitted.unwrap_or("").to_owned())
+			}
+		};
+	}
+	if !errors.is_empty() {
+		return Ok(Err(render!(
+			templates::admin::settings::edit,
+			&gctx.get_template_context(),
+			&kind,
+			&identity,
+			rows(schema, &values, &errors)
+		)));
+	}
+
+	let before = plugin_settings.get(plugin_type, &identity);
+	let settings = Settings::new(&schema, &values);
+	plugin_settings.save(&gctx.db, plugin_type, &identity, settings.clone())?;
+	audit.log(
+		Some(&current_user),
+		audit_log::ACTION_PLUGIN_SETTINGS,
+		None,
+		Some(serde_json::json!({ "type": kind, "identity": identity, "values": before.values() })),
+		Some(serde_json::json!({ "type": kind, "identity": identity, "values": settings.values() })),
+	);
+	if plugin_type == PluginType::Plugin {
+		gctx.plugin_manager.settings_changed(&identity, &settings);
+	}
+	Ok(Ok(Redirect::to(uri!(edit_get: kind = &kind, identity = &identity))))
+}
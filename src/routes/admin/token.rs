@@ -0,0 +1,129 @@
+use super::super::error::Error;
+use crate::{
+	db::Database,
+	models::{
+		api_token::ApiToken,
+		audit_log::{self, Audit},
+		user::{self, User},
+	},
+	render::RenderResult,
+	templates,
+	util::*,
+};
+use chrono::{Duration, Utc};
+use rocket::{
+	request::{FormItems, FromForm, LenientForm},
+	response::Redirect,
+	State,
+};
+use rocket_codegen::*;
+
+/// Permissions `user` is able to grant to a token
+fn grantable_scopes(user: &User) -> Vec<(i32, &'static str)> {
+	user::PERMISSIONS
+		.iter()
+		.cloned()
+		.filter(|&(perm, _)| user.has_permission(perm))
+		.collect()
+}
+
+#[get("/admin/token")]
+pub fn list(gctx: GlobalContext, current_user: User) -> Result<RenderResult, Error> {
+	Ok(render!(
+		templates::admin::token::list,
+		&gctx.get_template_context(),
+		ApiToken::find_by_user_id(&gctx.db, current_user.id)?,
+		grantable_scopes(&current_user),
+		None
+	))
+}
+
+/// `scope` may appear multiple times, one for each permission bit
+#[derive(Default, Debug)]
+pub struct NewTokenForm {
+	pub name: String,
+	pub expires_in: Option<i64>,
+	pub scope: i32,
+}
+impl<'f> FromForm<'f> for NewTokenForm {
+	type Error = ();
+	fn from_form(items: &mut FormItems<'f>, _strict: bool) -> Result<Self, ()> {
+		let mut form = Self::default();
+		for item in items {
+			let (key, value) = item.key_value_decoded();
+			match key.as_str() {
+				"name" => form.name = value,
+				"expires_in" => form.expires_in = value.parse::<i64>().ok(),
+				"scope" => form.scope |= value.parse::<i32>().map_err(|_| ())?,
+				_ => (),
+			}
+		}
+		Ok(form)
+	}
+}
+#[post("/admin/token/new", data = "<form>")]
+pub fn new(
+	gctx: GlobalContext,
+	form: LenientForm<NewTokenForm>,
+	current_user: User,
+	_csrf: CSRFTokenValidation,
+	audit: Audit,
+) -> Result<RenderResult, Error> {
+	let name = form.name.trim();
+	if name.len() == 0 {
+		return Err(Error::BadRequest("`name` field is illegal."));
+	}
+	let expires_at = form
+		.expires_in
+		.filter(|&days| days > 0)
+		.map(|days| Utc::now() + Duration::days(days));
+	let (row, token) = ApiToken::create(
+		&gctx.db,
+		&current_user,
+		name.to_owned(),
+		form.scope,
+		expires_at,
+	)?;
+	audit.log(
+		Some(&current_user),
+		audit_log::ACTION_TOKEN_CREATE,
+		Some(("api_token", row.id)),
+		None,
+		Some(serde_json::json!({
+			"name": row.name,
+			"scope": row.scope,
+			"expires_at": row.expires_at,
+		})),
+	);
+
+	Ok(render!(
+		templates::admin::token::list,
+		&gctx.get_template_context(),
+		ApiToken::find_by_user_id(&gctx.db, current_user.id)?,
+		grantable_scopes(&current_user),
+		Some(token)
+	))
+}
+
+#[post("/admin/token/<id>/revoke")]
+pub fn revoke(
+	db: State<Box<Database>>,
+	id: i32,
+	current_user: User,
+	_csrf: CSRFTokenValidation,
+	audit: Audit,
+) -> Result<Redirect, Error> {
+	let token: ApiToken = ApiToken::find(&db, id)?;
+	if token.user != current_user.id {
+		return Err(Error::NotFound);
+	}
+	token.delete(&db)?;
+	audit.log(
+		Some(&current_user),
+		audit_log::ACTION_TOKEN_REVOKE,
+		Some(("api_token", token.id)),
+		Some(serde_json::json!({ "name": token.name, "scope": token.scope })),
+		None,
+	);
+	Ok(Redirect::to(uri!(list)))
+}
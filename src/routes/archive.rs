@@ -0,0 +1,138 @@
+use super::{error::Error, post::ITEMS_PER_PAGE, Page};
+use crate::{
+	interfaces::models::Content as ContentInterface,
+	models::{
+		category::Category,
+		content::{Content, ContentStatus, PostFilter},
+		tag::Tag,
+		IntoInterface,
+	},
+	plugin::Archive,
+	render::{theme, RenderResult},
+	util::*,
+};
+use chrono::{Local, TimeZone, Utc};
+use rocket::http::Cookies;
+use rocket_codegen::*;
+
+/// Posts matching `filter` which the visitor can see, `page.total` is calculated
+fn find_posts(
+	gctx: &GlobalContext,
+	cookies: &mut Cookies,
+	page: &mut Page,
+	filter: &PostFilter,
+) -> Result<Vec<Box<dyn ContentInterface>>, Error> {
+	let status = if let None = gctx.user {
+		ContentStatus::PUBLIC_LIST.to_vec()
+	} else {
+		ContentStatus::LOGGED_IN_LIST.to_vec()
+	};
+	let mut posts =
+		Content::find_filtered_posts(&gctx.db, page.range(ITEMS_PER_PAGE), &status, filter)?;
+	page.calc_total(
+		Content::count_filtered_posts(&gctx.db, &status, filter)? as i32,
+		ITEMS_PER_PAGE,
+	);
+	for post in &mut posts {
+		super::post::lock_unless_accepted(gctx, cookies, post);
+		gctx.plugin_manager
+			.filter_content_before_render(post.id, &mut post.content);
+	}
+	Ok(posts.into_interface(&gctx.repository()))
+}
+
+/// An empty `q` shows the search form only
+#[get("/search?<q>&<page>")]
+pub fn search(
+	gctx: GlobalContext,
+	mut cookies: Cookies,
+	q: Option<String>,
+	mut page: Page,
+) -> Result<RenderResult, Error> {
+	let query = q.unwrap_or_default().trim().to_owned();
+	if query.is_empty() {
+		return Ok(theme::search_result(&gctx, "", page, Vec::new())?);
+	}
+	let filter = PostFilter {
+		keyword: Some(query.to_owned()),
+		..Default::default()
+	};
+	let posts = find_posts(&gctx, &mut cookies, &mut page, &filter)?;
+	Ok(theme::search_result(&gctx, &query, page, posts)?)
+}
+
+/// Months are in local time of the server
+#[get("/archive/<year>/<month>?<page>")]
+pub fn month(
+	gctx: GlobalContext,
+	mut cookies: Cookies,
+	year: i32,
+	month: u32,
+	mut page: Page,
+) -> Result<RenderResult, Error> {
+	let start = Local.ymd_opt(year, month, 1).single().ok_or(Error::NotFound)?;
+	// `month` is valid after `start`, but `year` can still be the last one
+	let (next_year, next_month) = if month == 12 {
+		(year.checked_add(1).ok_or(Error::NotFound)?, 1)
+	} else {
+		(year, month + 1)
+	};
+	let end = Local
+		.ymd_opt(next_year, next_month, 1)
+		.single()
+		.ok_or(Error::NotFound)?;
+	let filter = PostFilter {
+		time_range: Some((
+			start.and_hms(0, 0, 0).with_timezone(&Utc),
+			end.and_hms(0, 0, 0).with_timezone(&Utc),
+		)),
+		..Default::default()
+	};
+	let posts = find_posts(&gctx, &mut cookies, &mut page, &filter)?;
+	Ok(theme::archive(
+		&gctx,
+		&Archive::Month {
+			year: year,
+			month: month,
+		},
+		page,
+		posts,
+	)?)
+}
+
+#[get("/category/<slug>?<page>")]
+pub fn category(
+	gctx: GlobalContext,
+	mut cookies: Cookies,
+	slug: String,
+	mut page: Page,
+) -> Result<RenderResult, Error> {
+	let category = Category::find_by_slug(&gctx.db, &slug)?;
+	let filter = PostFilter {
+		category: Some(category.id),
+		..Default::default()
+	};
+	let posts = find_posts(&gctx, &mut cookies, &mut page, &filter)?;
+	Ok(theme::archive(
+		&gctx,
+		&Archive::Category(category.into_interface(&gctx.repository())),
+		page,
+		posts,
+	)?)
+}
+
+#[get("/tag/<name>?<page>")]
+pub fn tag(
+	gctx: GlobalContext,
+	mut cookies: Cookies,
+	name: String,
+	mut page: Page,
+) -> Result<RenderResult, Error> {
+	let tag = Tag::find_one_by_name(&gctx.db, &name.trim().to_lowercase())?;
+	let filter = PostFilter {
+		tag: Some(tag.id),
+		..Default::default()
+	};
+	let posts = find_posts(&gctx, &mut cookies, &mut page, &filter)?;
+	Ok(theme::archive(&gctx, &Archive::Tag(tag.name), page, posts)?)
+}
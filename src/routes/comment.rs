@@ -7,6 +7,7 @@ use crate::{
 		comment::{self, Comment, CommentSerializedNormal},
 		content,
 	},
+	plugin::CommentDraft,
 	types::CommentStatus,
 	util::*,
 };
@@ -104,17 +105,40 @@ pub fn new_content_comment(
 			.permanent()
 			.finish(),
 	);
+	let mut draft = CommentDraft {
+		content_id: content_id,
+		user_id: author.local_user,
+		author_name: author.name.to_owned(),
+		author_mail: author.mail.to_owned(),
+		author_link: author.link.to_owned(),
+		ip: gctx.ip.to_string(),
+		user_agent: gctx.user_agent.to_owned(),
+		text: comment_text.to_string(),
+		reply_to: reply_to,
+		status: CommentStatus::Normal, // TODO: Default comment status setting
+	};
+	gctx.plugin_manager
+		.filter_comment(&mut draft)
+		.map_err(Error::Rejected)?;
+	// filters may change what is shown, but not who posted it or where
+	let author = comment::Author {
+		name: draft.author_name.to_owned(),
+		mail: draft.author_mail.to_owned(),
+		link: draft.author_link.to_owned(),
+		..author
+	};
 	let new_comment = Comment::new(
 		author,
 		Some(gctx.ip.to_ipnetwork()),
-		gctx.user_agent.to_owned(),
-		comment_text.to_string(),
+		draft.user_agent.to_owned(),
+		draft.text.to_owned(),
 		reply_to,
 		parent,
 		content_id,
-		CommentStatus::Normal, // TODO: Default comment status setting
+		draft.status,
 	);
 	let new_comment = Comment::insert(&gctx.db, new_comment)?;
+	gctx.plugin_manager.on_comment(new_comment.id, &draft);
 
 	Ok(JsonOrNormal(
 		ApiResult::new(new_comment.serialize_normal(), None, None),
@@ -1,10 +1,11 @@
 use super::ApiResult;
-use crate::{models, render, util::*};
+use crate::{media, models, render, render::theme, storage, util::*};
 use rocket::{
 	http::Status,
-	response::{self, Responder},
+	response::{self, Responder, Response},
 	Request,
 };
+use rocket_codegen::*;
 use rocket_contrib::json::Json;
 
 #[derive(Debug)]
@@ -17,10 +18,13 @@ pub enum Error {
 	PermissionDenied,
 	CSRFViolation,
 	BadRequest(&'static str),
+	/// Rejected by a plugin, the reason is shown to user
+	Rejected(String),
 	Io(std::io::Error),
 	OptionNone,
 	HttpStatus(Status),
 	EnumType(crate::types::Error),
+	Storage(storage::Error),
 }
 impl From<std::option::NoneError> for Error {
 	fn from(_: std::option::NoneError) -> Self {
@@ -61,6 +65,21 @@ impl From<crate::types::Error> for Error {
 		Self::EnumType(e)
 	}
 }
+impl From<storage::Error> for Error {
+	fn from(e: storage::Error) -> Self {
+		Self::Storage(e)
+	}
+}
+impl From<media::Error> for Error {
+	fn from(e: media::Error) -> Self {
+		match e {
+			media::Error::Image(_) => Self::BadRequest("Broken image"),
+			media::Error::TooLarge => Self::BadRequest("Image has too many pixels"),
+			media::Error::Storage(e) => e.into(),
+			media::Error::Model(e) => e.into(),
+		}
+	}
+}
 
 impl<'a> Responder<'a> for Error {
 	fn respond_to(self, req: &Request) -> response::Result<'a> {
@@ -72,6 +91,7 @@ impl<'a> Responder<'a> for Error {
 			Self::NotFound => Status::NotFound,
 			Self::PermissionDenied => Status::Forbidden,
 			Self::BadRequest(reason) => Status::new(400, reason),
+			Self::Rejected(_) => Status::Forbidden,
 			_ => Status::InternalServerError,
 		};
 		if req
@@ -82,16 +102,56 @@ impl<'a> Responder<'a> for Error {
 		{
 			Json(ApiResult {
 				status: status.code.into(),
-				r#return: if global_context.system_config.is_prod {
-					status.reason.to_string()
-				} else {
-					format!("{:?}", &self)
+				r#return: match &self {
+					Self::Rejected(reason) => reason.to_owned(),
+					_ if global_context.system_config.is_prod => status.reason.to_string(),
+					_ => format!("{:?}", &self),
 				},
 				data: (),
 			})
 			.respond_to(req)
 		} else {
-			Err(status)
+			let message = match &self {
+				Self::Rejected(reason) => reason.to_owned(),
+				_ => status.reason.to_string(),
+			};
+			ErrorPage(status, message).respond_to(req)
 		}
 	}
 }
+
+/// Error page rendered by the theme, only the status is sent if rendering fails
+pub struct ErrorPage(pub Status, pub String);
+impl<'r> Responder<'r> for ErrorPage {
+	fn respond_to(self, req: &Request) -> response::Result<'r> {
+		let ErrorPage(status, message) = self;
+		let html = req
+			.guard::<GlobalContext>()
+			.succeeded()
+			.and_then(|gctx| theme::error_page(&gctx, status.code, &message).ok());
+		match html {
+			Some(html) => Response::build_from(html.respond_to(req)?)
+				.status(status)
+				.ok(),
+			None => Response::build().status(status).ok(),
+		}
+	}
+}
+
+#[catch(403)]
+pub fn forbidden(_req: &Request) -> ErrorPage {
+	ErrorPage(Status::Forbidden, Status::Forbidden.reason.to_owned())
+}
+
+#[catch(404)]
+pub fn not_found(_req: &Request) -> ErrorPage {
+	ErrorPage(Status::NotFound, Status::NotFound.reason.to_owned())
+}
+
+#[catch(500)]
+pub fn internal_error(_req: &Request) -> ErrorPage {
+	ErrorPage(
+		Status::InternalServerError,
+		Status::InternalServerError.reason.to_owned(),
+	)
+}
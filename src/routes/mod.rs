@@ -43,8 +43,11 @@ impl<'r, J: serde::Serialize, N: Responder<'r>> Responder<'r> for JsonOrNormal<J
 pub use crate::utils::Page;
 
 pub mod admin;
+pub mod archive;
 pub mod comment;
+pub mod plugin;
 pub mod post;
 pub mod root;
 pub mod static_file;
+pub mod upload;
 pub mod user;
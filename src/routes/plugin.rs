@@ -0,0 +1,162 @@
+//! Routes declared by plugins, mounted under `/plugin/<name>/`.
+//!
+//! Requests are converted to `http::Request` of `sohablog_lib`, so plugins don't need to link
+//! against the same version of Rocket. Plugins don't see credentials of the visitor, and only
+//! `http::ALLOWED_RESPONSE_HEADERS` of their responses are sent.
+
+use crate::{
+	http::{self as plugin_http, Method as PluginMethod},
+	plugin::SharedPluginManager,
+	util::*,
+};
+use rocket::{
+	handler::{Handler, Outcome as HandlerOutcome},
+	http::{Method, Status},
+	request::{FromRequest, Outcome, Request, State},
+	response::{self, Responder, Response},
+	Data, Route,
+};
+use std::{
+	collections::HashMap,
+	io::{Cursor, Read},
+};
+
+/// Larger bodies are rejected with `413`
+const BODY_LIMIT: u64 = 1 << 20;
+
+/// A request to a plugin without credentials, see `Request::without_credentials`.
+/// Body is read by routes.
+pub struct PluginRequest {
+	pub request: plugin_http::Request,
+	/// Put in place of the placeholder in the response, see `PluginResponse::new`
+	pub csrf_token: String,
+}
+impl<'a, 'r> FromRequest<'a, 'r> for PluginRequest {
+	type Error = ();
+	fn from_request(request: &'a Request<'r>) -> Outcome<Self, ()> {
+		let method = match request.method() {
+			Method::Get => PluginMethod::Get,
+			Method::Post => PluginMethod::Post,
+			Method::Put => PluginMethod::Put,
+			Method::Delete => PluginMethod::Delete,
+			Method::Patch => PluginMethod::Patch,
+			_ => return Outcome::Forward(()),
+		};
+		let gctx = request.guard::<GlobalContext>()?;
+		let csrf_token = gctx.session_info.csrf_token.as_str().to_owned();
+		let request = plugin_http::Request {
+			method: method,
+			path: String::from("/"),
+			params: HashMap::new(),
+			query: request.uri().query().map(String::from),
+			headers: request
+				.headers()
+				.iter()
+				.map(|h| (h.name().to_lowercase(), h.value().to_owned()))
+				.collect(),
+			body: Vec::new(),
+			ip: gctx.ip.to_string(),
+			user_id: gctx.user.as_ref().map(|u| u.id),
+			csrf_field_name: gctx.system_config.csrf_field_name.to_owned(),
+			csrf_token: csrf_token.to_owned(),
+		};
+		Outcome::Success(Self {
+			request: request.without_credentials(),
+			csrf_token: csrf_token,
+		})
+	}
+}
+
+pub fn read_body(data: Data) -> Result<Vec<u8>, Status> {
+	let mut body = Vec::new();
+	data.open()
+		.take(BODY_LIMIT + 1)
+		.read_to_end(&mut body)
+		.map_err(|_| Status::BadRequest)?;
+	if body.len() as u64 > BODY_LIMIT {
+		return Err(Status::PayloadTooLarge);
+	}
+	Ok(body)
+}
+
+pub struct PluginResponse(plugin_http::Response);
+impl PluginResponse {
+	/// Response of a plugin, see `Response::sanitize`
+	pub fn new(response: plugin_http::Response, csrf_token: &str) -> Self {
+		Self(response.sanitize(csrf_token))
+	}
+
+	/// Response built by the server, which is sent as is
+	pub fn trusted(response: plugin_http::Response) -> Self {
+		Self(response)
+	}
+}
+impl<'r> Responder<'r> for PluginResponse {
+	fn respond_to(self, _req: &Request) -> response::Result<'r> {
+		let mut response = Response::build();
+		response.status(Status::from_code(self.0.status).unwrap_or(Status::InternalServerError));
+		for (name, value) in self.0.headers {
+			response.raw_header(name, value);
+		}
+		response.sized_body(Cursor::new(self.0.body)).ok()
+	}
+}
+
+/// Dispatches `/plugin/<name>/<path..>` to routes of the plugin
+#[derive(Clone)]
+pub struct PluginRoutes;
+impl PluginRoutes {
+	pub fn routes() -> Vec<Route> {
+		[
+			Method::Get,
+			Method::Post,
+			Method::Put,
+			Method::Delete,
+			Method::Patch,
+		]
+		.iter()
+		.flat_map(|&method| {
+			vec![
+				Route::new(method, "/plugin/<name>", PluginRoutes),
+				Route::new(method, "/plugin/<name>/<path..>", PluginRoutes),
+			]
+		})
+		.collect()
+	}
+}
+impl Handler for PluginRoutes {
+	fn handle<'r>(&self, req: &'r Request, data: Data) -> HandlerOutcome<'r> {
+		let name = match req.get_param::<String>(0) {
+			Some(Ok(name)) => name,
+			_ => return HandlerOutcome::Failure(Status::NotFound),
+		};
+		// plugins don't have to care about forged forms
+		if req.method() != Method::Get && !req.guard::<CSRFTokenValidation>().is_success() {
+			return HandlerOutcome::Failure(Status::BadRequest);
+		}
+		let (mut plugin_req, csrf_token) = match req.guard::<PluginRequest>() {
+			Outcome::Success(r) => (r.request, r.csrf_token),
+			_ => return HandlerOutcome::Forward(data),
+		};
+		let manager = match req.guard::<State<SharedPluginManager>>() {
+			Outcome::Success(m) => m.inner().read().unwrap(),
+			_ => return HandlerOutcome::Failure(Status::InternalServerError),
+		};
+		let path: Vec<&str> = req
+			.uri()
+			.path()
+			.split('/')
+			.filter(|s| !s.is_empty())
+			.skip(2)
+			.collect();
+		plugin_req.path = format!("/{}", path.join("/"));
+		plugin_req.body = match read_body(data) {
+			Ok(body) => body,
+			Err(status) => return HandlerOutcome::Failure(status),
+		};
+		match manager.handle_request(&name, &mut plugin_req) {
+			Some(response) => HandlerOutcome::from(req, PluginResponse::new(response, &csrf_token)),
+			None => HandlerOutcome::Failure(Status::NotFound),
+		}
+	}
+}
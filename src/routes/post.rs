@@ -1,10 +1,14 @@
 use super::error::Error;
 use crate::{
-	models::{comment::Author, content, IntoInterface},
+	models::{comment::Author, content, user, IntoInterface},
 	render::{theme, RenderResult},
 	util::*,
 };
-use rocket::http::Cookies;
+use rocket::{
+	http::{Cookie, Cookies},
+	request::LenientForm,
+	response::Redirect,
+};
 use rocket_codegen::*;
 
 pub const ITEMS_PER_PAGE: i32 = 15;
@@ -16,7 +20,7 @@ pub fn post_show(
 	path: String,
 ) -> Result<RenderResult, Error> {
 	let slug = path.replace(".html", ""); // TODO: We just need to remove `.html` at the end
-	let post: content::Content = match slug.parse::<i32>() {
+	let mut post: content::Content = match slug.parse::<i32>() {
 		Ok(post_id) => content::Content::find(&gctx.db, post_id)?,
 		Err(_) => content::Content::find_by_slug(&gctx.db, &slug)?,
 	};
@@ -28,7 +32,18 @@ pub fn post_show(
 	if !post.user_has_access(gctx.user.as_ref()) {
 		return Err(Error::PermissionDenied);
 	}
-	// TODO: Password check when `view_password` exists
+	let title = post.title.to_owned().unwrap_or(String::from("Untitled"));
+	lock_unless_accepted(&gctx, &mut cookies, &mut post);
+	if post.view_password.is_some() {
+		return Ok(theme::password_form(
+			&gctx,
+			&title,
+			&uri!(password: id = post.id).to_string(),
+			false,
+		)?);
+	}
+	gctx.plugin_manager
+		.filter_content_before_render(post.id, &mut post.content);
 
 	let previous_author = cookies
 		.get_private("comment_author")
@@ -36,12 +51,91 @@ pub fn post_show(
 
 	Ok(theme::post_show(
 		&gctx,
-		format!(
-			"{}",
-			post.title.as_ref().unwrap_or(&String::from("Untitled"))
-		)
-		.as_str(),
-		post.into_interface(&gctx.db),
-		previous_author.into_interface(&gctx.db),
+		&title,
+		post.into_interface(&gctx.repository()),
+		previous_author.into_interface(&gctx.repository()),
 	)?)
 }
+
+fn password_cookie_name(id: i32) -> String {
+	format!("content_password_{}", id)
+}
+
+/// Whether a protected post or page can be shown, users able to view all posts skip the password
+pub fn password_accepted(
+	gctx: &GlobalContext,
+	cookies: &mut Cookies,
+	post: &content::Content,
+) -> bool {
+	match &post.view_password {
+		None => true,
+		Some(_)
+			if gctx
+				.user
+				.as_ref()
+				.map_or(false, |u| u.has_permission(user::PERM_POST_VIEW)) =>
+		{
+			true
+		}
+		Some(password) => cookies
+			.get_private(&password_cookie_name(post.id))
+			.map_or(false, |c| c.value() == password),
+	}
+}
+
+/// Locks `post` unless `password_accepted`, otherwise `view_password` is cleared so themes
+/// show it in full
+pub fn lock_unless_accepted(
+	gctx: &GlobalContext,
+	cookies: &mut Cookies,
+	post: &mut content::Content,
+) {
+	if password_accepted(gctx, cookies, post) {
+		post.view_password = None;
+	} else {
+		post.lock();
+	}
+}
+
+#[derive(Default, FromForm, Debug)]
+pub struct PasswordForm {
+	pub password: String,
+}
+
+/// The password is remembered in a private cookie, and checked again on every view
+#[post("/content/<id>/password", data = "<form>")]
+pub fn password(
+	gctx: GlobalContext,
+	mut cookies: Cookies,
+	_csrf: CSRFTokenValidation,
+	id: i32,
+	form: LenientForm<PasswordForm>,
+) -> Result<Result<Redirect, RenderResult>, Error> {
+	let post = content::Content::find(&gctx.db, id)?;
+	if post.status == content::ContentStatus::Deleted
+		|| !post.user_has_access(gctx.user.as_ref())
+	{
+		return Err(Error::NotFound);
+	}
+	if post.view_password.as_ref() != Some(&form.password) {
+		return Ok(Err(theme::password_form(
+			&gctx,
+			post.title.as_ref().map_or("Untitled", |t| t.as_str()),
+			&uri!(password: id = post.id).to_string(),
+			true,
+		)?));
+	}
+	cookies.add_private(
+		Cookie::build(password_cookie_name(post.id), form.password.to_owned())
+			.path("/")
+			.finish(),
+	);
+	let link = match post.r#type {
+		content::ContentType::SinglePage => {
+			let path = post.slug.to_owned().unwrap_or(post.id.to_string());
+			uri!(super::root::page_show: path = path).to_string()
+		}
+		_ => post.get_link(),
+	};
+	Ok(Ok(Redirect::to(link)))
+}
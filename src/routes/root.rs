@@ -8,13 +8,17 @@ use rocket::http::Cookies;
 use rocket_codegen::*;
 
 #[get("/?<page>")]
-pub fn index(gctx: GlobalContext, mut page: Page) -> Result<RenderResult, Error> {
+pub fn index(
+	gctx: GlobalContext,
+	mut cookies: Cookies,
+	mut page: Page,
+) -> Result<RenderResult, Error> {
 	let post_status = if let None = gctx.user {
 		content::ContentStatus::PUBLIC_LIST.to_vec()
 	} else {
 		content::ContentStatus::LOGGED_IN_LIST.to_vec()
 	};
-	let posts = content::Content::find_posts(
+	let mut posts = content::Content::find_posts(
 		&gctx.db,
 		page.range(super::post::ITEMS_PER_PAGE),
 		&post_status,
@@ -24,12 +28,17 @@ pub fn index(gctx: GlobalContext, mut page: Page) -> Result<RenderResult, Error>
 		content::Content::count_post(&gctx.db, &post_status)? as i32,
 		super::post::ITEMS_PER_PAGE,
 	);
+	for post in &mut posts {
+		super::post::lock_unless_accepted(&gctx, &mut cookies, post);
+		gctx.plugin_manager
+			.filter_content_before_render(post.id, &mut post.content);
+	}
 
 	Ok(theme::post_list(
 		&gctx,
 		"Index",
 		page,
-		posts.into_interface(&gctx.db),
+		posts.into_interface(&gctx.repository()),
 	)?)
 }
 
@@ -40,7 +49,7 @@ pub fn page_show(
 	path: String,
 ) -> Result<RenderResult, Error> {
 	let slug = path.replace(".html", ""); // TODO: We just need to remove `.html` at the end
-	let post: content::Content = content::Content::find_by_slug(&gctx.db, &slug)?;
+	let mut post: content::Content = content::Content::find_by_slug(&gctx.db, &slug)?;
 	if post.status == content::ContentStatus::Deleted
 		|| post.r#type != content::ContentType::SinglePage
 	{
@@ -49,19 +58,26 @@ pub fn page_show(
 	if !post.user_has_access(gctx.user.as_ref()) {
 		return Err(Error::PermissionDenied);
 	}
-	// TODO: Password check when `view_password` exists
+	let title = post.title.to_owned().unwrap_or(String::from("Untitled"));
+	super::post::lock_unless_accepted(&gctx, &mut cookies, &mut post);
+	if post.view_password.is_some() {
+		return Ok(theme::password_form(
+			&gctx,
+			&title,
+			&uri!(super::post::password: id = post.id).to_string(),
+			false,
+		)?);
+	}
+	gctx.plugin_manager
+		.filter_content_before_render(post.id, &mut post.content);
 
 	let previous_author = cookies
 		.get_private("comment_author")
 		.and_then(|c| serde_json::from_str::<Author>(c.value()).ok());
-	Ok(theme::post_show(
+	Ok(theme::page_show(
 		&gctx,
-		format!(
-			"{}",
-			post.title.as_ref().unwrap_or(&String::from("Untitled"))
-		)
-		.as_str(),
-		post.into_interface(&gctx.db),
-		previous_author.into_interface(&gctx.db),
+		&title,
+		post.into_interface(&gctx.repository()),
+		previous_author.into_interface(&gctx.repository()),
 	)?)
 }
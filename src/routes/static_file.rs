@@ -1,35 +1,61 @@
 use crate::{
-	render::theme, templates::statics::StaticFile as SystemStaticFile, util::GlobalContext,
-};
-use mime::Mime;
-use rocket::{
-	http::{ContentType, Status},
-	response::Content,
+	assets::{AssetCache, AssetContent, StaticAsset},
+	render::theme::{self, ThemeStatic},
+	templates::statics::StaticFile as SystemStaticFile,
+	util::GlobalContext,
 };
+use rocket::{http::Status, request::State};
 use rocket_codegen::*;
 
+/// Names of system statics contain a hash of content, so they can be cached forever
 #[get("/system/<name>", rank = 12)]
-pub fn system(name: String) -> Result<Content<&'static [u8]>, Status> {
+pub fn system(cache: State<AssetCache>, name: String) -> Result<StaticAsset, Status> {
 	if let Some(f) = SystemStaticFile::get(name.as_str()) {
-		let mime: &Mime = &f.mime;
-		Ok(Content(
-			ContentType::new(mime.type_().as_str(), mime.subtype().as_str()),
-			f.content,
-		))
+		Ok(StaticAsset {
+			content: AssetContent::Embedded(f.content),
+			mime: f.mime.clone(),
+			etag: format!("\"{}\"", f.name),
+			immutable: true,
+			cache_key: format!("system/{}", f.name),
+			cache: cache.inner(),
+		})
 	} else {
 		Err(Status::NotFound)
 	}
 }
 
-#[get("/theme/<name>", rank = 12)]
-pub fn theme(gctx: GlobalContext, name: String) -> Result<Content<&'static [u8]>, Status> {
-	if let Some(f) = theme::get_static(&gctx, &name) {
-		let mime: &Mime = f.mime();
-		Ok(Content(
-			ContentType::new(mime.type_().as_str(), mime.subtype().as_str()),
-			f.content(),
-		))
-	} else {
-		Err(Status::NotFound)
+/// Files of theme plugins are immutable only if `v` matches, see `TemplateContext::theme_static`.
+/// Nothing borrowed from the theme is returned, it may be unloaded once `gctx` is dropped.
+#[get("/theme/<name>?<v>", rank = 12)]
+pub fn theme(
+	gctx: GlobalContext,
+	cache: State<AssetCache>,
+	name: String,
+	v: Option<String>,
+) -> Result<StaticAsset, Status> {
+	match theme::get_static(&gctx, &name) {
+		Some(ThemeStatic::Builtin(f)) => Ok(StaticAsset {
+			content: AssetContent::Embedded(f.content),
+			mime: f.mime.clone(),
+			etag: format!("\"{}\"", f.name),
+			immutable: true,
+			cache_key: format!("theme/{}", f.name),
+			cache: cache.inner(),
+		}),
+		Some(ThemeStatic::Plugin(f)) => {
+			let version = gctx
+				.plugin_manager
+				.fingerprints()
+				.get(&gctx.theme_name, &name, f.as_ref());
+			Ok(StaticAsset {
+				content: AssetContent::Owned(f.content().to_vec()),
+				mime: f.mime().clone(),
+				etag: format!("\"{}\"", version),
+				immutable: v.as_ref() == Some(&version),
+				cache_key: format!("theme/{}/{}/{}", gctx.theme_name, name, version),
+				cache: cache.inner(),
+			})
+		}
+		None => Err(Status::NotFound),
 	}
 }
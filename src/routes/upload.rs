@@ -0,0 +1,302 @@
+//! Serves files of `local` storage, with caching headers and range requests.
+//!
+//! Files in other backends are served by themselves, see `storage::Storage::url`.
+
+use super::error::Error;
+use crate::{
+	db::Database,
+	models::{file::File, file_derivative::FileDerivative},
+	storage::{self, StorageManager},
+	upload,
+	util::*,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use rocket::{
+	http::{ContentType, Status},
+	request::{Request, State},
+	response::{self, Body, Responder, Response},
+};
+use rocket_codegen::*;
+use std::{
+	fs,
+	io::{Cursor, Read, Seek, SeekFrom},
+	path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+/// More ranges than this in one request are ignored, and the whole file is sent
+const MAX_RANGES: usize = 16;
+
+/// Filenames are all random, so a file never changes under the same URL
+const CACHE_CONTROL: &str = "public, max-age=2592000";
+
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+pub struct UploadedFile {
+	file: fs::File,
+	len: u64,
+	mime: String,
+	etag: String,
+	last_modified: DateTime<Utc>,
+	/// Original filename, sent in `Content-Disposition`
+	filename: Option<String>,
+	attachment: bool,
+}
+impl UploadedFile {
+	fn open(full_path: &Path, mime: String) -> Result<Self, Error> {
+		let file = fs::File::open(full_path).map_err(|_| Error::NotFound)?;
+		let metadata = file.metadata()?;
+		if !metadata.is_file() {
+			return Err(Error::NotFound);
+		}
+		let last_modified: DateTime<Utc> = metadata.modified()?.into();
+		// HTTP dates have no fraction of second
+		let last_modified = Utc.timestamp(last_modified.timestamp(), 0);
+		Ok(Self {
+			file: file,
+			len: metadata.len(),
+			mime: mime,
+			etag: format!("\"{:x}-{:x}\"", last_modified.timestamp(), metadata.len()),
+			last_modified: last_modified,
+			filename: None,
+			attachment: false,
+		})
+	}
+
+	fn last_modified_str(&self) -> String {
+		self.last_modified.format(HTTP_DATE_FORMAT).to_string()
+	}
+
+	/// Whether the client's copy is still fresh, `If-None-Match` takes precedence
+	fn is_not_modified(&self, req: &Request) -> bool {
+		if let Some(tags) = req.headers().get_one("If-None-Match") {
+			return tags
+				.split(',')
+				.map(|t| t.trim())
+				.any(|t| t == "*" || t.trim_start_matches("W/") == self.etag);
+		}
+		req.headers()
+			.get_one("If-Modified-Since")
+			.and_then(|s| DateTime::parse_from_rfc2822(s).ok())
+			.map(|since| self.last_modified <= since.with_timezone(&Utc))
+			.unwrap_or(false)
+	}
+
+	/// `Range` is ignored if `If-Range` doesn't match, so a changed file is sent in whole
+	fn requested_ranges(&self, req: &Request) -> Option<Vec<(u64, u64)>> {
+		let range = req.headers().get_one("Range")?;
+		if let Some(condition) = req.headers().get_one("If-Range") {
+			if condition != self.etag && condition != self.last_modified_str() {
+				return None;
+			}
+		}
+		parse_ranges(range, self.len)
+	}
+
+	fn content_disposition(&self) -> Option<String> {
+		let kind = if self.attachment {
+			"attachment"
+		} else {
+			"inline"
+		};
+		match &self.filename {
+			Some(name) => Some(format!(
+				"{}; filename=\"{}\"; filename*=UTF-8''{}",
+				kind,
+				ascii_filename(name),
+				percent_encode(name)
+			)),
+			None if self.attachment => Some(String::from(kind)),
+			None => None,
+		}
+	}
+}
+impl<'r> Responder<'r> for UploadedFile {
+	fn respond_to(mut self, req: &Request) -> response::Result<'r> {
+		let mut response = Response::build();
+		response
+			.raw_header("ETag", self.etag.to_owned())
+			.raw_header("Last-Modified", self.last_modified_str())
+			.raw_header("Cache-Control", CACHE_CONTROL)
+			.raw_header("Accept-Ranges", "bytes")
+			.raw_header("X-Content-Type-Options", "nosniff");
+		if self.is_not_modified(req) {
+			return response.status(Status::NotModified).ok();
+		}
+		if let Some(disposition) = self.content_disposition() {
+			response.raw_header("Content-Disposition", disposition);
+		}
+		let content_type = ContentType::parse_flexible(&self.mime).unwrap_or(ContentType::Binary);
+
+		let ranges = match self.requested_ranges(req) {
+			Some(ranges) => ranges,
+			None => {
+				return response
+					.header(content_type)
+					.sized_body(self.file)
+					.ok();
+			}
+		};
+		match ranges.as_slice() {
+			[] => response
+				.status(Status::RangeNotSatisfiable)
+				.raw_header("Content-Range", format!("bytes */{}", self.len))
+				.ok(),
+			[(start, end)] => {
+				self.file
+					.seek(SeekFrom::Start(*start))
+					.map_err(|_| Status::InternalServerError)?;
+				let len = end - start + 1;
+				response
+					.status(Status::PartialContent)
+					.header(content_type)
+					.raw_header(
+						"Content-Range",
+						format!("bytes {}-{}/{}", start, end, self.len),
+					)
+					.raw_body(Body::Sized(self.file.take(len), len))
+					.ok()
+			}
+			_ => {
+				let boundary = Uuid::new_v4().to_simple().to_string();
+				let mut body = Vec::new();
+				for (start, end) in &ranges {
+					body.extend_from_slice(
+						format!(
+							"--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+							boundary, content_type, start, end, self.len
+						)
+						.as_bytes(),
+					);
+					self.file
+						.seek(SeekFrom::Start(*start))
+						.map_err(|_| Status::InternalServerError)?;
+					(&mut self.file)
+						.take(end - start + 1)
+						.read_to_end(&mut body)
+						.map_err(|_| Status::InternalServerError)?;
+					body.extend_from_slice(b"\r\n");
+				}
+				body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+				response
+					.status(Status::PartialContent)
+					.raw_header(
+						"Content-Type",
+						format!("multipart/byteranges; boundary={}", boundary),
+					)
+					.sized_body(Cursor::new(body))
+					.ok()
+			}
+		}
+	}
+}
+
+/// Parses `Range` header into inclusive byte ranges, sorted with overlapping ones merged.
+/// Returns `None` if the header should be ignored, and an empty list if nothing is satisfiable.
+/// Ranges adding up to more than the file are ignored too, as sending them costs more than the file.
+fn parse_ranges(header: &str, len: u64) -> Option<Vec<(u64, u64)>> {
+	let header = header.trim();
+	if !header.starts_with("bytes=") {
+		return None;
+	}
+	let mut ranges = Vec::new();
+	for spec in header["bytes=".len()..].split(',') {
+		let spec = spec.trim();
+		let dash = spec.find('-')?;
+		let (first, last) = (spec[..dash].trim(), spec[dash + 1..].trim());
+		let range = if first.is_empty() {
+			// suffix like `-500`, the last 500 bytes
+			let suffix = last.parse::<u64>().ok()?;
+			if suffix == 0 || len == 0 {
+				continue;
+			}
+			(len.saturating_sub(suffix), len - 1)
+		} else {
+			let start = first.parse::<u64>().ok()?;
+			let end = match last {
+				"" => None,
+				s => Some(s.parse::<u64>().ok()?),
+			};
+			if end.map(|e| e < start).unwrap_or(false) {
+				return None; // invalid
+			}
+			if start >= len {
+				continue;
+			}
+			(start, end.unwrap_or(len - 1).min(len - 1))
+		};
+		ranges.push(range);
+	}
+	let requested = ranges
+		.iter()
+		.fold(0u64, |sum, (start, end)| sum.saturating_add(end - start + 1));
+	if ranges.len() > MAX_RANGES || requested > len {
+		return None;
+	}
+	ranges.sort();
+	let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+	for (start, end) in ranges {
+		match merged.last_mut() {
+			// adjacent ones are merged too
+			Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+			_ => merged.push((start, end)),
+		}
+	}
+	Some(merged)
+}
+
+/// Fallback for clients not supporting `filename*`
+fn ascii_filename(name: &str) -> String {
+	name.chars()
+		.map(|c| match c {
+			' '..='~' if c != '"' && c != '\\' => c,
+			_ => '_',
+		})
+		.collect()
+}
+
+fn percent_encode(s: &str) -> String {
+	s.bytes()
+		.map(|b| match b {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => {
+				(b as char).to_string()
+			}
+			_ => format!("%{:02X}", b),
+		})
+		.collect()
+}
+
+/// Mounted at `upload_route`. `?download=true` asks browsers to save the file with its original name.
+/// Only state is taken, unlike `GlobalContext` it doesn't wait for plugins being reloaded.
+#[get("/<path..>?<download>", rank = 12)]
+pub fn serve(
+	db: State<Box<Database>>,
+	system_config: State<SystemConfig>,
+	storage: State<StorageManager>,
+	path: PathBuf,
+	download: Option<bool>,
+) -> Result<UploadedFile, Error> {
+	let path_str = path.to_str().ok_or(Error::NotFound)?.replace('\\', "/");
+	let url = storage.url(&StorageManager::make_key("local", &path_str))?;
+	let keys = storage.keys_from_url(&url);
+
+	let (mime, filename) = match File::find_by_keys(&db, &keys) {
+		Ok(file) => (file.mime, Some(file.filename)),
+		Err(_) => match FileDerivative::find_by_keys(&db, &keys)? {
+			Some((derivative, file)) => (derivative.mime, Some(file.filename)),
+			// files uploaded before `file` table tracked them
+			None => (
+				storage::mime_guess(&path_str)
+					.unwrap_or("application/octet-stream")
+					.to_owned(),
+				None,
+			),
+		},
+	};
+	let full_path = Path::new(&system_config.upload_dir).join(&path);
+	let mut file = UploadedFile::open(&full_path, mime)?;
+	file.filename = filename;
+	// active content must not run in the origin of this site
+	file.attachment = download.unwrap_or(false) || upload::is_active(&file.mime);
+	Ok(file)
+}
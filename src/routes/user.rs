@@ -1,17 +1,77 @@
-use crate::{models::user, render::RenderResult, templates, util::*};
-use rocket::{http::Cookies, request::LenientForm, response::Redirect};
+use crate::{
+	models::{
+		audit_log::{self, Audit},
+		login_history::{LoginHistory, LoginMethod, LoginResult},
+		user,
+	},
+	oidc::{self, AuthorizationState, OidcClient},
+	render::RenderResult,
+	templates,
+	util::*,
+};
+use rocket::{
+	http::{Cookie, Cookies, SameSite},
+	request::LenientForm,
+	response::Redirect,
+	State,
+};
 use rocket_codegen::*;
 
 #[get("/user/login")]
-pub fn login_get(gctx: GlobalContext) -> RenderResult {
+pub fn login_get(gctx: GlobalContext, oidc_client: State<Option<OidcClient>>) -> RenderResult {
 	render!(
 		templates::user::login,
 		&gctx.get_template_context(),
 		None,
-		None
+		None,
+		oidc_client.is_some()
 	)
 }
 
+/// Writes a login attempt into login history, failing to write doesn't fail the login
+fn record_login(
+	gctx: &GlobalContext,
+	user: Option<&user::User>,
+	username: Option<&str>,
+	result: LoginResult,
+	method: LoginMethod,
+) {
+	if let Err(e) = LoginHistory::record(
+		&gctx.db,
+		user,
+		username,
+		Some(VisitorIP(gctx.ip.0).to_ipnetwork()),
+		gctx.user_agent.as_ref().map(|s| s.as_str()),
+		result,
+		method,
+	) {
+		println!("Failed to write login history: {:?}", e);
+	}
+}
+
+/// Saves `user` into session after it's authenticated
+fn login_as(
+	gctx: &mut GlobalContext,
+	cookies: &mut Cookies,
+	audit: &Audit,
+	user: &user::User,
+	method: LoginMethod,
+) {
+	gctx.session_info.user = Some(user.to_session_info());
+	gctx.session_info.persist(cookies, &gctx.system_config);
+	record_login(gctx, Some(user), Some(&user.username), LoginResult::Success, method);
+	if let Err(e) = user.update_last_login_time(&gctx.db) {
+		println!("Failed to update last login time: {:?}", e);
+	}
+	audit.log(
+		Some(user),
+		audit_log::ACTION_LOGIN,
+		Some(("user", user.id)),
+		None,
+		Some(serde_json::json!({ "method": method })),
+	);
+}
+
 #[derive(Default, FromForm, Debug)]
 pub struct LoginForm {
 	pub username: String,
@@ -23,18 +83,123 @@ pub fn login_post(
 	_csrf: CSRFTokenValidation,
 	mut cookies: Cookies,
 	form: LenientForm<LoginForm>,
+	oidc_client: State<Option<OidcClient>>,
+	audit: Audit,
 ) -> Result<Redirect, RenderResult> {
-	if let Ok(user) = user::User::find_by_username(&gctx.db, form.username.as_str()) {
-		if user.verify_password_hash(form.password.as_str()) {
-			gctx.session_info.user = Some(user.to_session_info());
-			gctx.session_info.persist(&mut cookies, &gctx.system_config);
+	let user = user::User::find_by_username(&gctx.db, form.username.as_str()).ok();
+	if let Some(user) = &user {
+		if user.status == user::UserStatus::Normal
+			&& user.verify_password_hash(form.password.as_str())
+		{
+			login_as(&mut gctx, &mut cookies, &audit, user, LoginMethod::Password);
 			return Ok(Redirect::to("/admin"));
 		}
 	}
+	record_login(
+		&gctx,
+		user.as_ref(),
+		Some(&form.username),
+		LoginResult::BadCredential,
+		LoginMethod::Password,
+	);
+	audit.log(
+		None, // nobody is authenticated, the user tried is the target
+		audit_log::ACTION_LOGIN_FAILED,
+		user.as_ref().map(|u| ("user", u.id)),
+		None,
+		Some(serde_json::json!({ "method": "password", "username": form.username })),
+	);
 	Err(render!(
 		templates::user::login,
 		&gctx.get_template_context(),
 		Some(String::from("Wrong username or password")),
-		Some(String::from(&form.username))
+		Some(String::from(&form.username)),
+		oidc_client.is_some()
 	))
 }
+
+#[get("/user/login/oidc")]
+pub fn oidc_login(
+	gctx: GlobalContext,
+	oidc_client: State<Option<OidcClient>>,
+	mut cookies: Cookies,
+) -> Result<Redirect, RenderResult> {
+	let client = match oidc_client.inner().as_ref() {
+		Some(c) => c,
+		None => return Ok(Redirect::to(uri!(login_get))),
+	};
+	match client.authorization_url() {
+		Ok((url, state)) => {
+			cookies.add_private(
+				Cookie::build(
+					oidc::STATE_COOKIE_NAME,
+					serde_json::to_string(&state).unwrap_or_default(),
+				)
+				.path("/")
+				.max_age(time::Duration::minutes(10))
+				// sent back by the cross-site redirect from provider, which `Strict` would block
+				.same_site(SameSite::Lax)
+				.finish(),
+			);
+			Ok(Redirect::to(url))
+		}
+		Err(e) => Err(render!(
+			templates::user::login,
+			&gctx.get_template_context(),
+			Some(e.to_string()),
+			None,
+			true
+		)),
+	}
+}
+
+#[get("/user/login/oidc/callback?<code>&<state>&<error>")]
+pub fn oidc_callback(
+	mut gctx: GlobalContext,
+	oidc_client: State<Option<OidcClient>>,
+	mut cookies: Cookies,
+	code: Option<String>,
+	state: Option<String>,
+	error: Option<String>,
+	audit: Audit,
+) -> Result<Redirect, RenderResult> {
+	let client = match oidc_client.inner().as_ref() {
+		Some(c) => c,
+		None => return Ok(Redirect::to(uri!(login_get))),
+	};
+	let saved_state = cookies
+		.get_private(oidc::STATE_COOKIE_NAME)
+		.and_then(|c| serde_json::from_str::<AuthorizationState>(c.value()).ok());
+	cookies.remove_private(Cookie::build(oidc::STATE_COOKIE_NAME, "").path("/").finish());
+
+	let result = match (saved_state, code, state, error) {
+		(_, _, _, Some(_)) => Err(oidc::Error::Invalid("Login was rejected by identity provider")),
+		(Some(saved), Some(code), Some(state), None) if saved.state == state => client
+			.exchange_code(&code, &saved)
+			.and_then(|claims| client.map_user(&gctx.db, &claims)),
+		_ => Err(oidc::Error::Invalid("Login session expired, please try again")),
+	};
+	match result {
+		Ok(user) => {
+			login_as(&mut gctx, &mut cookies, &audit, &user, LoginMethod::Oidc);
+			Ok(Redirect::to("/admin"))
+		}
+		Err(e) => {
+			record_login(&gctx, None, None, LoginResult::Rejected, LoginMethod::Oidc);
+			audit.log(
+				None,
+				audit_log::ACTION_LOGIN_FAILED,
+				None,
+				None,
+				Some(serde_json::json!({ "method": "oidc", "reason": e.to_string() })),
+			);
+			Err(render!(
+				templates::user::login,
+				&gctx.get_template_context(),
+				Some(e.to_string()),
+				None,
+				true
+			))
+		}
+	}
+}
@@ -1,3 +1,17 @@
+table! {
+    api_token (id) {
+        id -> Int4,
+        user -> Int4,
+        name -> Varchar,
+        token_hash -> Varchar,
+        scope -> Int4,
+        created_at -> Timestamptz,
+        expires_at -> Nullable<Timestamptz>,
+        last_used_at -> Nullable<Timestamptz>,
+        last_used_ip -> Nullable<Inet>,
+    }
+}
+
 table! {
     assoc_tag_content (id) {
         id -> Int4,
@@ -6,6 +20,29 @@ table! {
     }
 }
 
+table! {
+    audit_log (id) {
+        id -> Int4,
+        user -> Nullable<Int4>,
+        ip -> Nullable<Inet>,
+        action -> Varchar,
+        target_type -> Nullable<Varchar>,
+        target_id -> Nullable<Int4>,
+        detail -> Nullable<Text>,
+        time -> Timestamptz,
+    }
+}
+
+table! {
+    blob (id) {
+        id -> Int4,
+        hash -> Varchar,
+        key -> Varchar,
+        ref_count -> Int4,
+        time -> Timestamptz,
+    }
+}
+
 table! {
     category (id) {
         id -> Int4,
@@ -56,6 +93,7 @@ table! {
         allow_comment -> Bool,
         allow_feed -> Bool,
         parent -> Nullable<Int4>,
+        featured_file -> Nullable<Int4>,
     }
 }
 
@@ -67,6 +105,46 @@ table! {
         content -> Nullable<Int4>,
         user -> Int4,
         time -> Timestamptz,
+        width -> Nullable<Int4>,
+        height -> Nullable<Int4>,
+        mime -> Varchar,
+        size -> Int8,
+        hash -> Nullable<Varchar>,
+        draft_token -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    file_derivative (id) {
+        id -> Int4,
+        file -> Int4,
+        key -> Varchar,
+        width -> Int4,
+        height -> Int4,
+        mime -> Varchar,
+        time -> Timestamptz,
+    }
+}
+
+table! {
+    login_history (id) {
+        id -> Int4,
+        user -> Nullable<Int4>,
+        username -> Nullable<Varchar>,
+        ip -> Nullable<Inet>,
+        user_agent -> Nullable<Text>,
+        result -> Int4,
+        method -> Int4,
+        new_network -> Bool,
+        time -> Timestamptz,
+    }
+}
+
+table! {
+    setting (key) {
+        key -> Varchar,
+        value -> Text,
+        modified_at -> Timestamptz,
     }
 }
 
@@ -93,24 +171,35 @@ table! {
         modified_at -> Timestamptz,
         last_login_time -> Timestamptz,
         status -> Int4,
+        oidc_subject -> Nullable<Varchar>,
     }
 }
 
+joinable!(api_token -> user (user));
 joinable!(assoc_tag_content -> content (content));
 joinable!(assoc_tag_content -> tag (tag));
+joinable!(audit_log -> user (user));
 joinable!(comment -> content (content));
 joinable!(comment -> user (user));
 joinable!(content -> category (category));
 joinable!(content -> user (user));
 joinable!(file -> content (content));
 joinable!(file -> user (user));
+joinable!(file_derivative -> file (file));
+joinable!(login_history -> user (user));
 
 allow_tables_to_appear_in_same_query!(
+    api_token,
     assoc_tag_content,
+    audit_log,
+    blob,
     category,
     comment,
     content,
     file,
+    file_derivative,
+    login_history,
+    setting,
     tag,
     user,
 );
@@ -0,0 +1,74 @@
+use super::{Result, Storage};
+use std::{
+	fs,
+	io::ErrorKind,
+	path::{Component, Path, PathBuf},
+};
+
+/// Saves files under `upload_dir`, which is served by `upload_route`
+pub struct LocalStorage {
+	root: PathBuf,
+	base_url: String,
+}
+impl LocalStorage {
+	pub fn new(root: &str, base_url: &str) -> Self {
+		Self {
+			root: PathBuf::from(root),
+			base_url: base_url.trim_end_matches('/').to_owned(),
+		}
+	}
+
+	/// Full path on disk, paths escaping `root` are rejected
+	fn full_path(&self, path: &str) -> Result<PathBuf> {
+		let relative = Path::new(path);
+		let is_normal = |c: Component| match c {
+			Component::Normal(_) => true,
+			_ => false,
+		};
+		if !relative.components().all(is_normal) {
+			return Err(super::Error::InvalidKey(path.to_owned()));
+		}
+		Ok(self.root.join(relative))
+	}
+
+	fn create_parent(full: &Path) -> Result<()> {
+		if let Some(parent) = full.parent() {
+			fs::create_dir_all(parent)?;
+		}
+		Ok(())
+	}
+}
+impl Storage for LocalStorage {
+	fn name(&self) -> &'static str {
+		"local"
+	}
+
+	fn put(&self, path: &str, data: &[u8], _content_type: Option<&str>) -> Result<()> {
+		let full = self.full_path(path)?;
+		Self::create_parent(&full)?;
+		fs::write(full, data)?;
+		Ok(())
+	}
+
+	fn put_file(&self, path: &str, source: &Path, _content_type: Option<&str>) -> Result<()> {
+		let full = self.full_path(path)?;
+		Self::create_parent(&full)?;
+		fs::copy(source, full)?;
+		Ok(())
+	}
+
+	fn get(&self, path: &str) -> Result<Vec<u8>> {
+		Ok(fs::read(self.full_path(path)?)?)
+	}
+
+	fn delete(&self, path: &str) -> Result<()> {
+		match fs::remove_file(self.full_path(path)?) {
+			Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+			_ => Ok(()),
+		}
+	}
+
+	fn url(&self, path: &str) -> String {
+		format!("{}/{}", self.base_url, path)
+	}
+}
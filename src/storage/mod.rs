@@ -0,0 +1,231 @@
+//! Backends for uploaded files.
+//!
+//! A file key saved in database is like `local:201906/mori.love`, the part before `:` names the
+//! backend, and the rest is the path inside it. Keys from older versions like
+//! `{upload_dir}/201906/mori.love` are treated as `local`.
+
+mod local;
+mod s3;
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+use std::{collections::HashMap, env, fmt, fs, path::Path, sync::Arc};
+use uuid::Uuid;
+
+const LEGACY_KEY_PREFIX: &str = "{upload_dir}/";
+
+#[derive(Debug)]
+pub enum Error {
+	Io(std::io::Error),
+	Http(reqwest::Error),
+	/// Non-success response from remote, with status code and body
+	Status(u16, String),
+	UnknownBackend(String),
+	InvalidKey(String),
+	Config(&'static str),
+}
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::Io(e) => write!(f, "IO error: {}", e),
+			Error::Http(e) => write!(f, "HTTP error: {}", e),
+			Error::Status(code, body) => write!(f, "Remote responded {}: {}", code, body),
+			Error::UnknownBackend(name) => write!(f, "Unknown storage backend `{}`", name),
+			Error::InvalidKey(key) => write!(f, "Invalid file key `{}`", key),
+			Error::Config(s) => write!(f, "Invalid storage config: {}", s),
+		}
+	}
+}
+impl From<std::io::Error> for Error {
+	fn from(e: std::io::Error) -> Self {
+		Error::Io(e)
+	}
+}
+impl From<reqwest::Error> for Error {
+	fn from(e: reqwest::Error) -> Self {
+		Error::Http(e)
+	}
+}
+pub type Result<T> = std::result::Result<T, Error>;
+
+pub trait Storage: Send + Sync {
+	/// Name used as key prefix, like `local`
+	fn name(&self) -> &'static str;
+
+	fn put(&self, path: &str, data: &[u8], content_type: Option<&str>) -> Result<()>;
+
+	/// Saves a file on local disk, e.g. a temporary file of multipart body
+	fn put_file(&self, path: &str, source: &Path, content_type: Option<&str>) -> Result<()> {
+		self.put(path, &fs::read(source)?, content_type)
+	}
+
+	fn get(&self, path: &str) -> Result<Vec<u8>>;
+
+	/// Deleting a file that doesn't exist is not an error
+	fn delete(&self, path: &str) -> Result<()>;
+
+	/// Public URL for visitors
+	fn url(&self, path: &str) -> String;
+
+	/// Reverse of `url`, returns `None` if `url` is not in this backend
+	fn path_from_url(&self, url: &str) -> Option<String> {
+		let base = self.url("");
+		if url.starts_with(&base) && url.len() > base.len() {
+			Some(url[base.len()..].to_owned())
+		} else {
+			None
+		}
+	}
+}
+
+/// Cheap to clone, so it can be moved into background jobs
+#[derive(Clone)]
+pub struct StorageManager {
+	backends: HashMap<&'static str, Arc<dyn Storage>>,
+	/// Backend for new uploads
+	default: &'static str,
+}
+impl StorageManager {
+	/// Local storage is always available, S3 is enabled if `SOHABLOG_S3_BUCKET` is set.
+	/// `SOHABLOG_STORAGE` selects the backend for new uploads.
+	pub fn from_env(upload_dir: &str, upload_route: &str) -> Result<Self> {
+		let mut backends: HashMap<&'static str, Arc<dyn Storage>> = HashMap::new();
+		let local = LocalStorage::new(upload_dir, upload_route);
+		backends.insert(local.name(), Arc::new(local));
+		if let Some(s3) = S3Storage::from_env()? {
+			backends.insert(s3.name(), Arc::new(s3));
+		}
+
+		let default_name = env::var("SOHABLOG_STORAGE").unwrap_or(String::from("local"));
+		let default = backends
+			.keys()
+			.find(|&&name| name == default_name)
+			.cloned()
+			.ok_or_else(|| Error::UnknownBackend(default_name))?;
+		Ok(Self {
+			backends: backends,
+			default: default,
+		})
+	}
+
+	pub fn backend(&self, name: &str) -> Result<&dyn Storage> {
+		self.backends
+			.get(name)
+			.map(|b| b.as_ref())
+			.ok_or_else(|| Error::UnknownBackend(name.to_owned()))
+	}
+
+	/// Splits a key into backend name and path
+	pub fn parse_key(key: &str) -> Result<(&str, &str)> {
+		if key.starts_with(LEGACY_KEY_PREFIX) {
+			return Ok(("local", &key[LEGACY_KEY_PREFIX.len()..]));
+		}
+		let mut parts = key.splitn(2, ':');
+		match (parts.next(), parts.next()) {
+			(Some(backend), Some(path)) if backend.len() > 0 && path.len() > 0 => {
+				Ok((backend, path))
+			}
+			_ => Err(Error::InvalidKey(key.to_owned())),
+		}
+	}
+
+	pub fn make_key(backend: &str, path: &str) -> String {
+		format!("{}:{}", backend, path)
+	}
+
+	/// Keys which may have `url`, including the legacy form for local files
+	pub fn keys_from_url(&self, url: &str) -> Vec<String> {
+		let mut keys = Vec::new();
+		for (name, backend) in &self.backends {
+			if let Some(path) = backend.path_from_url(url) {
+				if *name == "local" {
+					keys.push(format!("{}{}", LEGACY_KEY_PREFIX, path));
+				}
+				keys.push(Self::make_key(name, &path));
+			}
+		}
+		keys
+	}
+
+	/// New path like `201906/<uuid>.png`, `extension` includes the dot
+	pub fn new_path(extension: &str) -> String {
+		format!(
+			"{}/{}{}",
+			chrono::Utc::now().format("%Y%m"),
+			Uuid::new_v4(),
+			extension
+		)
+	}
+
+	/// Saves a local file into default backend, returns the key
+	pub fn put_file(&self, path: &str, source: &Path, content_type: Option<&str>) -> Result<String> {
+		let backend = self.backend(self.default)?;
+		backend.put_file(path, source, content_type)?;
+		Ok(Self::make_key(self.default, path))
+	}
+
+	/// Saves data into default backend, returns the key
+	pub fn put(&self, path: &str, data: &[u8], content_type: Option<&str>) -> Result<String> {
+		let backend = self.backend(self.default)?;
+		backend.put(path, data, content_type)?;
+		Ok(Self::make_key(self.default, path))
+	}
+
+	/// Saves data next to the file of `key`, in the same backend, returns the new key
+	pub fn put_beside(
+		&self,
+		key: &str,
+		path: &str,
+		data: &[u8],
+		content_type: Option<&str>,
+	) -> Result<String> {
+		let (backend, _) = Self::parse_key(key)?;
+		self.backend(backend)?.put(path, data, content_type)?;
+		Ok(Self::make_key(backend, path))
+	}
+
+	pub fn get(&self, key: &str) -> Result<Vec<u8>> {
+		let (backend, path) = Self::parse_key(key)?;
+		self.backend(backend)?.get(path)
+	}
+
+	pub fn delete(&self, key: &str) -> Result<()> {
+		let (backend, path) = Self::parse_key(key)?;
+		self.backend(backend)?.delete(path)
+	}
+
+	pub fn url(&self, key: &str) -> Result<String> {
+		let (backend, path) = Self::parse_key(key)?;
+		Ok(self.backend(backend)?.url(path))
+	}
+
+	/// Copies the file of `key` into backend `to`, returns the new key.
+	/// The source is kept, so old links still work until it's deleted.
+	pub fn copy_to(&self, key: &str, to: &str) -> Result<String> {
+		let (_, path) = Self::parse_key(key)?;
+		let data = self.get(key)?;
+		let content_type = mime_guess(path);
+		self.backend(to)?.put(path, &data, content_type)?;
+		Ok(Self::make_key(to, path))
+	}
+}
+
+/// Guesses content type of common uploads from extension
+pub fn mime_guess(path: &str) -> Option<&'static str> {
+	let ext = path.rsplit('.').next()?.to_lowercase();
+	Some(match ext.as_str() {
+		"jpg" | "jpeg" => "image/jpeg",
+		"png" => "image/png",
+		"gif" => "image/gif",
+		"webp" => "image/webp",
+		"bmp" => "image/bmp",
+		"svg" => "image/svg+xml",
+		"pdf" => "application/pdf",
+		"txt" => "text/plain",
+		"zip" => "application/zip",
+		"mp3" => "audio/mpeg",
+		"mp4" => "video/mp4",
+		_ => return None,
+	})
+}
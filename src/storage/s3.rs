@@ -0,0 +1,200 @@
+use super::{Error, Result, Storage};
+use chrono::Utc;
+use data_encoding::HEXLOWER;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Method, Url};
+use sha2::{Digest, Sha256};
+use std::{env, io::Read};
+
+const SIGN_ALGORITHM: &str = "AWS4-HMAC-SHA256";
+const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+/// S3-compatible object storage, e.g. AWS S3 or MinIO.
+/// Requests are signed with AWS Signature Version 4.
+pub struct S3Storage {
+	endpoint: Url,
+	bucket: String,
+	region: String,
+	access_key: String,
+	secret_key: String,
+	/// `https://endpoint/bucket/key` instead of `https://bucket.endpoint/key`, most self-hosted services need this
+	path_style: bool,
+	/// Base URL for visitors, e.g. a CDN in front of the bucket
+	public_url: Option<String>,
+	http: Client,
+}
+impl S3Storage {
+	/// Returns `None` if `SOHABLOG_S3_BUCKET` is not set
+	pub fn from_env() -> Result<Option<Self>> {
+		let bucket = match env::var("SOHABLOG_S3_BUCKET") {
+			Ok(b) => b,
+			Err(_) => return Ok(None),
+		};
+		let var = |name: &'static str| env::var(name).map_err(|_| Error::Config(name));
+		let endpoint = Url::parse(&var("SOHABLOG_S3_ENDPOINT")?)
+			.map_err(|_| Error::Config("SOHABLOG_S3_ENDPOINT"))?;
+		Ok(Some(Self {
+			endpoint: endpoint,
+			bucket: bucket,
+			region: env::var("SOHABLOG_S3_REGION").unwrap_or(String::from("us-east-1")),
+			access_key: var("SOHABLOG_S3_ACCESS_KEY")?,
+			secret_key: var("SOHABLOG_S3_SECRET_KEY")?,
+			path_style: env::var("SOHABLOG_S3_PATH_STYLE")
+				.map(|s| s != "false" && s != "0")
+				.unwrap_or(true),
+			public_url: env::var("SOHABLOG_S3_PUBLIC_URL")
+				.ok()
+				.map(|s| s.trim_end_matches('/').to_owned()),
+			http: Client::new(),
+		}))
+	}
+
+	/// Encodes everything except unreserved characters, `/` is kept for paths
+	fn uri_encode(s: &str, keep_slash: bool) -> String {
+		let mut encoded = String::with_capacity(s.len());
+		for b in s.bytes() {
+			match b {
+				b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+					encoded.push(b as char)
+				}
+				b'/' if keep_slash => encoded.push('/'),
+				_ => encoded.push_str(&format!("%{:02X}", b)),
+			}
+		}
+		encoded
+	}
+
+	fn object_url(&self, path: &str) -> Result<Url> {
+		let path = Self::uri_encode(path, true);
+		let base = self.endpoint.as_str().trim_end_matches('/');
+		let url = if self.path_style {
+			format!("{}/{}/{}", base, Self::uri_encode(&self.bucket, false), path)
+		} else {
+			let host = self.endpoint.host_str().ok_or(Error::Config("SOHABLOG_S3_ENDPOINT"))?;
+			let mut url = self.endpoint.clone();
+			url.set_host(Some(&format!("{}.{}", self.bucket, host)))
+				.map_err(|_| Error::Config("SOHABLOG_S3_BUCKET"))?;
+			format!("{}/{}", url.as_str().trim_end_matches('/'), path)
+		};
+		Url::parse(&url).map_err(|_| Error::InvalidKey(path))
+	}
+
+	fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+		let mut mac = Hmac::<Sha256>::new_varkey(key).expect("HMAC accepts keys of any length");
+		mac.input(data.as_bytes());
+		mac.result().code().to_vec()
+	}
+
+	/// Returns `(x-amz-date, Authorization)` headers
+	fn sign(&self, method: &Method, url: &Url, payload_hash: &str) -> (String, String) {
+		let now = Utc::now();
+		let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+		let date = now.format("%Y%m%d").to_string();
+		let host = match url.port() {
+			Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+			None => url.host_str().unwrap_or_default().to_owned(),
+		};
+
+		let canonical_request = format!(
+			"{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+			method.as_str(),
+			url.path(),
+			host,
+			payload_hash,
+			amz_date,
+			SIGNED_HEADERS,
+			payload_hash
+		);
+		let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+		let string_to_sign = format!(
+			"{}\n{}\n{}\n{}",
+			SIGN_ALGORITHM,
+			amz_date,
+			scope,
+			HEXLOWER.encode(&Sha256::digest(canonical_request.as_bytes()))
+		);
+
+		let key = Self::hmac(format!("AWS4{}", self.secret_key).as_bytes(), &date);
+		let key = Self::hmac(&key, &self.region);
+		let key = Self::hmac(&key, "s3");
+		let key = Self::hmac(&key, "aws4_request");
+		let signature = HEXLOWER.encode(&Self::hmac(&key, &string_to_sign));
+
+		let authorization = format!(
+			"{} Credential={}/{}, SignedHeaders={}, Signature={}",
+			SIGN_ALGORITHM, self.access_key, scope, SIGNED_HEADERS, signature
+		);
+		(amz_date, authorization)
+	}
+
+	fn request(
+		&self,
+		method: Method,
+		path: &str,
+		body: Option<(&[u8], Option<&str>)>,
+	) -> Result<reqwest::Response> {
+		let url = self.object_url(path)?;
+		let payload_hash = HEXLOWER.encode(&Sha256::digest(body.map(|(b, _)| b).unwrap_or(&[])));
+		let (amz_date, authorization) = self.sign(&method, &url, &payload_hash);
+		let mut builder = self
+			.http
+			.request(method, url)
+			.header("x-amz-date", amz_date)
+			.header("x-amz-content-sha256", payload_hash)
+			.header("Authorization", authorization);
+		if let Some((data, content_type)) = body {
+			if let Some(content_type) = content_type {
+				builder = builder.header("Content-Type", content_type);
+			}
+			builder = builder.body(data.to_vec());
+		}
+		Ok(builder.send()?)
+	}
+
+	fn check(mut res: reqwest::Response) -> Result<reqwest::Response> {
+		if res.status().is_success() {
+			Ok(res)
+		} else {
+			Err(Error::Status(
+				res.status().as_u16(),
+				res.text().unwrap_or_default(),
+			))
+		}
+	}
+}
+impl Storage for S3Storage {
+	fn name(&self) -> &'static str {
+		"s3"
+	}
+
+	fn put(&self, path: &str, data: &[u8], content_type: Option<&str>) -> Result<()> {
+		Self::check(self.request(Method::PUT, path, Some((data, content_type)))?)?;
+		Ok(())
+	}
+
+	fn get(&self, path: &str) -> Result<Vec<u8>> {
+		let mut res = Self::check(self.request(Method::GET, path, None)?)?;
+		let mut data = Vec::new();
+		res.read_to_end(&mut data)?;
+		Ok(data)
+	}
+
+	fn delete(&self, path: &str) -> Result<()> {
+		let res = self.request(Method::DELETE, path, None)?;
+		if res.status().as_u16() == 404 {
+			return Ok(());
+		}
+		Self::check(res)?;
+		Ok(())
+	}
+
+	fn url(&self, path: &str) -> String {
+		match &self.public_url {
+			Some(base) => format!("{}/{}", base, Self::uri_encode(path, true)),
+			None => self
+				.object_url(path)
+				.map(|u| u.into_string())
+				.unwrap_or_default(),
+		}
+	}
+}
@@ -0,0 +1,190 @@
+//! Validation of uploaded files.
+//!
+//! The type of a file is sniffed from its first bytes, and the client's filename and
+//! `Content-Type` are never trusted. The extension of the saved file follows the sniffed type.
+
+use crate::models::user::{self, User};
+use std::env;
+
+/// Bytes read for sniffing
+const SNIFF_LEN: usize = 512;
+
+/// Types which can run scripts when opened in a browser, never matched by wildcards
+const ACTIVE_TYPES: &[&str] = &["image/svg+xml", "text/html", "application/xhtml+xml", "text/xml"];
+
+const DEFAULT_ALLOW: &str = "author=image/jpeg,image/png,image/gif,image/webp,application/pdf,text/plain;editor=image/*,audio/*,video/*,application/pdf,application/zip,text/plain";
+
+#[derive(Debug)]
+pub struct UploadConfig {
+	/// Max size of a file in bytes, enforced while reading the body
+	pub max_size: u64,
+	/// `(permission, allowed types)`, a user with every bit of the permission may upload those types.
+	/// Types can be like `image/*` or `*`.
+	pub allow: Vec<(i32, Vec<String>)>,
+}
+impl UploadConfig {
+	/// `SOHABLOG_UPLOAD_ALLOW` is like `author=image/*,application/pdf;admin=*`,
+	/// a role is one of `user::ROLES` or a raw permission number.
+	pub fn from_env() -> Self {
+		let allow = env::var("SOHABLOG_UPLOAD_ALLOW").unwrap_or(String::from(DEFAULT_ALLOW));
+		Self {
+			max_size: env::var("SOHABLOG_UPLOAD_MAX_SIZE")
+				.ok()
+				.and_then(|s| s.parse::<u64>().ok())
+				.unwrap_or(10 * 1024 * 1024),
+			allow: allow
+				.split(';')
+				.filter_map(|rule| {
+					let mut parts = rule.splitn(2, '=');
+					let role = parts.next()?.trim();
+					let permission = user::ROLES
+						.iter()
+						.find(|(name, _)| *name == role)
+						.map(|(_, perm)| *perm)
+						.or_else(|| role.parse::<i32>().ok())?;
+					let types = parts
+						.next()?
+						.split(',')
+						.map(|t| t.trim().to_lowercase())
+						.filter(|t| t.len() > 0)
+						.collect();
+					Some((permission, types))
+				})
+				.collect(),
+		}
+	}
+
+	/// Whether `user` may upload a file of `mime`
+	pub fn is_allowed(&self, user: &User, mime: &str) -> bool {
+		let is_active = is_active(mime);
+		self.allow
+			.iter()
+			.filter(|(perm, _)| user.has_all_permissions(*perm))
+			.flat_map(|(_, types)| types.iter())
+			.any(|t| {
+				if t == mime {
+					return true;
+				}
+				if is_active {
+					return false; // must be listed explicitly
+				}
+				t == "*" || (t.ends_with("/*") && mime.starts_with(&t[..t.len() - 1]))
+			})
+	}
+}
+
+/// Whether `mime` can run scripts when opened in a browser
+pub fn is_active(mime: &str) -> bool {
+	ACTIVE_TYPES.contains(&mime)
+}
+
+fn starts_with_ignore_case(data: &[u8], prefix: &[u8]) -> bool {
+	data.len() >= prefix.len() && data[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+/// Sniffs markup types, which may start with whitespace or BOM
+fn sniff_markup(head: &[u8]) -> Option<&'static str> {
+	let head = if head.starts_with(&[0xEF, 0xBB, 0xBF]) {
+		&head[3..]
+	} else {
+		head
+	};
+	let start = head.iter().position(|b| !b.is_ascii_whitespace())?;
+	let head = &head[start..];
+	const HTML_PREFIXES: &[&[u8]] = &[
+		b"<!doctype html",
+		b"<html",
+		b"<head",
+		b"<body",
+		b"<script",
+		b"<iframe",
+		b"<style",
+		b"<title",
+	];
+	if HTML_PREFIXES.iter().any(|p| starts_with_ignore_case(head, p)) {
+		return Some("text/html");
+	}
+	let lower = head.to_ascii_lowercase();
+	let contains = |needle: &[u8]| lower.windows(needle.len()).any(|w| w == needle);
+	let is_xml = head.starts_with(b"<?xml");
+	if starts_with_ignore_case(head, b"<svg") || (is_xml && contains(b"<svg")) {
+		return Some("image/svg+xml");
+	}
+	if is_xml {
+		return Some(if contains(b"xhtml") {
+			"application/xhtml+xml"
+		} else {
+			"text/xml"
+		});
+	}
+	None
+}
+
+/// MIME type from magic bytes, `application/octet-stream` if unknown
+pub fn sniff(data: &[u8]) -> &'static str {
+	let head = &data[..data.len().min(SNIFF_LEN)];
+	let at = |offset: usize, magic: &[u8]| {
+		head.len() >= offset + magic.len() && &head[offset..offset + magic.len()] == magic
+	};
+	if at(0, &[0xFF, 0xD8, 0xFF]) {
+		"image/jpeg"
+	} else if at(0, b"\x89PNG\r\n\x1a\n") {
+		"image/png"
+	} else if at(0, b"GIF87a") || at(0, b"GIF89a") {
+		"image/gif"
+	} else if at(0, b"RIFF") && at(8, b"WEBP") {
+		"image/webp"
+	} else if at(0, b"BM") && head.len() >= 14 {
+		"image/bmp"
+	} else if at(0, b"%PDF-") {
+		"application/pdf"
+	} else if at(0, b"PK\x03\x04") {
+		"application/zip"
+	} else if at(0, &[0x1F, 0x8B]) {
+		"application/gzip"
+	} else if at(0, b"ID3") || at(0, &[0xFF, 0xFB]) || at(0, &[0xFF, 0xF3]) {
+		"audio/mpeg"
+	} else if at(0, b"OggS") {
+		"audio/ogg"
+	} else if at(0, b"RIFF") && at(8, b"WAVE") {
+		"audio/wav"
+	} else if at(0, b"fLaC") {
+		"audio/flac"
+	} else if at(4, b"ftyp") {
+		"video/mp4"
+	} else if at(0, &[0x1A, 0x45, 0xDF, 0xA3]) {
+		"video/webm"
+	} else if let Some(mime) = sniff_markup(head) {
+		mime
+	} else if !head.contains(&0) && std::str::from_utf8(data).is_ok() {
+		"text/plain"
+	} else {
+		"application/octet-stream"
+	}
+}
+
+/// Extension with the dot, empty for unknown types
+pub fn extension_of(mime: &str) -> &'static str {
+	match mime {
+		"image/jpeg" => ".jpg",
+		"image/png" => ".png",
+		"image/gif" => ".gif",
+		"image/webp" => ".webp",
+		"image/bmp" => ".bmp",
+		"image/svg+xml" => ".svg",
+		"application/pdf" => ".pdf",
+		"application/zip" => ".zip",
+		"application/gzip" => ".gz",
+		"audio/mpeg" => ".mp3",
+		"audio/ogg" => ".ogg",
+		"audio/wav" => ".wav",
+		"audio/flac" => ".flac",
+		"video/mp4" => ".mp4",
+		"video/webm" => ".webm",
+		"text/html" => ".html",
+		"application/xhtml+xml" => ".xhtml",
+		"text/xml" => ".xml",
+		"text/plain" => ".txt",
+		_ => "",
+	}
+}
@@ -1,9 +1,10 @@
 pub use crate::utils::*;
 use crate::{
 	db::Database,
-	models::{user, IntoInterface},
-	plugin::PluginManager,
-	render::{RenderHelper, RenderFunctions},
+	models::{api_token, setting::PluginSettings, user, IntoInterface, Repository},
+	plugin::{InjectionPoint, PluginManager, PluginType, SharedPluginManager},
+	render::{theme::{self, ActiveTheme}, RenderHelper, RenderFunctions},
+	storage::StorageManager,
 };
 use rocket::{
 	fairing::{Fairing, Info as FairingInfo, Kind as FairingKind},
@@ -13,6 +14,7 @@ use rocket::{
 	Data,
 	handler::{Handler, Outcome as HandlerOutcome},
 };
+use std::sync::RwLockReadGuard;
 
 use crate::routes::error::Error; // temp solution
 
@@ -37,6 +39,9 @@ impl Handler for RobotsTxt {
     }
 }
 
+/// Shown at the bottom of pages while previewing a theme
+const PREVIEW_BANNER: &str = r#"<div style="position: fixed; bottom: 0; left: 0; right: 0; padding: 8px; background: #ffd; border-top: 1px solid #cc9; text-align: center; z-index: 9999;">You are previewing a theme, other visitors still see the active one. <a href="/admin/appearance">Activate or stop preview</a></div>"#;
+
 /// `GlobalContext` is a struct contained some globally useful items, such as user and database connection.
 pub struct GlobalContext<'a> {
 	pub ip: VisitorIP,
@@ -45,38 +50,99 @@ pub struct GlobalContext<'a> {
 	pub system_config: &'a SystemConfig,
 	pub user_agent: Option<String>,
 	pub session_info: SessionInfo,
-	pub plugin_manager: State<'a, PluginManager>,
+	/// Held until the request ends, so plugins can't be reloaded meanwhile
+	pub plugin_manager: RwLockReadGuard<'a, PluginManager>,
+	pub storage: State<'a, StorageManager>,
+	pub plugin_settings: State<'a, PluginSettings>,
+	/// Theme for this request, which may be previewed by an admin
+	pub theme_name: String,
 }
 impl<'a> GlobalContext<'a> {
+	/// Whether the session sees a previewed theme instead of the active one
+	pub fn is_previewing_theme(&self) -> bool {
+		self.session_info.preview_theme.as_ref() == Some(&self.theme_name)
+			&& self
+				.user
+				.as_ref()
+				.map_or(false, |u| u.has_permission(user::PERM_SETTINGS_MANAGE))
+	}
+
+	/// For turning models into interfaces, see `models::IntoInterface`
+	pub fn repository(&self) -> Repository {
+		Repository {
+			db: self.db.clone(),
+			storage: self.storage.inner().clone(),
+		}
+	}
+
 	pub fn get_template_context(&self) -> TemplateContext {
+		let mut plugin_footer = self.plugin_manager.inject(InjectionPoint::Footer);
+		if self.is_previewing_theme() {
+			plugin_footer.push_str(PREVIEW_BANNER);
+		}
 		TemplateContext {
 			ip: &self.ip,
-			user: self.user.clone().into_interface(&self.db),
+			user: self.user.clone().into_interface(&self.repository()),
 			system_config: &self.system_config,
 			user_agent: self.user_agent.as_ref(),
 			session_info: &self.session_info,
-			render_helper: Box::new(RenderFunctions::default()) as Box<dyn RenderHelper>,
+			render_helper: Box::new(RenderFunctions::with_images(
+				self.db.clone(),
+				self.storage.inner().clone(),
+			)) as Box<dyn RenderHelper>,
+			theme: self
+				.plugin_manager
+				.get_theme(&self.theme_name)
+				.map(|theme| theme.as_ref()),
+			builtin_theme: &theme::BUILTIN,
+			fingerprints: Some(self.plugin_manager.fingerprints()),
+			theme_settings: self.plugin_settings.get(PluginType::Theme, &self.theme_name),
+			plugin_head: self.plugin_manager.inject(InjectionPoint::Head),
+			plugin_footer: plugin_footer,
+			plugin_admin_pages: match self.user {
+				Some(_) => self.plugin_manager.admin_pages(),
+				None => Vec::new(),
+			},
 		}
 	}
 }
 impl<'a, 'r> FromRequest<'a, 'r> for GlobalContext<'r> {
 	type Error = ();
 	fn from_request(request: &'a Request<'r>) -> Outcome<Self, ()> {
+		let user = request.guard::<Option<user::User>>().unwrap();
+		let session_info = request.guard::<SessionInfo>()?;
+		let plugin_manager = request
+			.guard::<State<SharedPluginManager>>()?
+			.inner()
+			.read()
+			.unwrap();
+		let theme_name = theme::resolve(
+			request.guard::<State<ActiveTheme>>()?.inner(),
+			&plugin_manager,
+			user.as_ref(),
+			&session_info,
+		);
 		Outcome::Success(Self {
 			ip: request.guard::<VisitorIP>().unwrap(), // FIXME: Needs to process errors properly
 			db: request.guard::<State<Box<Database>>>()?.inner().clone(),
-			user: request.guard::<Option<user::User>>().unwrap(),
+			user: user,
 			system_config: request.guard::<State<SystemConfig>>()?.inner(),
 			user_agent: request
 				.headers()
 				.get_one("User-Agent")
 				.and_then(|s| Some(s.to_string())),
-			session_info: request.guard::<SessionInfo>()?,
-			plugin_manager: request.guard::<State<PluginManager>>()?,
+			session_info: session_info,
+			plugin_manager: plugin_manager,
+			storage: request.guard::<State<StorageManager>>()?,
+			plugin_settings: request.guard::<State<PluginSettings>>()?,
+			theme_name: theme_name,
 		})
 	}
 }
 
+/// Header for CSRF token, used by AJAX requests which can't carry it in body
+pub const CSRF_HEADER_NAME: &str = "X-CSRF-Token";
+
 #[derive(Debug)]
 pub struct CSRFTokenValidation(pub Option<String>);
 impl Fairing for CSRFTokenValidation {
@@ -87,8 +153,18 @@ impl Fairing for CSRFTokenValidation {
 		}
 	}
 
-	/// `csrf_field` should appear in the front of the form, cuz we didn't use data.peek for the second time. The full stream will not be loaded.
+	/// The token is taken from `X-CSRF-Token` header if present, which other sites can't set
+	/// without CORS. Otherwise `csrf_field` should appear in the front of the form, cuz we didn't
+	/// use data.peek for the second time. The full stream will not be loaded.
 	fn on_request(&self, request: &mut Request, data: &Data) {
+		if api_token::bearer_token(request).is_some() {
+			return; // API token requests can't be forged by browsers, validation is skipped in guard
+		}
+		if let Some(token) = request.headers().get_one(CSRF_HEADER_NAME) {
+			let token = String::from(token);
+			request.local_cache(|| CSRFTokenValidation(Some(token)));
+			return;
+		}
 		let system_config = request.guard::<State<SystemConfig>>().unwrap();
 
 		if request.method() == Method::Post
@@ -143,6 +219,9 @@ impl Fairing for CSRFTokenValidation {
 impl<'a, 'r> FromRequest<'a, 'r> for CSRFTokenValidation {
 	type Error = Error;
 	fn from_request(request: &'a Request<'r>) -> Outcome<Self, Error> {
+		if api_token::bearer_auth(request).0.is_some() {
+			return Outcome::Success(Self(None));
+		}
 		let token: &CSRFTokenValidation = request.local_cache(|| CSRFTokenValidation(None));
 		if let Some(token) = &token.0 {
 			if let Outcome::Success(session_info) = request.guard::<SessionInfo>() {
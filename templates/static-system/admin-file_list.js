@@ -0,0 +1,18 @@
+$(document).ready(function (){
+	$('.copy-snippet').on('click', function (){
+		var input = $(this).siblings('.markdown-snippet')[0];
+		input.select();
+		var button = $(this);
+		var done = function (){
+			button.text('Copied');
+			setTimeout(function (){ button.text('Copy'); }, 1500);
+		};
+		if(navigator.clipboard){
+			navigator.clipboard.writeText(input.value).then(done, function (e){
+				console.error(e);
+			});
+		}else if(document.execCommand('copy')){
+			done();
+		}
+	});
+});
@@ -13,11 +13,16 @@ document.addEventListener('DOMContentLoaded',function (){
 	mpContent.content=document.getElementById('content').value;
 },false);
 
+var csrfHeaders = function () {
+	return {'X-CSRF-Token': $('#attachments-container').data('csrf-token')};
+};
+
 var deleteFile = function (id) {
 	$.ajax({
 		url: $('#attachments-container').data('delete-route').replace('-20001003', id),
 	//   magic number, seems no better idea when using `url!` generator ^ 
 		type: 'DELETE',
+		headers: csrfHeaders(),
 		success: function (){
 			fetchAllAttachments();
 		},
@@ -31,7 +36,6 @@ var deleteFile = function (id) {
 var fetchAllAttachments = function () {
 	var ctn = $('#attachments-container');
 	if(ctn.length < 1) return;
-	var uploadDir = ctn.data('upload-route');
 	$.ajax({
 		url: ctn.data('url'),
 		type: 'GET',
@@ -53,10 +57,18 @@ var fetchAllAttachments = function () {
 					ext=v.filename.split('.');
 					ext=ext[ext.length-1].toLowerCase();
 				}
-				$('code', e).text((['jpg','jpeg','png','gif','svg','bmp'].indexOf(ext)<0?"":"!")+"["+v.filename+"]("+v.key.replace('{upload_dir}', uploadDir)+")");
+				$('code', e).text((['jpg','jpeg','png','gif','svg','bmp'].indexOf(ext)<0?"":"!")+"["+v.filename+"]("+v.url+")");
+				if(v.width){
+					var picker=$('<p><label><input type="radio" name="featured-picker" />&nbsp;Use as cover</label></p>');
+					$('input', picker).val(v.id);
+					$('p', e).first().after(picker);
+				}
 				root.prepend(e);
 			});
 			$('#list', ctn).replaceWith(root);
+			$('input[name="featured-picker"]', ctn).each(function (){
+				this.checked = this.value === $('#featured_file').val();
+			});
 		},
 		error: function (e){
 			console.error(e);
@@ -65,39 +77,124 @@ var fetchAllAttachments = function () {
 	});
 };
 
+var uploadForm = function () {
+	return $('#attachment-upload');
+};
+
+// uploads `file` with fields of the upload form, `success` gets the uploaded file
+var uploadFile = function (file, success, complete) {
+	var form = uploadForm();
+	var formData = new FormData();
+	$('input[type="hidden"]', form).each(function (){
+		formData.append(this.name, this.value);
+	});
+	formData.append('file', file, file.name || 'pasted');
+	var progress = $('#upload-progress');
+	progress.attr('value', 0).attr('hidden', false);
+	$.ajax({
+		url: form.attr('action'),
+		type: 'POST',
+		headers: csrfHeaders(),
+		data: formData,
+		contentType: false,
+		processData: false,
+		dataType: 'json',
+		xhr: function (){
+			var xhr = $.ajaxSettings.xhr();
+			if(xhr.upload){
+				xhr.upload.addEventListener('progress', function (e){
+					if(e.lengthComputable){
+						progress.attr('value', Math.round(e.loaded / e.total * 100));
+					}
+				});
+			}
+			return xhr;
+		},
+		success: function (data){
+			!success || success(data);
+			fetchAllAttachments();
+		},
+		error: function (e){
+			console.error(e);
+			alert(e.status === 413 ? 'File is too large' : e.status === 415 ? 'File type is not allowed' : 'Upload failed');
+		},
+		complete: function (){
+			progress.attr('hidden', true);
+			!complete || complete();
+		}
+	});
+};
+
+// inserts `text` at the cursor of the editor, or appends it if the cursor is unknown
+var insertToEditor = function (text) {
+	var cmElement = document.querySelector('#content-mp .CodeMirror');
+	if(cmElement && cmElement.CodeMirror){
+		cmElement.CodeMirror.replaceSelection(text);
+	}else{
+		mpContent.content += text;
+	}
+};
+
+var markdownOf = function (file) {
+	var name = (file.filename || '').replace(/[\[\]]/g, '');
+	var isImage = (file.mime || '').indexOf('image/') === 0;
+	return (isImage ? '!' : '') + '[' + name + '](' + file.url + ')';
+};
+
+var uploadAndInsert = function (files) {
+	Array.prototype.forEach.call(files, function (file){
+		uploadFile(file, function (data){
+			insertToEditor(markdownOf(data) + '\n');
+		});
+	});
+};
+
 $(document).ready(function (){
 	fetchAllAttachments();
 
+	// cover picker, saved with the post
+	$('#attachments-container').on('change', 'input[name="featured-picker"]', function (){
+		$('#featured_file').val(this.value);
+	});
+
 	// file uploading handler
 	$('#attachment-upload').on("submit", function (e){
 		e.preventDefault();
-		var form = $(e.currentTarget);
 		var fileBox=$('input[name="file"]', e.currentTarget);
 		var submitButton=$('input[type="submit"]', e.currentTarget);
-		var formData = new FormData(e.currentTarget);
 
 		if(fileBox[0].files.length !== 1){
 			alert("expected files.length = 1, got "+fileBox[0].files.length);
+			return;
 		}
 
 		submitButton.attr('disabled', true);
-		$.ajax({
-			url: form.attr('action'),
-			type: 'POST',
-			data: formData,
-			contentType: false,
-			processData: false,
-			success: function (data){
-				fileBox.replaceWith(fileBox.val('').clone());
-				fetchAllAttachments();
-			},
-			error: function (e){
-				console.error(e);
-				alert('Upload failed');
-			},
-			complete: function (){
-				submitButton.attr('disabled', false);
-			}
+		uploadFile(fileBox[0].files[0], function (){
+			fileBox.replaceWith(fileBox.val('').clone());
+		}, function (){
+			submitButton.attr('disabled', false);
 		});
 	});
+
+	// drag and drop, or paste files into the editor
+	var editorBox = document.getElementById('content-mp');
+	editorBox.addEventListener('dragover', function (e){
+		if(e.dataTransfer && Array.prototype.indexOf.call(e.dataTransfer.types, 'Files') >= 0){
+			e.preventDefault();
+		}
+	}, true);
+	editorBox.addEventListener('drop', function (e){
+		var files = e.dataTransfer ? e.dataTransfer.files : [];
+		if(files.length < 1) return;
+		e.preventDefault();
+		e.stopPropagation();
+		uploadAndInsert(files);
+	}, true);
+	editorBox.addEventListener('paste', function (e){
+		var files = e.clipboardData ? e.clipboardData.files : [];
+		if(files.length < 1) return; // plain text is pasted as usual
+		e.preventDefault();
+		e.stopPropagation();
+		uploadAndInsert(files);
+	}, true);
 });